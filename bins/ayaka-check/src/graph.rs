@@ -0,0 +1,146 @@
+use ayaka_runtime::*;
+use clap::ValueEnum;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+    Json,
+}
+
+impl GraphFormat {
+    pub fn export(&self, graph: &StoryGraph) -> anyhow::Result<String> {
+        match self {
+            Self::Dot => Ok(to_dot(graph)),
+            Self::Mermaid => Ok(to_mermaid(graph)),
+            Self::Json => Ok(serde_json::to_string_pretty(graph)?),
+        }
+    }
+}
+
+fn node_label(node: &StoryNode) -> String {
+    let mut label = if node.base_para == node.para {
+        node.para.clone()
+    } else {
+        format!("{}/{}", node.base_para, node.para)
+    };
+    if let Some(title) = &node.title {
+        label.push('\n');
+        label.push_str(title);
+    }
+    label
+}
+
+fn edge_label(edge: &StoryEdge) -> Option<String> {
+    match (edge.kind, &edge.label) {
+        (StoryEdgeKind::Call, Some(label)) => Some(format!("call: {}", label)),
+        (StoryEdgeKind::Call, None) => Some("call".to_string()),
+        (_, label) => label.clone(),
+    }
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn to_dot(graph: &StoryGraph) -> String {
+    let mut output = String::new();
+    writeln!(output, "digraph story {{").unwrap();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        write!(
+            output,
+            "    n{} [label=\"{}\"",
+            i,
+            dot_escape(&node_label(node))
+        )
+        .unwrap();
+        if graph.start == Some(i) {
+            write!(output, ", peripheries=2").unwrap();
+        }
+        writeln!(output, "];").unwrap();
+    }
+    for (i, edge) in graph.edges.iter().enumerate() {
+        let to = match edge.to {
+            Some(to) => format!("n{}", to),
+            None => {
+                writeln!(
+                    output,
+                    "    x{} [label=\"{}\", shape=none, fontcolor=red];",
+                    i,
+                    dot_escape(&edge.target)
+                )
+                .unwrap();
+                format!("x{}", i)
+            }
+        };
+        let mut attrs = vec![];
+        if let Some(label) = edge_label(edge) {
+            attrs.push(format!("label=\"{}\"", dot_escape(&label)));
+        }
+        if edge.dynamic {
+            attrs.push("style=dashed".to_string());
+        }
+        if edge.kind == StoryEdgeKind::Call {
+            attrs.push("arrowhead=empty".to_string());
+        }
+        write!(output, "    n{} -> {}", edge.from, to).unwrap();
+        if !attrs.is_empty() {
+            write!(output, " [{}]", attrs.join(", ")).unwrap();
+        }
+        writeln!(output, ";").unwrap();
+    }
+    writeln!(output, "}}").unwrap();
+    output
+}
+
+fn mermaid_escape(s: &str) -> String {
+    s.replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+        .replace('\n', "<br/>")
+}
+
+fn to_mermaid(graph: &StoryGraph) -> String {
+    let mut output = String::new();
+    writeln!(output, "flowchart TD").unwrap();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let label = mermaid_escape(&node_label(node));
+        if graph.start == Some(i) {
+            writeln!(output, "    n{}([\"{}\"])", i, label).unwrap();
+        } else {
+            writeln!(output, "    n{}[\"{}\"]", i, label).unwrap();
+        }
+    }
+    for (i, edge) in graph.edges.iter().enumerate() {
+        let to = match edge.to {
+            Some(to) => format!("n{}", to),
+            None => {
+                writeln!(
+                    output,
+                    "    x{}{{{{\"{}\"}}}}",
+                    i,
+                    mermaid_escape(&edge.target)
+                )
+                .unwrap();
+                format!("x{}", i)
+            }
+        };
+        let arrow = if edge.dynamic { "-.->" } else { "-->" };
+        match edge_label(edge) {
+            Some(label) => writeln!(
+                output,
+                "    n{} {}|\"{}\"| {}",
+                edge.from,
+                arrow,
+                mermaid_escape(&label),
+                to
+            ),
+            None => writeln!(output, "    n{} {} {}", edge.from, arrow, to),
+        }
+        .unwrap();
+    }
+    output
+}
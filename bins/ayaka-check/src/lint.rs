@@ -0,0 +1,20 @@
+use ayaka_runtime::*;
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum LintFormat {
+    Text,
+    Json,
+}
+
+impl LintFormat {
+    pub fn export(&self, diagnostics: &[LintDiagnostic]) -> anyhow::Result<String> {
+        match self {
+            Self::Text => Ok(diagnostics
+                .iter()
+                .map(|diag| format!("{}\n", diag))
+                .collect()),
+            Self::Json => Ok(serde_json::to_string_pretty(diagnostics)? + "\n"),
+        }
+    }
+}
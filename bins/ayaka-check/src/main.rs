@@ -1,12 +1,23 @@
+mod graph;
+mod lint;
+mod translation;
+
 use ayaka_plugin_wasmi::{WasmiLinker, WasmiModule};
-use ayaka_runtime::{anyhow::Result, *};
+use ayaka_runtime::{
+    anyhow::{anyhow, Result},
+    *,
+};
 use clap::Parser;
 use flexi_logger::{LogSpecification, Logger};
+use graph::GraphFormat;
+use lint::LintFormat;
 use std::{
     ffi::OsString,
     io::{stdin, stdout, Write},
+    path::{Path, PathBuf},
     pin::pin,
 };
+use translation::{CoverageFormat, TranslationFormat};
 
 #[derive(Debug, Parser)]
 #[clap(about, version, author)]
@@ -17,6 +28,31 @@ pub struct Options {
     auto: bool,
     #[clap(short, long)]
     locale: Option<Locale>,
+    /// The seed of the random number generator, to reproduce a run.
+    #[clap(long)]
+    seed: Option<u64>,
+    /// The fuel of each plugin call, to stop the plugins running forever.
+    #[clap(long)]
+    fuel: Option<u64>,
+    /// Export the story graph instead of running the game.
+    #[clap(long, value_enum)]
+    graph: Option<GraphFormat>,
+    /// Check the game statically instead of running it.
+    /// Exit with nonzero code if any problem is found.
+    #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "text")]
+    lint: Option<LintFormat>,
+    /// Report the translation coverage of the locale, or all locales if not specified.
+    #[clap(long, value_enum, num_args = 0..=1, default_missing_value = "text")]
+    coverage: Option<CoverageFormat>,
+    /// Export the translatable texts of the locale.
+    #[clap(long, value_enum, requires = "locale")]
+    export: Option<TranslationFormat>,
+    /// Import the translation file, and write the paragraphs and resources to the output directory.
+    #[clap(long, requires = "output")]
+    import: Option<PathBuf>,
+    /// The output directory of the imported translation.
+    #[clap(long)]
+    output: Option<PathBuf>,
 }
 
 fn read_line() -> Result<String> {
@@ -26,6 +62,55 @@ fn read_line() -> Result<String> {
     Ok(s)
 }
 
+// The value is invalid if the validation fails to evaluate.
+fn submit_input(ctx: &mut Context<WasmiModule>, loc: &Locale, value: &str) -> bool {
+    ctx.input(loc, value).unwrap_or_else(|e| {
+        log::warn!("Cannot validate the input: {:#}", e);
+        false
+    })
+}
+
+fn write_file(path: &Path, content: String) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)?;
+    log::info!("Written {}", path.display());
+    Ok(())
+}
+
+fn import_translation(game: &Game, path: &Path, loc: Option<Locale>, output: &Path) -> Result<()> {
+    let format = TranslationFormat::from_path(path)?;
+    let (file_loc, translations) = format.import(&std::fs::read_to_string(path)?)?;
+    let loc = loc
+        .or(file_loc)
+        .ok_or_else(|| anyhow!("Cannot determine the locale of \"{}\"", path.display()))?;
+    let translation = game.apply_translation(&translations)?;
+    let paras_dir = output.join(game.config.paras.trim_start_matches('/'));
+    for (file, paras) in &translation.paras {
+        write_file(
+            &paras_dir
+                .join(loc.to_string())
+                .join(format!("{}.yaml", file)),
+            serde_yaml::to_string(paras)?,
+        )?;
+    }
+    if !translation.res.is_empty() {
+        let res = game
+            .config
+            .res
+            .as_deref()
+            .ok_or_else(|| anyhow!("The resource directory is not specified"))?;
+        write_file(
+            &output
+                .join(res.trim_start_matches('/'))
+                .join(format!("{}.yaml", loc)),
+            serde_yaml::to_string(&translation.res)?,
+        )?;
+    }
+    Ok(())
+}
+
 fn pause(auto: bool) -> Result<()> {
     if auto {
         println!();
@@ -39,17 +124,33 @@ fn pause(auto: bool) -> Result<()> {
 async fn main() -> Result<()> {
     let opts = Options::parse();
     let spec = LogSpecification::parse("warn,ayaka=debug")?;
-    let _log_handle = Logger::with(spec)
-        .log_to_stdout()
+    let logger = Logger::with(spec);
+    let quiet = opts.graph.is_some()
+        || opts.lint.is_some()
+        || opts.coverage.is_some()
+        || opts.export.is_some()
+        || opts.import.is_some();
+    // Keep the stdout clean for the exported graph, the lint results and the translations.
+    let logger = if quiet {
+        logger.log_to_stderr()
+    } else {
+        logger.log_to_stdout()
+    };
+    let _log_handle = logger
         .set_palette("b1;3;2;4;6".to_string())
         .use_utc()
         .start()?;
-    let linker = WasmiLinker::new(())?;
-    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
-        .with_paths(&opts.input)?
-        .open();
+    let linker = WasmiLinker::new(LinkerConfig { fuel: opts.fuel })?;
+    let mut builder = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker);
+    if let Some(seed) = opts.seed {
+        builder = builder.with_seed(seed);
+    }
+    let context = builder.with_paths(&opts.input)?.open();
     let mut context = pin!(context);
     while let Some(status) = context.next().await {
+        if quiet {
+            continue;
+        }
         match status {
             OpenStatus::LoadProfile => println!("Loading profile..."),
             OpenStatus::CreateRuntime => println!("Creating runtime..."),
@@ -62,9 +163,54 @@ async fn main() -> Result<()> {
         }
     }
     let mut ctx = context.await?;
+    if let Some(format) = opts.graph {
+        let graph = StoryGraph::new(ctx.game());
+        print!("{}", format.export(&graph)?);
+        return Ok(());
+    }
+    if let Some(format) = opts.lint {
+        let diagnostics = ctx.lint();
+        print!("{}", format.export(&diagnostics)?);
+        if diagnostics
+            .iter()
+            .any(|diag| diag.severity == LintSeverity::Error)
+        {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    if let Some(format) = opts.coverage {
+        let game = ctx.game();
+        let coverages = match &opts.locale {
+            Some(loc) => vec![game.translation_coverage(loc)],
+            None => {
+                let mut locs = game
+                    .paras
+                    .keys()
+                    .chain(game.res.keys())
+                    .filter(|loc| **loc != game.config.base_lang)
+                    .collect::<Vec<_>>();
+                locs.sort_by_key(|loc| loc.to_string());
+                locs.dedup();
+                locs.into_iter()
+                    .map(|loc| game.translation_coverage(loc))
+                    .collect()
+            }
+        };
+        print!("{}", format.export(&coverages)?);
+        return Ok(());
+    }
+    if let Some(format) = opts.export {
+        let loc = opts.locale.as_ref().expect("the locale should be required");
+        print!("{}", format.export(ctx.game(), loc)?);
+        return Ok(());
+    }
+    if let (Some(path), Some(output)) = (&opts.import, &opts.output) {
+        return import_translation(ctx.game(), path, opts.locale, output);
+    }
     ctx.set_start_context();
     let loc = opts.locale.unwrap_or_else(Locale::current);
-    while let Some(raw_ctx) = ctx.next_run() {
+    while let Some(raw_ctx) = ctx.next_run(&loc)? {
         let action = ctx.get_action(&loc, &raw_ctx)?;
         match action {
             Action::Empty | Action::Custom(_) => {}
@@ -78,7 +224,7 @@ async fn main() -> Result<()> {
                 pause(opts.auto)?;
             }
             Action::Switches(switches) => {
-                for (i, s) in switches.iter().enumerate() {
+                for (i, s) in switches.switches.iter().enumerate() {
                     if s.enabled {
                         print!("\n-{}- {}", i + 1, s.text);
                     } else {
@@ -86,6 +232,15 @@ async fn main() -> Result<()> {
                     }
                 }
                 println!();
+                // The default switch is chosen by the timeout.
+                // It is resolved at once with `--auto`, without waiting.
+                if opts.auto && switches.timeout.is_some() {
+                    if let Some(i) = ctx.timeout() {
+                        println!("Timed out, choose {}", i + 1);
+                        continue;
+                    }
+                }
+                let switches = switches.switches;
                 loop {
                     let s = read_line()?;
                     if let Ok(i) = s.trim().parse::<usize>() {
@@ -98,6 +253,36 @@ async fn main() -> Result<()> {
                     println!("Invalid switch, enter again!");
                 }
             }
+            Action::Input(input) => {
+                for s in &input.prompt.text {
+                    print!("{}", s.as_str());
+                }
+                if let Some(default) = &input.default {
+                    print!(" [{}]", default);
+                }
+                println!();
+                // The default value has been stored, or the empty value is submitted.
+                if opts.auto {
+                    if input.default.is_none() && !submit_input(&mut ctx, &loc, "") {
+                        return Err(anyhow!(
+                            "The input has no default value, and the empty value is invalid"
+                        ));
+                    }
+                    continue;
+                }
+                loop {
+                    let s = read_line()?;
+                    let s = s.trim_end_matches(['\r', '\n']);
+                    let valid = match (s, &input.default) {
+                        ("", Some(_)) => true,
+                        _ => submit_input(&mut ctx, &loc, s),
+                    };
+                    if valid {
+                        break;
+                    }
+                    println!("Invalid input, enter again!");
+                }
+            }
         }
     }
     Ok(())
@@ -0,0 +1,336 @@
+use ayaka_runtime::{
+    anyhow::{anyhow, bail, Result},
+    *,
+};
+use clap::ValueEnum;
+use quick_xml::events::Event;
+use std::{collections::HashMap, fmt::Write, path::Path};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CoverageFormat {
+    Text,
+    Json,
+}
+
+impl CoverageFormat {
+    pub fn export(&self, coverages: &[TranslationCoverage]) -> Result<String> {
+        match self {
+            Self::Text => {
+                let mut output = String::new();
+                for c in coverages {
+                    writeln!(
+                        output,
+                        "{}: {}/{} lines, {}/{} resources",
+                        c.locale,
+                        c.translated_lines,
+                        c.total_lines,
+                        c.total_res - c.missing_res.len(),
+                        c.total_res
+                    )?;
+                    for p in &c.missing_paras {
+                        writeln!(
+                            output,
+                            "  missing paragraph: {}: paragraph \"{}\"",
+                            p.base_para,
+                            p.para.escape_default()
+                        )?;
+                    }
+                    for line in &c.empty_lines {
+                        writeln!(
+                            output,
+                            "  empty line: {}",
+                            SourceSpan::line(
+                                &line.base_para,
+                                &line.para,
+                                line.line.unwrap_or_default()
+                            )
+                        )?;
+                    }
+                    for key in &c.missing_res {
+                        writeln!(output, "  missing resource: {}", key)?;
+                    }
+                }
+                Ok(output)
+            }
+            Self::Json => Ok(serde_json::to_string_pretty(coverages)? + "\n"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TranslationFormat {
+    Po,
+    Xliff,
+}
+
+impl TranslationFormat {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|ex| ex.to_str()) {
+            Some("po") => Ok(Self::Po),
+            Some("xlf" | "xliff") => Ok(Self::Xliff),
+            _ => bail!("Unknown translation file \"{}\"", path.display()),
+        }
+    }
+
+    pub fn export(&self, game: &Game, loc: &Locale) -> Result<String> {
+        let units = game.translation_units(loc);
+        match self {
+            Self::Po => export_po(game, loc, &units),
+            Self::Xliff => export_xliff(game, loc, &units),
+        }
+    }
+
+    /// Returns the locale specified in the file, and the translations indexed by keys.
+    pub fn import(&self, content: &str) -> Result<(Option<Locale>, HashMap<String, String>)> {
+        match self {
+            Self::Po => import_po(content),
+            Self::Xliff => import_xliff(content),
+        }
+    }
+}
+
+fn po_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+fn po_unescape(s: &str) -> Result<String> {
+    let s = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| anyhow!("Invalid PO string: {}", s))?;
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => res.push('\n'),
+                Some('t') => res.push('\t'),
+                Some('r') => res.push('\r'),
+                Some(c) => res.push(c),
+                None => bail!("Invalid PO string: {}", s),
+            }
+        } else {
+            res.push(c);
+        }
+    }
+    Ok(res)
+}
+
+fn export_po(game: &Game, loc: &Locale, units: &[TranslationUnit]) -> Result<String> {
+    let mut output = String::new();
+    writeln!(output, "msgid \"\"")?;
+    writeln!(output, "msgstr \"\"")?;
+    writeln!(
+        output,
+        "\"Project-Id-Version: {}\\n\"",
+        po_escape(&game.config.title)
+    )?;
+    writeln!(output, "\"Language: {}\\n\"", loc)?;
+    writeln!(output, "\"MIME-Version: 1.0\\n\"")?;
+    writeln!(output, "\"Content-Type: text/plain; charset=UTF-8\\n\"")?;
+    writeln!(output, "\"Content-Transfer-Encoding: 8bit\\n\"")?;
+    for unit in units {
+        writeln!(output)?;
+        writeln!(output, "msgctxt \"{}\"", po_escape(&unit.key))?;
+        writeln!(output, "msgid \"{}\"", po_escape(&unit.source))?;
+        writeln!(
+            output,
+            "msgstr \"{}\"",
+            po_escape(unit.target.as_deref().unwrap_or_default())
+        )?;
+    }
+    Ok(output)
+}
+
+#[derive(Default)]
+struct PoEntry {
+    fuzzy: bool,
+    msgctxt: Option<String>,
+    msgid: Option<String>,
+    msgstr: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum PoField {
+    Msgctxt,
+    Msgid,
+    Msgstr,
+}
+
+impl PoEntry {
+    fn field(&mut self, field: PoField) -> &mut Option<String> {
+        match field {
+            PoField::Msgctxt => &mut self.msgctxt,
+            PoField::Msgid => &mut self.msgid,
+            PoField::Msgstr => &mut self.msgstr,
+        }
+    }
+}
+
+fn import_po(content: &str) -> Result<(Option<Locale>, HashMap<String, String>)> {
+    let mut entries = vec![];
+    let mut entry = PoEntry::default();
+    // The field which the continuation lines are appended to.
+    let mut field = None;
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(flags) = line.strip_prefix("#,") {
+            if entry.msgstr.is_some() {
+                entries.push(std::mem::take(&mut entry));
+            }
+            entry.fuzzy = flags.split(',').any(|flag| flag.trim() == "fuzzy");
+            field = None;
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('"') {
+            let s = po_unescape(line)?;
+            match field.and_then(|field| entry.field(field).as_mut()) {
+                Some(value) => value.push_str(&s),
+                None => bail!("Unexpected PO string: {}", line),
+            }
+            continue;
+        }
+        let (keyword, value) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow!("Invalid PO line: {}", line))?;
+        field = match keyword {
+            "msgctxt" => Some(PoField::Msgctxt),
+            "msgid" => Some(PoField::Msgid),
+            "msgstr" | "msgstr[0]" => Some(PoField::Msgstr),
+            _ => None,
+        };
+        if let Some(field) = field {
+            if !matches!(field, PoField::Msgstr) && entry.msgstr.is_some() {
+                entries.push(std::mem::take(&mut entry));
+            }
+            *entry.field(field) = Some(po_unescape(value.trim())?);
+        }
+    }
+    entries.push(entry);
+
+    let mut loc = None;
+    let mut translations = HashMap::new();
+    for entry in entries {
+        match (entry.msgctxt, entry.msgid, entry.msgstr) {
+            (None, Some(msgid), Some(header)) if msgid.is_empty() => {
+                loc = header
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Language:"))
+                    .map(|lang| lang.trim().parse())
+                    .transpose()?;
+            }
+            (Some(key), Some(_), Some(msgstr)) if !entry.fuzzy && !msgstr.is_empty() => {
+                translations.insert(key, msgstr);
+            }
+            _ => {}
+        }
+    }
+    Ok((loc, translations))
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn export_xliff(game: &Game, loc: &Locale, units: &[TranslationUnit]) -> Result<String> {
+    let mut output = String::new();
+    writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        output,
+        r#"<xliff version="1.2" xmlns="urn:oasis:names:tc:xliff:document:1.2">"#
+    )?;
+    writeln!(
+        output,
+        r#"  <file original="{}" source-language="{}" target-language="{}" datatype="plaintext">"#,
+        xml_escape(&game.config.title),
+        game.config.base_lang,
+        loc
+    )?;
+    writeln!(output, "    <body>")?;
+    for unit in units {
+        writeln!(
+            output,
+            r#"      <trans-unit id="{}" xml:space="preserve">"#,
+            xml_escape(&unit.key)
+        )?;
+        writeln!(
+            output,
+            "        <source>{}</source>",
+            xml_escape(&unit.source)
+        )?;
+        if let Some(target) = &unit.target {
+            writeln!(
+                output,
+                r#"        <target state="translated">{}</target>"#,
+                xml_escape(target)
+            )?;
+        }
+        writeln!(output, "      </trans-unit>")?;
+    }
+    writeln!(output, "    </body>")?;
+    writeln!(output, "  </file>")?;
+    writeln!(output, "</xliff>")?;
+    Ok(output)
+}
+
+fn import_xliff(content: &str) -> Result<(Option<Locale>, HashMap<String, String>)> {
+    let mut reader = quick_xml::Reader::from_str(content);
+    let mut loc = None;
+    let mut translations = HashMap::new();
+    let mut key = None;
+    let mut target: Option<String> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"file" => {
+                    if let Some(lang) = e.try_get_attribute("target-language")? {
+                        loc = Some(lang.unescape_value()?.parse()?);
+                    }
+                }
+                b"trans-unit" => {
+                    key = e
+                        .try_get_attribute("id")?
+                        .map(|id| id.unescape_value().map(|id| id.into_owned()))
+                        .transpose()?;
+                }
+                b"target" => target = Some(String::new()),
+                _ => {}
+            },
+            Event::Text(e) => {
+                if let Some(target) = &mut target {
+                    target.push_str(&e.unescape()?);
+                }
+            }
+            Event::CData(e) => {
+                if let Some(target) = &mut target {
+                    target.push_str(&String::from_utf8_lossy(&e));
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"target" => {
+                    if let (Some(key), Some(target)) = (&key, target.take()) {
+                        if !target.is_empty() {
+                            translations.insert(key.clone(), target);
+                        }
+                    }
+                }
+                b"trans-unit" => key = None,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok((loc, translations))
+}
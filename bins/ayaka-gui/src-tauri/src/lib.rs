@@ -150,7 +150,7 @@ async fn open_game(handle: AppHandle, storage: State<'_, Storage>) -> CommandRes
 
     const OPEN_STATUS_EVENT: &str = "ayaka://open_status";
     let mut model = storage.model.write().await;
-    let linker = WasmiLinker::new(())?;
+    let linker = WasmiLinker::new(LinkerConfig::default())?;
     let builder = ContextBuilder::<WasmiModule>::new(FrontendType::Html, linker);
     let builder = if storage.config.is_empty() {
         let files = show_pick_files(&handle, &window).await?;
@@ -263,7 +263,7 @@ async fn start_record(index: usize, storage: State<'_, Storage>) -> CommandResul
 async fn next_run(storage: State<'_, Storage>) -> CommandResult<bool> {
     loop {
         let mut model = storage.model.write().await;
-        if model.next_run() {
+        if model.next_run()? {
             let is_empty = {
                 let action = model
                     .current_action()
@@ -316,6 +316,16 @@ async fn switch(i: usize, storage: State<'_, Storage>) -> CommandResult<()> {
     Ok(())
 }
 
+#[command]
+async fn input(value: String, storage: State<'_, Storage>) -> CommandResult<bool> {
+    Ok(storage.model.write().await.input(value)?)
+}
+
+#[command]
+async fn timeout(storage: State<'_, Storage>) -> CommandResult<Option<usize>> {
+    Ok(storage.model.write().await.timeout())
+}
+
 #[command]
 async fn history(storage: State<'_, Storage>) -> CommandResult<Vec<(Action, Option<Action>)>> {
     Ok(storage.model.read().await.current_history().rev().collect())
@@ -436,6 +446,8 @@ pub fn run() -> Result<()> {
             current_title,
             current_visited,
             switch,
+            input,
+            timeout,
             history,
         ])
         .run({
@@ -46,7 +46,7 @@ export interface RawContext {
     locals: {
         bg?: string,
         bgm?: string,
-        ch_models?: string,
+        ch_models?: string[] | string,
     }
 }
 
@@ -60,13 +60,14 @@ export interface GameInfo {
 
 export interface Action {
     type: keyof typeof ActionType,
-    data: undefined | ActionText | Switch[] | CustomVars
+    data: undefined | ActionText | ActionSwitches | ActionInput | CustomVars
 }
 
 export enum ActionType {
     Empty,
     Text,
     Switches,
+    Input,
     Custom,
 }
 
@@ -76,7 +77,8 @@ export interface ActionText {
     character?: string,
     vars: {
         voice?: string
-    }
+    },
+    timeout?: number,
 }
 
 export interface CustomVars {
@@ -93,11 +95,22 @@ export enum ActionLineType {
     Block,
 }
 
+export interface ActionSwitches {
+    switches: Switch[],
+    timeout?: number,
+    default?: number,
+}
+
 export interface Switch {
     text: string,
     enabled: boolean,
 }
 
+export interface ActionInput {
+    prompt: ActionText,
+    default?: string,
+}
+
 export function ayaka_version(): Promise<string> {
     return invoke("ayaka_version")
 }
@@ -197,6 +210,14 @@ export function switch_(i: number): Promise<void> {
     return invoke("switch", { i: i })
 }
 
+export function input(value: string): Promise<boolean> {
+    return invoke("input", { value: value })
+}
+
+export function timeout(): Promise<number | undefined> {
+    return invoke("timeout")
+}
+
 export function history(): Promise<[Action, Action | undefined][]> {
     return invoke("history")
 }
@@ -31,7 +31,7 @@ async fn main() -> Result<()> {
         .set_palette("b1;3;2;4;6".to_string())
         .use_utc()
         .start()?;
-    let linker = WasmiLinker::new(())?;
+    let linker = WasmiLinker::new(LinkerConfig::default())?;
     let context = ContextBuilder::<WasmiModule>::new(FrontendType::Latex, linker)
         .with_paths(&opts.input)?
         .open();
@@ -59,7 +59,7 @@ async fn main() -> Result<()> {
             let mut current_para = None;
             let mut current_bg = None;
 
-            while let Some(raw_ctx) = ctx.next_run() {
+            while let Some(raw_ctx) = ctx.next_run(&loc)? {
                 let action = ctx.get_action(&loc, &raw_ctx)?;
                 let para_title = ctx.current_paragraph_title(&loc);
                 if para_title != current_para.as_ref() {
@@ -108,10 +108,20 @@ async fn main() -> Result<()> {
                         }
                         output.write("\n\n").await?;
                     }
+                    Action::Input(input) => {
+                        for s in input.prompt.text {
+                            output.write(s.as_str()).await?;
+                        }
+                        if let Some(default) = &input.default {
+                            output.write(" ").await?;
+                            output.command("underline", [default]).await?;
+                        }
+                        output.write("\n\n").await?;
+                    }
                     Action::Switches(switches) => {
                         output
                             .environment("itemize", |output| async move {
-                                for s in switches.iter() {
+                                for s in switches.switches.iter() {
                                     output.command0("item").await?;
                                     if s.enabled {
                                         output.write(&s.text).await?;
@@ -16,7 +16,10 @@ use std::{
 
 #[export]
 fn plugin_type() -> PluginType {
-    PluginType::builder().line(["exec"]).build()
+    PluginType::builder()
+        .line(["exec"])
+        .metadata(plugin_metadata!())
+        .build()
 }
 
 #[import("script")]
@@ -25,32 +28,24 @@ extern "C" {
 }
 
 static RUNTIME: LazyLock<Runtime> = LazyLock::new(Runtime::new);
-static PROGRAM_CACHE: LazyLock<Mutex<HashMap<LineKey, Program>>> =
+// The programs are keyed by the source,
+// because the runtime evaluates different programs in the same line, e.g., the guards of `next`.
+static PROGRAM_CACHE: LazyLock<Mutex<HashMap<String, Program>>> =
     LazyLock::new(|| Mutex::new(HashMap::new()));
 
-#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
-struct LineKey {
-    pub cur_base_para: String,
-    pub cur_para: String,
-    pub cur_act: usize,
-}
-
 #[export]
 fn exec(mut ctx: LineProcessContext) -> LineProcessResult {
-    let key = LineKey {
-        cur_base_para: ctx.ctx.cur_base_para,
-        cur_para: ctx.ctx.cur_para,
-        cur_act: ctx.ctx.cur_act,
-    };
+    let source = ctx.props["exec"].get_str();
     let mut cache = PROGRAM_CACHE.lock().unwrap();
     let exec = cache
-        .entry(key)
-        .or_insert_with(|| __parse(&ctx.props["exec"].get_str()));
+        .entry(source.into_owned())
+        .or_insert_with_key(|source| __parse(source));
     let mut table = VarTable::new(&RUNTIME, &mut ctx.ctx.locals);
     table.call(exec);
     let vars = table.vars;
     LineProcessResult {
         locals: ctx.ctx.locals,
         vars,
+        ..Default::default()
     }
 }
@@ -35,7 +35,7 @@ impl Linker<HostModule> for HostLinker {
         unimplemented!()
     }
 
-    fn create(&self, _binary: &[u8]) -> Result<HostModule> {
+    fn create_with(&self, _binary: &[u8], _granted: impl Fn(&str) -> bool) -> Result<HostModule> {
         unimplemented!()
     }
 
@@ -79,7 +79,8 @@ impl Module {
 
     /// Calls a script plugin method by name.
     pub fn dispatch_method(&self, name: &str, args: &[RawValue]) -> Result<RawValue> {
-        self.module.call(name, (args,))
+        self.module
+            .call_with(name, (args,), |(args,)| (args.to_vec(),))
     }
 }
 
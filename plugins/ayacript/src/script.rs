@@ -67,14 +67,16 @@ impl Callable for Expr {
             Self::Ref(r) => r.call(ctx),
             Self::Const(c) => c.clone(),
             Self::Unary(op, e) => match op {
-                UnaryOp::Positive => RawValue::Num(e.call(ctx).get_num()),
-                UnaryOp::Negative => RawValue::Num(-e.call(ctx).get_num()),
-                UnaryOp::Not => match e.call(ctx) {
-                    RawValue::Unit => RawValue::Unit,
-                    RawValue::Bool(b) => RawValue::Bool(!b),
-                    RawValue::Num(i) => RawValue::Num(!i),
-                    RawValue::Str(_) => RawValue::Str(String::new()),
+                UnaryOp::Positive => match e.call(ctx) {
+                    RawValue::Float(f) => RawValue::Float(f),
+                    v => RawValue::Num(v.get_num()),
                 },
+                UnaryOp::Negative => match e.call(ctx) {
+                    RawValue::Float(f) => RawValue::Float(-f),
+                    v => RawValue::Num(-v.get_num()),
+                },
+                // The logical not of all types, same as the conditions.
+                UnaryOp::Not => RawValue::Bool(!e.call(ctx).get_bool()),
             },
             Self::Binary(lhs, op, rhs) => match op {
                 BinaryOp::Val(op) => bin_val(ctx, lhs, op, rhs),
@@ -89,7 +91,92 @@ impl Callable for Expr {
                 }
             },
             Self::Call(ns, name, args) => call(ctx, ns, name, args),
+            Self::List(items) => RawValue::List(items.iter().map(|e| e.call(ctx)).collect()),
+            Self::Map(items) => RawValue::Map(
+                items
+                    .iter()
+                    .map(|(key, e)| (key.clone(), e.call(ctx)))
+                    .collect(),
+            ),
+            Self::Index(e, index) => {
+                let value = e.call(ctx);
+                let index = index.call(ctx);
+                unwrap_or_default_log!(
+                    get_index(&value, &index).cloned(),
+                    format!("Cannot index {:?} with {:?}", value, index)
+                )
+            }
+            Self::For(r, e, body) => {
+                let items = iter_items(e.call(ctx));
+                let mut res = vec![];
+                for item in items {
+                    assign(ctx, &Expr::Ref(r.clone()), item);
+                    let mut value = RawValue::Unit;
+                    for expr in body {
+                        value = expr.call(ctx);
+                    }
+                    res.push(value);
+                }
+                RawValue::List(res)
+            }
+        }
+    }
+}
+
+/// The max count of a range in `for`.
+pub const MAX_RANGE: i64 = 65536;
+
+fn iter_items(value: RawValue) -> Box<dyn Iterator<Item = RawValue>> {
+    match value {
+        RawValue::Num(n) => {
+            if n > MAX_RANGE {
+                log::warn!("The range {} is larger than {}", n, MAX_RANGE);
+            }
+            Box::new((0..n.min(MAX_RANGE)).map(RawValue::Num))
+        }
+        RawValue::Str(s) => Box::new(
+            s.chars()
+                .map(|c| RawValue::Str(c.to_string()))
+                .collect::<Vec<_>>()
+                .into_iter(),
+        ),
+        RawValue::Map(m) => Box::new(m.into_keys().map(RawValue::Str)),
+        v => Box::new(v.into_list().into_iter()),
+    }
+}
+
+fn list_index(len: usize, index: &RawValue) -> Option<usize> {
+    let index = index.get_num();
+    let index = if index < 0 { len as i64 + index } else { index };
+    usize::try_from(index).ok()
+}
+
+fn get_index<'a>(value: &'a RawValue, index: &RawValue) -> Option<&'a RawValue> {
+    match value {
+        RawValue::List(l) => l.get(list_index(l.len(), index)?),
+        RawValue::Map(m) => m.get(index.get_str().as_ref()),
+        _ => None,
+    }
+}
+
+fn get_index_mut<'a>(value: &'a mut RawValue, index: &RawValue) -> Option<&'a mut RawValue> {
+    if let RawValue::Unit = value {
+        *value = match index {
+            RawValue::Str(_) => RawValue::Map(Default::default()),
+            _ => RawValue::List(vec![]),
+        };
+    }
+    match value {
+        RawValue::List(l) => {
+            let index = list_index(l.len(), index)?;
+            // Assigning to the end pushes a new item.
+            if index == l.len() {
+                l.push(RawValue::Unit);
+            }
+            l.get_mut(index)
         }
+        RawValue::Map(m) => Some(m.entry(index.get_str().into_owned()).or_default()),
+        _ => None,
     }
 }
 
@@ -101,7 +188,10 @@ fn bin_val(ctx: &mut VarTable, lhs: &Expr, op: &ValBinaryOp, rhs: &Expr) -> RawV
         ValueType::Unit => RawValue::Unit,
         ValueType::Bool => bin_bool_val(lhs.get_bool(), op, rhs.get_bool()),
         ValueType::Num => RawValue::Num(bin_num_val(lhs.get_num(), op, rhs.get_num())),
+        ValueType::Float => bin_float_val(lhs.get_float(), op, rhs.get_float()),
         ValueType::Str => bin_str_val(lhs, op, rhs),
+        ValueType::List => bin_list_val(lhs, op, rhs),
+        ValueType::Map => bin_map_val(lhs, op, rhs),
     }
 }
 
@@ -131,26 +221,102 @@ fn bin_num_val(lhs: i64, op: &ValBinaryOp, rhs: i64) -> i64 {
     }
 }
 
+fn bin_float_val(lhs: f64, op: &ValBinaryOp, rhs: f64) -> RawValue {
+    match op {
+        ValBinaryOp::Add => RawValue::Float(lhs + rhs),
+        ValBinaryOp::Minus => RawValue::Float(lhs - rhs),
+        ValBinaryOp::Mul => RawValue::Float(lhs * rhs),
+        ValBinaryOp::Div => RawValue::Float(lhs / rhs),
+        ValBinaryOp::Mod => RawValue::Float(lhs % rhs),
+        _ => RawValue::Unit,
+    }
+}
+
 fn bin_str_val(lhs: RawValue, op: &ValBinaryOp, rhs: RawValue) -> RawValue {
     match op {
         ValBinaryOp::Add => RawValue::Str((lhs.get_str() + rhs.get_str()).into()),
-        ValBinaryOp::Mul => match (
-            lhs.get_type().max(ValueType::Num),
-            rhs.get_type().max(ValueType::Num),
-        ) {
-            (ValueType::Str, ValueType::Str) => RawValue::Unit,
-            (ValueType::Num, ValueType::Str) => {
-                RawValue::Str(rhs.get_str().repeat(lhs.get_num() as usize))
+        ValBinaryOp::Mul => match (lhs, rhs) {
+            (RawValue::Str(_), RawValue::Str(_)) => RawValue::Unit,
+            (RawValue::Str(s), n) | (n, RawValue::Str(s)) => {
+                RawValue::Str(s.repeat(repeat_count(&n)))
             }
-            (ValueType::Str, ValueType::Num) => {
-                RawValue::Str(lhs.get_str().repeat(rhs.get_num() as usize))
+            (lhs, rhs) => mismatched(lhs, op, rhs),
+        },
+        _ => RawValue::Unit,
+    }
+}
+
+// A negative count repeats nothing.
+fn repeat_count(n: &RawValue) -> usize {
+    usize::try_from(n.get_num()).unwrap_or_default()
+}
+
+fn bin_list_val(lhs: RawValue, op: &ValBinaryOp, rhs: RawValue) -> RawValue {
+    match op {
+        ValBinaryOp::Add => match (lhs, rhs) {
+            (RawValue::List(mut lhs), RawValue::List(mut rhs)) => {
+                lhs.append(&mut rhs);
+                RawValue::List(lhs)
+            }
+            (RawValue::List(mut l), v) => {
+                l.push(v);
+                RawValue::List(l)
+            }
+            (v, RawValue::List(mut l)) => {
+                l.insert(0, v);
+                RawValue::List(l)
+            }
+            (lhs, rhs) => mismatched(lhs, op, rhs),
+        },
+        ValBinaryOp::Minus => match lhs {
+            RawValue::List(l) => {
+                let rhs = match rhs {
+                    RawValue::List(rhs) => rhs,
+                    v => vec![v],
+                };
+                RawValue::List(l.into_iter().filter(|v| !rhs.contains(v)).collect())
             }
-            _ => unreachable!(),
+            _ => RawValue::Unit,
+        },
+        ValBinaryOp::Mul => match (lhs, rhs) {
+            (RawValue::List(_), RawValue::List(_)) => RawValue::Unit,
+            (RawValue::List(l), n) | (n, RawValue::List(l)) => RawValue::List(
+                std::iter::repeat(l)
+                    .take(repeat_count(&n))
+                    .flatten()
+                    .collect(),
+            ),
+            (lhs, rhs) => mismatched(lhs, op, rhs),
         },
         _ => RawValue::Unit,
     }
 }
 
+fn mismatched(lhs: RawValue, op: &ValBinaryOp, rhs: RawValue) -> RawValue {
+    log::warn!("Cannot apply {:?} to {:?} and {:?}", op, lhs, rhs);
+    RawValue::Unit
+}
+
+fn bin_map_val(lhs: RawValue, op: &ValBinaryOp, rhs: RawValue) -> RawValue {
+    match (lhs, op, rhs) {
+        (RawValue::Map(mut lhs), ValBinaryOp::Add, RawValue::Map(rhs)) => {
+            lhs.extend(rhs);
+            RawValue::Map(lhs)
+        }
+        (RawValue::Map(mut m), ValBinaryOp::Minus, rhs) => {
+            let keys: Vec<String> = match rhs {
+                RawValue::Map(rhs) => rhs.into_keys().collect(),
+                v => v.into_list().into_iter().map(|v| v.into_str()).collect(),
+            };
+            for key in keys {
+                m.remove(&key);
+            }
+            RawValue::Map(m)
+        }
+        _ => RawValue::Unit,
+    }
+}
+
 fn bin_logic(ctx: &mut VarTable, lhs: &Expr, op: &LogicBinaryOp, rhs: &Expr) -> RawValue {
     let res = match op {
         LogicBinaryOp::And => lhs.call(ctx).get_bool() && rhs.call(ctx).get_bool(),
@@ -163,14 +329,16 @@ fn bin_logic(ctx: &mut VarTable, lhs: &Expr, op: &LogicBinaryOp, rhs: &Expr) ->
                 ValueType::Unit => false,
                 ValueType::Bool => bin_ord_logic(&lhs.get_bool(), op, &rhs.get_bool()),
                 ValueType::Num => bin_ord_logic(&lhs.get_num(), op, &rhs.get_num()),
+                ValueType::Float => bin_ord_logic(&lhs.get_float(), op, &rhs.get_float()),
                 ValueType::Str => bin_ord_logic(&lhs.get_str(), op, &rhs.get_str()),
+                ValueType::List | ValueType::Map => bin_ord_logic(&lhs, op, &rhs),
             }
         }
     };
     RawValue::Bool(res)
 }
 
-fn bin_ord_logic<T: Ord>(lhs: &T, op: &LogicBinaryOp, rhs: &T) -> bool {
+fn bin_ord_logic<T: PartialOrd>(lhs: &T, op: &LogicBinaryOp, rhs: &T) -> bool {
     match op {
         LogicBinaryOp::Eq => lhs == rhs,
         LogicBinaryOp::Neq => lhs != rhs,
@@ -183,14 +351,25 @@ fn bin_ord_logic<T: Ord>(lhs: &T, op: &LogicBinaryOp, rhs: &T) -> bool {
 }
 
 fn assign(ctx: &mut VarTable, e: &Expr, val: RawValue) -> RawValue {
+    match place(ctx, e) {
+        Some(p) => *p = val,
+        None => log::warn!("Cannot assign to {:?}", e),
+    }
+    RawValue::Unit
+}
+
+fn place<'a>(ctx: &'a mut VarTable, e: &Expr) -> Option<&'a mut RawValue> {
     match e {
         Expr::Ref(r) => match r {
-            Ref::Var(n) => ctx.vars.insert(n.into(), val),
-            Ref::Ctx(n) => ctx.locals.insert(n.into(), val),
+            Ref::Var(n) => Some(ctx.vars.entry(n.into()).or_default()),
+            Ref::Ctx(n) => Some(ctx.locals.entry(n.into()).or_default()),
         },
-        _ => unreachable!(),
-    };
-    RawValue::Unit
+        Expr::Index(e, index) => {
+            let index = index.call(ctx);
+            get_index_mut(place(ctx, e)?, &index)
+        }
+        _ => None,
+    }
 }
 
 fn call(ctx: &mut VarTable, ns: &str, name: &str, args: &[Expr]) -> RawValue {
@@ -202,6 +381,28 @@ fn call(ctx: &mut VarTable, ns: &str, name: &str, args: &[Expr]) -> RawValue {
                 args.get(2)
             }
             .call(ctx),
+            "len" => match args.get(0).call(ctx) {
+                RawValue::Str(s) => RawValue::Num(s.chars().count() as i64),
+                RawValue::List(l) => RawValue::Num(l.len() as i64),
+                RawValue::Map(m) => RawValue::Num(m.len() as i64),
+                // The scalars have no length.
+                _ => RawValue::Num(0),
+            },
+            "keys" => match args.get(0).call(ctx) {
+                RawValue::Map(m) => RawValue::List(m.into_keys().map(RawValue::Str).collect()),
+                _ => RawValue::List(vec![]),
+            },
+            "values" => RawValue::List(args.get(0).call(ctx).into_list()),
+            "contains" => {
+                let value = args.get(0).call(ctx);
+                let item = args.get(1).call(ctx);
+                RawValue::Bool(match value {
+                    RawValue::List(l) => l.contains(&item),
+                    RawValue::Map(m) => m.contains_key(item.get_str().as_ref()),
+                    RawValue::Str(s) => s.contains(item.get_str().as_ref()),
+                    _ => false,
+                })
+            }
             _ => unimplemented!("intrinstics"),
         }
     } else {
@@ -6,19 +6,33 @@ use ayaka_bindings::*;
 fn plugin_type() -> PluginType {
     PluginType::builder()
         .text(["par", "textrm", "textsf", "texttt", "ruby"])
+        .metadata(plugin_metadata!())
         .build()
 }
 
-#[export]
-fn par(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
-    assert!(args.is_empty());
+fn check_args(cmd: &str, args: &[String], count: usize) -> Result<(), PluginError> {
+    if args.len() == count {
+        Ok(())
+    } else {
+        Err(PluginError::new(format!(
+            "`{}` expects {} argument(s), but {} were given",
+            cmd,
+            count,
+            args.len()
+        )))
+    }
+}
+
+#[export(result)]
+fn par(args: Vec<String>, ctx: TextProcessContext) -> Result<TextProcessResult, PluginError> {
+    check_args("par", &args, 0)?;
     let mut res = TextProcessResult::default();
     match ctx.frontend {
         FrontendType::Text => res.text.push_back_chars("\n"),
         FrontendType::Html => res.text.push_back_block("<br />"),
         FrontendType::Latex => res.text.push_back_block("\\par "),
     }
-    res
+    Ok(res)
 }
 
 fn text_font(
@@ -26,8 +40,8 @@ fn text_font(
     args: Vec<String>,
     ctx: TextProcessContext,
     fonts: &str,
-) -> TextProcessResult {
-    assert_eq!(args.len(), 1);
+) -> Result<TextProcessResult, PluginError> {
+    check_args(cmd, &args, 1)?;
     let mut res = TextProcessResult::default();
     match ctx.frontend {
         FrontendType::Text => res.text.push_back_chars(&args[0]),
@@ -43,43 +57,51 @@ fn text_font(
             res.text.push_back_block("}");
         }
     }
-    res
+    Ok(res)
 }
 
-#[export]
-fn textrm(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
+#[export(result)]
+fn textrm(args: Vec<String>, ctx: TextProcessContext) -> Result<TextProcessResult, PluginError> {
     text_font("textrm", args, ctx, "Times New Roman")
 }
 
-#[export]
-fn textsf(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
+#[export(result)]
+fn textsf(args: Vec<String>, ctx: TextProcessContext) -> Result<TextProcessResult, PluginError> {
     text_font("textsf", args, ctx, "Arial")
 }
 
-#[export]
-fn texttt(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
+#[export(result)]
+fn texttt(args: Vec<String>, ctx: TextProcessContext) -> Result<TextProcessResult, PluginError> {
     text_font("texttt", args, ctx, "Courier New")
 }
 
-#[export]
-fn ruby(args: Vec<String>, ctx: TextProcessContext) -> TextProcessResult {
-    assert_eq!(args.len(), 2);
+fn ruby_brackets(ctx: &TextProcessContext) -> (&'static str, &'static str) {
+    match ctx.locale.language.as_str() {
+        "ja" | "ko" | "zh" => ("（", "）"),
+        _ => ("(", ")"),
+    }
+}
+
+#[export(result)]
+fn ruby(args: Vec<String>, ctx: TextProcessContext) -> Result<TextProcessResult, PluginError> {
+    check_args("ruby", &args, 2)?;
+    let (left, right) = ruby_brackets(&ctx);
     let mut res = TextProcessResult::default();
     match ctx.frontend {
         FrontendType::Text => res
             .text
-            .push_back_chars(format!("{}（{}）", args[0], args[1])),
+            .push_back_chars(format!("{}{}{}{}", args[0], left, args[1], right)),
         FrontendType::Html => {
             res.text.push_back_block("<ruby>");
             res.text.push_back_chars(&args[0]);
-            res.text.push_back_block("<rp>（</rp><rt>");
+            res.text.push_back_block(format!("<rp>{}</rp><rt>", left));
             res.text.push_back_chars(&args[1]);
-            res.text.push_back_block("</rt><rp>）</rp>");
+            res.text.push_back_block(format!("</rt><rp>{}</rp>", right));
             res.text.push_back_block("</ruby>");
         }
         FrontendType::Latex => res
             .text
             .push_back_block(format!("\\ruby{{{}}}{{{}}}", args[0], args[1])),
     }
-    res
+    Ok(res)
 }
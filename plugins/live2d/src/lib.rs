@@ -5,7 +5,11 @@ use std::collections::{HashMap, HashSet};
 
 #[export]
 fn plugin_type() -> PluginType {
-    PluginType::builder().line(["show", "hide"]).game().build()
+    PluginType::builder()
+        .line(["show", "hide"])
+        .game()
+        .metadata(plugin_metadata!())
+        .build()
 }
 
 fn find_model(ch: &str, game_props: &HashMap<String, String>) -> Option<VfsPath> {
@@ -19,52 +23,61 @@ fn find_model(ch: &str, game_props: &HashMap<String, String>) -> Option<VfsPath>
     })
 }
 
+// The names could be a list, or comma-separated string in old records.
+fn model_names(value: Option<&RawValue>) -> Vec<String> {
+    match value {
+        Some(RawValue::List(names)) => names
+            .iter()
+            .map(|name| name.get_str().into_owned())
+            .collect(),
+        Some(value) => value
+            .get_str()
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_string())
+            .collect(),
+        None => vec![],
+    }
+}
+
+fn models_value(models: Vec<String>) -> RawValue {
+    RawValue::List(models.into_iter().map(RawValue::Str).collect())
+}
+
 #[export]
 fn show(ctx: LineProcessContext) -> LineProcessResult {
-    let models = ctx.props["show"].get_str();
-    let exist_models = ctx
-        .ctx
-        .locals
-        .get("ch_models")
-        .map(|value| value.get_str())
-        .unwrap_or_default();
-    let models = exist_models
-        .split(',')
+    let models = model_names(ctx.props.get("show"));
+    let models = model_names(ctx.ctx.locals.get("ch_models"))
+        .into_iter()
         .chain(
             models
-                .split(',')
+                .into_iter()
                 .filter(|name| ctx.game_props.contains_key(&format!("ch_{}_model", name))),
         )
         .collect::<Vec<_>>();
     let mut res = LineProcessResult::default();
     res.locals
-        .insert("ch_models".to_string(), RawValue::Str(models.join(",")));
+        .insert("ch_models".to_string(), models_value(models));
     res
 }
 
 #[export]
 fn hide(ctx: LineProcessContext) -> LineProcessResult {
-    let hide = ctx.props["hide"].get_str();
+    let hide = model_names(ctx.props.get("hide"));
 
-    let models = ctx
-        .ctx
-        .locals
-        .get("ch_models")
-        .map(|value| value.get_str())
-        .unwrap_or_default();
     let models = if hide.is_empty() {
         vec![]
     } else {
-        let hide = hide.split(',').collect::<HashSet<_>>();
-        models
-            .split(',')
+        let hide = hide.into_iter().collect::<HashSet<_>>();
+        model_names(ctx.ctx.locals.get("ch_models"))
+            .into_iter()
             .filter(|ch| !hide.contains(ch))
             .collect::<Vec<_>>()
     };
 
     let mut res = LineProcessResult::default();
     res.locals
-        .insert("ch_models".to_string(), RawValue::Str(models.join(",")));
+        .insert("ch_models".to_string(), models_value(models));
     res
 }
 
@@ -78,5 +91,8 @@ fn process_game(mut ctx: GameProcessContext) -> GameProcessResult {
             }
         }
     }
-    GameProcessResult { props: ctx.props }
+    GameProcessResult {
+        props: ctx.props,
+        ..Default::default()
+    }
 }
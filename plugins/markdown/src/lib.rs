@@ -9,7 +9,10 @@ use std::{
 
 #[export]
 fn plugin_type() -> PluginType {
-    PluginType::builder().action().build()
+    PluginType::builder()
+        .action()
+        .metadata(plugin_metadata!())
+        .build()
 }
 
 #[export]
@@ -8,6 +8,7 @@ fn plugin_type() -> PluginType {
         .action()
         .line(["bg", "bgm", "video"])
         .game()
+        .metadata(plugin_metadata!())
         .build()
 }
 
@@ -80,7 +81,7 @@ fn video(ctx: LineProcessContext) -> LineProcessResult {
 #[export]
 fn process_action(mut ctx: ActionProcessContext) -> ActionProcessResult {
     let root: VfsPath = HostFS::default().into();
-    let voice_id = ctx.ctx.cur_act.to_string();
+    let voice_id = ctx.ctx.line_key();
     let res = file(
         &voice_id,
         ctx.game_props
@@ -104,5 +105,8 @@ fn process_game(mut ctx: GameProcessContext) -> GameProcessResult {
             *bg = path.as_str().to_string();
         }
     }
-    GameProcessResult { props: ctx.props }
+    GameProcessResult {
+        props: ctx.props,
+        ..Default::default()
+    }
 }
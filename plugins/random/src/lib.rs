@@ -4,7 +4,7 @@ use ayaka_bindings::*;
 
 #[export]
 fn plugin_type() -> PluginType {
-    PluginType::default()
+    PluginType::builder().metadata(plugin_metadata!()).build()
 }
 
 #[import("rand")]
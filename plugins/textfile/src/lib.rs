@@ -0,0 +1,25 @@
+#![deny(unsafe_code)]
+
+use ayaka_bindings::*;
+
+#[export]
+fn plugin_type() -> PluginType {
+    PluginType::builder()
+        .line(["textfile"])
+        .metadata(plugin_metadata!())
+        .build()
+}
+
+#[export]
+fn textfile(ctx: LineProcessContext) -> LineProcessResult {
+    let path = ctx.props["textfile"].get_str();
+    let mut res = LineProcessResult::default();
+    match std::fs::read_to_string(path.as_ref()) {
+        Ok(text) => {
+            println!("Read {} bytes from {}", text.len(), path);
+            res.locals.insert("textfile".to_string(), RawValue::Str(text));
+        }
+        Err(e) => eprintln!("Cannot read {}: {}", path, e),
+    }
+    res
+}
@@ -4,18 +4,36 @@ use quote::{quote, TokenStreamExt};
 use syn::{parse_macro_input, parse_str, FnArg, ForeignItem, Ident, ItemFn, ItemForeignMod, Lit};
 
 #[proc_macro_attribute]
-pub fn export(_attr: TokenStream, input: TokenStream) -> TokenStream {
+pub fn export(attr: TokenStream, input: TokenStream) -> TokenStream {
+    // `#[export(result)]` marks the functions returning `Result`,
+    // which report the errors to the host.
+    let is_result = if attr.is_empty() {
+        false
+    } else {
+        let attr = parse_macro_input!(attr as Ident);
+        if attr != "result" {
+            return syn::Error::new(attr.span(), "expected `result`")
+                .to_compile_error()
+                .into();
+        }
+        true
+    };
     let func = input.clone();
     let func = parse_macro_input!(func as ItemFn);
     let name = func.sig.ident;
     let name_str = name.to_string();
     let expname = parse_str::<Ident>(&format!("__export_{}", name_str)).unwrap();
+    let export = if is_result {
+        quote!(__export_result)
+    } else {
+        quote!(__export)
+    };
     let input = proc_macro2::TokenStream::from(input);
     let export_func = quote! {
         #[doc(hidden)]
         #[export_name = #name_str]
         extern "C" fn #expname(len: usize, data: *const u8) -> u64 {
-            ::ayaka_bindings::__export(len, data, #name)
+            ::ayaka_bindings::#export(len, data, #name)
         }
         #input
     };
@@ -60,8 +60,73 @@ pub struct RawContext {
     pub cur_para: String,
     /// Current text index.
     pub cur_act: usize,
+    /// Current line identifier, if specified.
+    #[serde(default)]
+    pub cur_id: Option<String>,
     /// Current local variables.
     pub locals: VarMap,
+    /// The return points of the called paragraphs.
+    #[serde(default)]
+    pub call_stack: Vec<CallFrame>,
+    /// The state of the random number generator.
+    /// If [`None`], the generator is seeded when the context runs.
+    #[serde(default)]
+    pub rng: Option<RngState>,
+}
+
+impl RawContext {
+    /// Gets the key of the current line.
+    /// It is the line identifier if specified, otherwise the text index.
+    ///
+    /// ```
+    /// # use ayaka_bindings_types::*;
+    /// let mut ctx = RawContext {
+    ///     cur_act: 1,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(ctx.line_key(), "1");
+    /// ctx.cur_id = Some("hello".to_string());
+    /// assert_eq!(ctx.line_key(), "hello");
+    /// ```
+    pub fn line_key(&self) -> Cow<str> {
+        match &self.cur_id {
+            Some(id) => Cow::Borrowed(id),
+            None => Cow::Owned(self.cur_act.to_string()),
+        }
+    }
+}
+
+/// The return point of a called paragraph.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CallFrame {
+    /// The base paragraph tag to return.
+    pub cur_base_para: String,
+    /// The paragraph tag to return.
+    pub cur_para: String,
+    /// The text index to return.
+    pub cur_act: usize,
+    /// The line identifier to return, if specified.
+    #[serde(default)]
+    pub cur_id: Option<String>,
+}
+
+/// The state of the random number generator used by the plugins.
+///
+/// The generator is fully determined by the state,
+/// so that the outcomes are the same after loading a record.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RngState {
+    /// The seed.
+    pub seed: u64,
+    /// The count of the generated 32-bit words.
+    pub pos: u64,
+}
+
+impl RngState {
+    /// Creates a new [`RngState`] from a seed.
+    pub const fn new(seed: u64) -> Self {
+        Self { seed, pos: 0 }
+    }
 }
 
 /// The `text` is a [`VecDeque<ActionSubText>`].
@@ -89,6 +154,9 @@ pub struct ActionText {
     pub character: Option<String>,
     /// The temp variables.
     pub vars: VarMap,
+    /// The timeout in milliseconds.
+    /// The action advances automatically when timed out.
+    pub timeout: Option<u64>,
 }
 
 impl ActionText {
@@ -130,6 +198,7 @@ impl PartialEq for ActionText {
             && self.ch_key == other.ch_key
             && self.character == other.character
             && self.vars == other.vars
+            && self.timeout == other.timeout
     }
 }
 
@@ -144,11 +213,34 @@ pub enum Action {
     /// A text action, display some texts.
     Text(ActionText),
     /// A switch action, display switches and let player to choose.
-    Switches(Vec<Switch>),
+    Switches(ActionSwitches),
+    /// An input action, display a prompt and let player to input a text.
+    Input(ActionInput),
     /// A custom action.
     Custom(VarMap),
 }
 
+/// The input of an [`Action`].
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ActionInput {
+    /// The prompt text.
+    pub prompt: ActionText,
+    /// The default value.
+    pub default: Option<String>,
+}
+
+/// The switches of an [`Action`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionSwitches {
+    /// The switch items.
+    pub switches: Vec<Switch>,
+    /// The timeout in milliseconds.
+    /// The default switch is chosen when timed out.
+    pub timeout: Option<u64>,
+    /// The index of the switch chosen when timed out.
+    pub default: Option<usize>,
+}
+
 /// One switch in the switches of an [`Action`].
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize, FallbackSpec)]
 pub struct Switch {
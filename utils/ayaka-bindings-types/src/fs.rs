@@ -1,7 +1,7 @@
 use std::io::SeekFrom;
 
 use serde::{Deserialize, Serialize};
-use vfs::*;
+use vfs::{error::VfsErrorKind, *};
 
 /// Type of a file.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
@@ -87,3 +87,73 @@ impl From<FileSeekFrom> for SeekFrom {
         }
     }
 }
+
+/// The error of the file operations through the `fs` ABI.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum FileError {
+    /// The file or directory is not found.
+    NotFound,
+    /// The path is invalid, or escapes the root directory.
+    InvalidPath,
+    /// The path is not a file.
+    NotAFile,
+    /// The path is not a directory.
+    NotADir,
+    /// Other errors, with the message.
+    Other(String),
+}
+
+impl std::fmt::Display for FileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "the file or directory could not be found"),
+            Self::InvalidPath => write!(f, "the path is invalid"),
+            Self::NotAFile => write!(f, "the path is not a file"),
+            Self::NotADir => write!(f, "the path is not a directory"),
+            Self::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl From<std::io::Error> for FileError {
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            std::io::ErrorKind::NotFound => Self::NotFound,
+            _ => Self::Other(value.to_string()),
+        }
+    }
+}
+
+impl From<VfsError> for FileError {
+    fn from(value: VfsError) -> Self {
+        match value.kind() {
+            VfsErrorKind::FileNotFound => Self::NotFound,
+            VfsErrorKind::InvalidPath => Self::InvalidPath,
+            VfsErrorKind::IoError(e) if e.kind() == std::io::ErrorKind::NotFound => Self::NotFound,
+            _ => Self::Other(value.to_string()),
+        }
+    }
+}
+
+impl From<FileError> for VfsError {
+    fn from(value: FileError) -> Self {
+        match value {
+            FileError::NotFound => VfsErrorKind::FileNotFound.into(),
+            FileError::InvalidPath => VfsErrorKind::InvalidPath.into(),
+            e => VfsErrorKind::Other(e.to_string()).into(),
+        }
+    }
+}
+
+impl From<FileError> for std::io::Error {
+    fn from(value: FileError) -> Self {
+        let kind = match value {
+            FileError::NotFound => std::io::ErrorKind::NotFound,
+            FileError::InvalidPath => std::io::ErrorKind::InvalidInput,
+            _ => std::io::ErrorKind::Other,
+        };
+        Self::new(kind, value)
+    }
+}
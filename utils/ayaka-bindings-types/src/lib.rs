@@ -14,3 +14,6 @@ pub use config::*;
 
 mod fs;
 pub use fs::*;
+
+#[doc(no_inline)]
+pub use icu_locid::LanguageIdentifier;
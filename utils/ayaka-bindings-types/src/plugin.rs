@@ -2,6 +2,80 @@ use crate::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+/// The version of the ABI between the plugins and the runtime.
+///
+/// It is bumped when the calling convention or the types passed
+/// between the plugins and the runtime change incompatibly.
+/// The plugins built with `ayaka-bindings` export it as `__abi_version`.
+pub const ABI_VERSION: u32 = 4;
+
+/// The error returned by the exported methods of a plugin.
+///
+/// The exported methods return `Result<T, PluginError>` across the ABI.
+/// The runtime reports the errors from the text and line commands
+/// as warnings of the current line, instead of aborting.
+///
+/// ```ignore
+/// use ayaka_bindings::*;
+///
+/// #[export(result)]
+/// fn hello(args: Vec<String>, _ctx: TextProcessContext) -> Result<TextProcessResult, PluginError> {
+///     if args.len() != 1 {
+///         return Err(PluginError::new("expected 1 argument"));
+///     }
+///     Ok(TextProcessResult::default())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginError {
+    /// The error message.
+    pub message: String,
+}
+
+impl PluginError {
+    /// Creates a [`PluginError`] with message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+impl From<String> for PluginError {
+    fn from(message: String) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<&str> for PluginError {
+    fn from(message: &str) -> Self {
+        Self::new(message)
+    }
+}
+
+/// The metadata of a plugin.
+///
+/// The fields could be filled from the package by `plugin_metadata!()` in `ayaka-bindings`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginMetadata {
+    /// The name of the plugin.
+    pub name: String,
+    /// The version of the plugin.
+    pub version: String,
+    /// The author of the plugin.
+    pub author: String,
+    /// The minimum version of the runtime required by the plugin.
+    pub min_runtime: Option<String>,
+}
+
 /// The bit flags to describe plugin type.
 ///
 /// Every plugin should provide a function `plugin_type`,
@@ -29,6 +103,26 @@ pub struct PluginType {
     /// The game plugin.
     /// This plugin processes the game properties after it is loaded.
     pub game: bool,
+    /// The metadata of the plugin.
+    #[serde(default)]
+    pub metadata: PluginMetadata,
+    /// The paragraph plugin.
+    /// This plugin is notified when a paragraph is entered or left.
+    #[serde(default)]
+    pub paragraph: bool,
+    /// The switch plugin.
+    /// This plugin is notified when a switch item is chosen.
+    #[serde(default)]
+    pub switch: bool,
+    /// The record plugin.
+    /// This plugin is notified when a record is saved or loaded.
+    #[serde(default)]
+    pub record: bool,
+    /// The props accepted by the line commands, keyed by the commands.
+    /// A line command only gets its own key and the props declared here,
+    /// or all the keys in the line if it declares none.
+    #[serde(default)]
+    pub line_props: HashMap<String, Vec<String>>,
 }
 
 impl PluginType {
@@ -64,12 +158,48 @@ impl PluginTypeBuilder {
         self
     }
 
+    /// The props accepted by a line command.
+    pub fn line_props(
+        mut self,
+        cmd: impl Into<String>,
+        props: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.data
+            .line_props
+            .insert(cmd.into(), props.into_iter().map(|s| s.into()).collect());
+        self
+    }
+
     /// A game plugin.
     pub fn game(mut self) -> Self {
         self.data.game = true;
         self
     }
 
+    /// A paragraph plugin.
+    pub fn paragraph(mut self) -> Self {
+        self.data.paragraph = true;
+        self
+    }
+
+    /// A switch plugin.
+    pub fn switch(mut self) -> Self {
+        self.data.switch = true;
+        self
+    }
+
+    /// A record plugin.
+    pub fn record(mut self) -> Self {
+        self.data.record = true;
+        self
+    }
+
+    /// The metadata of the plugin.
+    pub fn metadata(mut self, metadata: PluginMetadata) -> Self {
+        self.data.metadata = metadata;
+        self
+    }
+
     /// Build a [`PluginType`].
     pub fn build(self) -> PluginType {
         self.data
@@ -105,6 +235,8 @@ pub struct ActionProcessContext {
     pub game_props: HashMap<String, String>,
     /// The frontend type.
     pub frontend: FrontendType,
+    /// The current locale.
+    pub locale: LanguageIdentifier,
     /// The current context.
     pub ctx: RawContext,
     /// The current action.
@@ -116,10 +248,23 @@ pub struct ActionProcessContext {
 pub struct ActionProcessContextRef<'a> {
     pub game_props: &'a HashMap<String, String>,
     pub frontend: FrontendType,
+    pub locale: &'a LanguageIdentifier,
     pub ctx: &'a RawContext,
     pub action: &'a ActionText,
 }
 
+impl From<ActionProcessContextRef<'_>> for ActionProcessContext {
+    fn from(ctx: ActionProcessContextRef) -> Self {
+        Self {
+            game_props: ctx.game_props.clone(),
+            frontend: ctx.frontend,
+            locale: ctx.locale.clone(),
+            ctx: ctx.ctx.clone(),
+            action: ctx.action.clone(),
+        }
+    }
+}
+
 /// The result of action plugins.
 /// See examples at [`ActionProcessContext`].
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -151,6 +296,10 @@ pub struct TextProcessContext {
     pub game_props: HashMap<String, String>,
     /// The frontend type.
     pub frontend: FrontendType,
+    /// The current locale.
+    pub locale: LanguageIdentifier,
+    /// The current context.
+    pub ctx: RawContext,
 }
 
 #[derive(Debug, Serialize)]
@@ -158,6 +307,19 @@ pub struct TextProcessContext {
 pub struct TextProcessContextRef<'a> {
     pub game_props: &'a HashMap<String, String>,
     pub frontend: FrontendType,
+    pub locale: &'a LanguageIdentifier,
+    pub ctx: &'a RawContext,
+}
+
+impl From<TextProcessContextRef<'_>> for TextProcessContext {
+    fn from(ctx: TextProcessContextRef) -> Self {
+        Self {
+            game_props: ctx.game_props.clone(),
+            frontend: ctx.frontend,
+            locale: ctx.locale.clone(),
+            ctx: ctx.ctx.clone(),
+        }
+    }
 }
 
 /// The result of commands in text plugins.
@@ -177,7 +339,10 @@ pub struct TextProcessResult {
 /// #[export]
 /// fn process_game(mut ctx: GameProcessContext) -> GameProcessResult {
 ///     // Process the game...
-///     GameProcessResult { props: ctx.props }
+///     GameProcessResult {
+///         props: ctx.props,
+///         ..Default::default()
+///     }
 /// }
 /// ```
 #[derive(Debug, Serialize, Deserialize)]
@@ -198,12 +363,33 @@ pub struct GameProcessContextRef<'a> {
     pub props: &'a HashMap<String, String>,
 }
 
+impl From<GameProcessContextRef<'_>> for GameProcessContext {
+    fn from(ctx: GameProcessContextRef) -> Self {
+        Self {
+            title: ctx.title.to_string(),
+            author: ctx.author.to_string(),
+            props: ctx.props.clone(),
+        }
+    }
+}
+
 /// The result of game plugins.
 /// See examples at [`GameProcessContext`].
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GameProcessResult {
     /// The updated properties.
     pub props: HashMap<String, String>,
+    /// The contributed paragraph files, indexed by locale and file name without extension.
+    /// They are in the same YAML format as the paragraph files.
+    #[serde(default)]
+    pub paras: HashMap<String, HashMap<String, String>>,
+    /// The contributed resources, indexed by locale.
+    #[serde(default)]
+    pub res: HashMap<String, VarMap>,
+    /// The contributed files, indexed by path.
+    /// They are mounted into the root directory.
+    #[serde(default)]
+    pub files: HashMap<String, Vec<u8>>,
 }
 
 /// The argument to line plugin.
@@ -229,6 +415,8 @@ pub struct LineProcessContext {
     pub game_props: HashMap<String, String>,
     /// The frontend type.
     pub frontend: FrontendType,
+    /// The current locale.
+    pub locale: LanguageIdentifier,
     /// The current context.
     pub ctx: RawContext,
     /// The full properties of the custom command.
@@ -240,10 +428,23 @@ pub struct LineProcessContext {
 pub struct LineProcessContextRef<'a> {
     pub game_props: &'a HashMap<String, String>,
     pub frontend: FrontendType,
+    pub locale: &'a LanguageIdentifier,
     pub ctx: &'a RawContext,
     pub props: &'a VarMap,
 }
 
+impl From<LineProcessContextRef<'_>> for LineProcessContext {
+    fn from(ctx: LineProcessContextRef) -> Self {
+        Self {
+            game_props: ctx.game_props.clone(),
+            frontend: ctx.frontend,
+            locale: ctx.locale.clone(),
+            ctx: ctx.ctx.clone(),
+            props: ctx.props.clone(),
+        }
+    }
+}
+
 /// The result of commands in line plugins.
 /// See examples at [`LineProcessContext`].
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -252,4 +453,202 @@ pub struct LineProcessResult {
     pub locals: VarMap,
     /// The temp variables.
     pub vars: VarMap,
+    /// The flow directive applied after the current line.
+    #[serde(default)]
+    pub flow: Option<FlowDirective>,
+}
+
+/// The flow directive returned by line plugins.
+///
+/// It is applied after the current line is recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FlowDirective {
+    /// Jumps to the paragraph by tag, the same as the `next` of a paragraph.
+    Jump(String),
+    /// Skips the next lines by count.
+    /// The paragraph ends if there are not enough lines.
+    Skip(usize),
+    /// Ends the current paragraph, and the `next` of the paragraph is applied.
+    EndParagraph,
+}
+
+/// The event of paragraph plugins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParagraphEvent {
+    /// Before the first line of the paragraph is processed.
+    Enter,
+    /// After the last line of the paragraph is processed.
+    Leave,
+}
+
+/// The argument to paragraph plugin.
+///
+/// Every paragraph plugin should implement `process_paragraph`:
+/// ```ignore
+/// use ayaka_bindings::*;
+///
+/// #[export]
+/// fn process_paragraph(ctx: ParagraphProcessContext) -> ParagraphProcessResult {
+///     let mut res = ParagraphProcessResult::default();
+///     if ctx.event == ParagraphEvent::Enter {
+///         res.locals.insert("entered".to_string(), RawValue::Str(ctx.ctx.cur_para));
+///     }
+///     res
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParagraphProcessContext {
+    /// The global properties of the game profile.
+    pub game_props: HashMap<String, String>,
+    /// The frontend type.
+    pub frontend: FrontendType,
+    /// The current context.
+    pub ctx: RawContext,
+    /// The event.
+    pub event: ParagraphEvent,
+}
+
+#[derive(Debug, Serialize)]
+#[doc(hidden)]
+pub struct ParagraphProcessContextRef<'a> {
+    pub game_props: &'a HashMap<String, String>,
+    pub frontend: FrontendType,
+    pub ctx: &'a RawContext,
+    pub event: ParagraphEvent,
+}
+
+impl From<ParagraphProcessContextRef<'_>> for ParagraphProcessContext {
+    fn from(ctx: ParagraphProcessContextRef) -> Self {
+        Self {
+            game_props: ctx.game_props.clone(),
+            frontend: ctx.frontend,
+            ctx: ctx.ctx.clone(),
+            event: ctx.event,
+        }
+    }
+}
+
+/// The result of paragraph plugins.
+/// See examples at [`ParagraphProcessContext`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ParagraphProcessResult {
+    /// The updated variables.
+    pub locals: VarMap,
+}
+
+/// The argument to switch plugin.
+///
+/// Every switch plugin should implement `process_switch`:
+/// ```ignore
+/// use ayaka_bindings::*;
+///
+/// #[export]
+/// fn process_switch(ctx: SwitchProcessContext) -> SwitchProcessResult {
+///     let mut res = SwitchProcessResult::default();
+///     res.locals.insert("last_choice".to_string(), RawValue::Num(ctx.index as i64));
+///     res
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SwitchProcessContext {
+    /// The global properties of the game profile.
+    pub game_props: HashMap<String, String>,
+    /// The frontend type.
+    pub frontend: FrontendType,
+    /// The current context.
+    pub ctx: RawContext,
+    /// The index of the chosen item.
+    pub index: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[doc(hidden)]
+pub struct SwitchProcessContextRef<'a> {
+    pub game_props: &'a HashMap<String, String>,
+    pub frontend: FrontendType,
+    pub ctx: &'a RawContext,
+    pub index: usize,
+}
+
+impl From<SwitchProcessContextRef<'_>> for SwitchProcessContext {
+    fn from(ctx: SwitchProcessContextRef) -> Self {
+        Self {
+            game_props: ctx.game_props.clone(),
+            frontend: ctx.frontend,
+            ctx: ctx.ctx.clone(),
+            index: ctx.index,
+        }
+    }
+}
+
+/// The result of switch plugins.
+/// See examples at [`SwitchProcessContext`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SwitchProcessResult {
+    /// The updated variables.
+    pub locals: VarMap,
+}
+
+/// The event of record plugins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordEvent {
+    /// The record is being saved.
+    Save,
+    /// The record is being loaded.
+    Load,
+}
+
+/// The argument to record plugin.
+///
+/// Every record plugin should implement `process_record`:
+/// ```ignore
+/// use ayaka_bindings::*;
+///
+/// #[export]
+/// fn process_record(ctx: RecordProcessContext) -> RecordProcessResult {
+///     let mut res = RecordProcessResult::default();
+///     if ctx.event == RecordEvent::Save {
+///         res.locals.insert("saved".to_string(), RawValue::Bool(true));
+///     }
+///     res
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordProcessContext {
+    /// The global properties of the game profile.
+    pub game_props: HashMap<String, String>,
+    /// The frontend type.
+    pub frontend: FrontendType,
+    /// The context in the record.
+    pub ctx: RawContext,
+    /// The event.
+    pub event: RecordEvent,
+}
+
+#[derive(Debug, Serialize)]
+#[doc(hidden)]
+pub struct RecordProcessContextRef<'a> {
+    pub game_props: &'a HashMap<String, String>,
+    pub frontend: FrontendType,
+    pub ctx: &'a RawContext,
+    pub event: RecordEvent,
+}
+
+impl From<RecordProcessContextRef<'_>> for RecordProcessContext {
+    fn from(ctx: RecordProcessContextRef) -> Self {
+        Self {
+            game_props: ctx.game_props.clone(),
+            frontend: ctx.frontend,
+            ctx: ctx.ctx.clone(),
+            event: ctx.event,
+        }
+    }
+}
+
+/// The result of record plugins.
+/// See examples at [`RecordProcessContext`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RecordProcessResult {
+    /// The updated variables of the context in the record.
+    pub locals: VarMap,
 }
@@ -51,22 +51,79 @@ unsafe fn __abi_alloc_from(data: &[u8]) -> (*mut u8, usize) {
     (slice.as_mut_ptr(), slice.len())
 }
 
-#[doc(hidden)]
-#[allow(clippy::not_unsafe_ptr_arg_deref)]
-pub fn __export<Params: DeserializeOwned + Tuple, Res: Serialize>(
+// The ABI version is not wrapped in `Result`,
+// so that it could be read by the runtimes of all versions.
+#[no_mangle]
+extern "C" fn __abi_version(_len: usize, _data: *const u8) -> u64 {
+    let data = rmp_serde::to_vec(&ABI_VERSION).expect("cannot serialize ABI version");
+    let (ptr, len) = unsafe { __abi_alloc_from(&data) };
+    ((len as u64) << 32) | (ptr as u64)
+}
+
+/// Creates a [`PluginMetadata`] from the package of the plugin.
+///
+/// ```ignore
+/// use ayaka_bindings::*;
+///
+/// #[export]
+/// fn plugin_type() -> PluginType {
+///     PluginType::builder().metadata(plugin_metadata!()).build()
+/// }
+/// ```
+#[macro_export]
+macro_rules! plugin_metadata {
+    () => {
+        $crate::PluginMetadata {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            author: env!("CARGO_PKG_AUTHORS").to_string(),
+            min_runtime: None,
+        }
+    };
+    ($min_runtime:expr) => {
+        $crate::PluginMetadata {
+            min_runtime: Some($min_runtime.to_string()),
+            ..$crate::plugin_metadata!()
+        }
+    };
+}
+
+fn __export_impl<Params: DeserializeOwned, Res: Serialize>(
     len: usize,
     data: *const u8,
-    f: impl FnOnce<Params, Output = Res>,
+    f: impl FnOnce(Params) -> Result<Res, PluginError>,
 ) -> u64 {
     logger::PluginLogger::init();
     let data = unsafe { std::slice::from_raw_parts(data, len) };
-    let data = rmp_serde::from_slice(data).expect("cannot deserialize input args");
-    let res = f.call_once(data);
-    let data = rmp_serde::to_vec(&res).expect("cannot serialize result");
+    let res = rmp_serde::from_slice(data)
+        .map_err(|e| PluginError::new(format!("cannot deserialize input args: {}", e)))
+        .and_then(f);
+    let data = rmp_serde::to_vec(&res).unwrap_or_else(|e| {
+        let res = Err::<(), _>(PluginError::new(format!("cannot serialize result: {}", e)));
+        rmp_serde::to_vec(&res).expect("cannot serialize error")
+    });
     let (ptr, len) = unsafe { __abi_alloc_from(&data) };
     ((len as u64) << 32) | (ptr as u64)
 }
 
+#[doc(hidden)]
+pub fn __export<Params: DeserializeOwned + Tuple, Res: Serialize>(
+    len: usize,
+    data: *const u8,
+    f: impl FnOnce<Params, Output = Res>,
+) -> u64 {
+    __export_impl(len, data, |args| Ok(f.call_once(args)))
+}
+
+#[doc(hidden)]
+pub fn __export_result<Params: DeserializeOwned + Tuple, Res: Serialize, E: Into<PluginError>>(
+    len: usize,
+    data: *const u8,
+    f: impl FnOnce<Params, Output = Result<Res, E>>,
+) -> u64 {
+    __export_impl(len, data, |args| f.call_once(args).map_err(Into::into))
+}
+
 #[doc(hidden)]
 pub fn __import<Params: Serialize, Res: DeserializeOwned>(
     f: unsafe extern "C" fn(len: usize, data: *const u8) -> u64,
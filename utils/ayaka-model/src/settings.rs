@@ -2,7 +2,7 @@ use crate::*;
 use anyhow::Result;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
 };
 
@@ -39,13 +39,23 @@ pub struct GlobalRecord {
     /// The key is the tag of paragraphs,
     /// the value is the maximum text index.
     pub record: HashMap<String, usize>,
+    /// The key is the tag of paragraphs,
+    /// the value is the visited line identifiers.
+    #[serde(default)]
+    pub ids: HashMap<String, HashSet<String>>,
 }
 
 impl GlobalRecord {
     /// Determine if an [`RawContext`] has been visited,
-    /// by the paragraph tag and action index.
+    /// by the paragraph tag and the line identifier,
+    /// or the action index if the line identifier is not specified.
     pub fn visited(&self, ctx: &RawContext) -> bool {
-        if let Some(max_act) = self.record.get(&ctx.cur_para) {
+        if let Some(id) = &ctx.cur_id {
+            self.ids
+                .get(&ctx.cur_para)
+                .map(|ids| ids.contains(id))
+                .unwrap_or_default()
+        } else if let Some(max_act) = self.record.get(&ctx.cur_para) {
             log::debug!("Test act: {}, max act: {}", ctx.cur_act, max_act);
             *max_act >= ctx.cur_act
         } else {
@@ -59,6 +69,12 @@ impl GlobalRecord {
             .entry(ctx.cur_para.clone())
             .and_modify(|act| *act = (*act).max(ctx.cur_act))
             .or_insert(ctx.cur_act);
+        if let Some(id) = &ctx.cur_id {
+            self.ids
+                .entry(ctx.cur_para.clone())
+                .or_default()
+                .insert(id.clone());
+        }
     }
 }
 
@@ -145,8 +145,13 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
     }
 
     /// Start a game with record.
-    pub fn init_context(&mut self, record: ActionRecord) {
+    pub fn init_context(&mut self, mut record: ActionRecord) {
+        // The lines may be moved since the record was saved.
+        for ctx in &mut record.history {
+            self.context().game().relocate(ctx);
+        }
         let mut ctx = record.last_ctx_with_game(self.context().game());
+        self.context().process_record(RecordEvent::Load, &mut ctx);
         self.current_record = record;
         // Update current raw context.
         self.current_raw_context = self.current_record.history.last().cloned();
@@ -161,34 +166,36 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
         self.init_context(self.records()[index].clone())
     }
 
-    fn push_history(&mut self, ctx: &RawContext) {
-        let cur_text = self
-            .context()
+    fn line(&self, ctx: &RawContext) -> Option<&Line> {
+        self.context()
             .game()
             .find_para(
                 &self.context().game().config.base_lang,
                 &ctx.cur_base_para,
                 &ctx.cur_para,
             )
-            .and_then(|p| p.texts.get(ctx.cur_act));
-        let is_text = cur_text
-            .map(|line| matches!(line, Line::Text(_)))
-            .unwrap_or_default();
-        if is_text {
+            .and_then(|p| p.line(ctx.cur_act))
+    }
+
+    fn push_history(&mut self, ctx: &RawContext) {
+        if matches!(self.line(ctx), Some(Line::Text(_) | Line::Input { .. })) {
             self.current_record.history.push(ctx.clone());
         }
     }
 
-    /// Step to the next run.
-    pub fn next_run(&mut self) -> bool {
-        let ctx = self.context_mut().next_run();
+    /// Step to the next run by language.
+    ///
+    /// It fails if [`Context::next_run`] fails.
+    pub fn next_run(&mut self) -> Result<bool> {
+        let loc = self.settings().lang.clone();
+        let ctx = self.context_mut().next_run(&loc)?;
         if let Some(ctx) = &ctx {
             self.push_history(ctx);
             self.global_record_mut().update(ctx);
             log::debug!("{:?}", ctx);
         }
         self.current_raw_context = ctx;
-        self.current_raw_context.is_some()
+        Ok(self.current_raw_context.is_some())
     }
 
     /// Step back to the last run.
@@ -263,9 +270,45 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
         self.context_mut().switch(i);
     }
 
+    /// Submit the input of the current input line.
+    ///
+    /// Returns `false` if the input is rejected by the validation.
+    pub fn input(&mut self, value: impl Into<String>) -> Result<bool> {
+        let value = value.into();
+        log::debug!("Input {}", value);
+        let loc = self.settings().lang.clone();
+        let valid = self.context_mut().input(&loc, value.clone())?;
+        if valid {
+            // The value is stored in the history, and restored on load and rollback.
+            let name = match self.current_run().and_then(|ctx| self.line(ctx)) {
+                Some(Line::Input { input, .. }) => Some(input.clone()),
+                _ => None,
+            };
+            if let (Some(name), Some(ctx)) = (name, self.current_record.history.last_mut()) {
+                ctx.locals.insert(name, RawValue::Str(value));
+                self.current_raw_context = Some(ctx.clone());
+            }
+        }
+        Ok(valid)
+    }
+
+    /// Resolve the timeout of the current line.
+    ///
+    /// Returns the index of the chosen switch if the current line is timed switches.
+    pub fn timeout(&mut self) -> Option<usize> {
+        let res = self.context_mut().timeout();
+        log::debug!("Timeout {:?}", res);
+        res
+    }
+
     /// Save current [`ActionRecord`] to the records.
+    ///
+    /// The record plugins could update the last context of the saved record.
     pub fn save_current_to(&mut self, index: usize) {
-        let record = self.current_record.clone();
+        let mut record = self.current_record.clone();
+        if let Some(ctx) = record.history.last_mut() {
+            self.context().process_record(RecordEvent::Save, ctx);
+        }
         if index >= self.records.len() {
             self.records.push(record);
         } else {
@@ -291,6 +334,8 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
     }
 
     /// Get the last action text from each record.
+    ///
+    /// The prompt is returned if the last action is an input.
     pub fn records_text(&self) -> impl Iterator<Item = ActionText> + '_ {
         self.records().iter().map(|record| {
             let raw_ctx = record
@@ -300,10 +345,10 @@ impl<S: SettingsManager, M: RawModule + Send + Sync + 'static> GameViewModel<S,
                 self.context().get_action(&self.settings().lang, raw_ctx),
                 "Cannot get action"
             );
-            if let Action::Text(action) = action {
-                action
-            } else {
-                panic!("action in the record should be text action")
+            match action {
+                Action::Text(action) => action,
+                Action::Input(action) => action.prompt,
+                _ => ActionText::default(),
             }
         })
     }
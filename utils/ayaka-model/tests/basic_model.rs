@@ -1,11 +1,14 @@
 #![feature(return_position_impl_trait_in_trait)]
 #![allow(incomplete_features)]
 
+mod common;
+
 use ayaka_model::{
     anyhow::{Error, Result},
     *,
 };
-use ayaka_plugin_wasmi::{WasmiLinker, WasmiModule};
+use ayaka_plugin_wasmi::WasmiModule;
+use common::text_chars;
 use serde::{de::DeserializeOwned, Serialize};
 use std::{
     path::{Path, PathBuf},
@@ -91,13 +94,7 @@ const CONFIG_PATH: &str = "tests/basic/config.yaml";
 async fn progress() {
     let mut model =
         GameViewModel::<NopSettingsManager, WasmiModule>::new(NopSettingsManager::new().unwrap());
-    let linker = WasmiLinker::new(()).unwrap();
-    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
-        .with_paths(&[CONFIG_PATH])
-        .unwrap()
-        .open()
-        .await
-        .unwrap();
+    let context = common::open(CONFIG_PATH).await;
     let mut context = model.open_game(context);
     let progresses = unsafe { Pin::new_unchecked(&mut context) }
         .collect::<Vec<_>>()
@@ -114,12 +111,6 @@ async fn progress() {
     );
 }
 
-fn text_chars(s: impl Into<String>) -> Action {
-    let mut text = ActionText::default();
-    text.push_back_chars(s.into());
-    Action::Text(text)
-}
-
 #[tokio::test(flavor = "current_thread")]
 async fn paras() {
     let manager = {
@@ -133,17 +124,11 @@ async fn paras() {
         manager
     };
     let mut model = GameViewModel::<NopSettingsManager, WasmiModule>::new(manager);
-    let linker = WasmiLinker::new(()).unwrap();
-    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
-        .with_paths(&[CONFIG_PATH])
-        .unwrap()
-        .open()
-        .await
-        .unwrap();
+    let context = common::open(CONFIG_PATH).await;
     model.open_game(context).await.unwrap();
     model.init_new();
     let actions = std::iter::from_fn(|| {
-        if model.next_run() {
+        if model.next_run().unwrap() {
             model.current_actions()
         } else {
             None
@@ -160,3 +145,70 @@ async fn paras() {
         ]
     )
 }
+
+const INPUT_CONFIG_PATH: &str = "tests/input/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn input() {
+    let mut model =
+        GameViewModel::<NopSettingsManager, WasmiModule>::new(NopSettingsManager::new().unwrap());
+    let context = common::open(INPUT_CONFIG_PATH).await;
+    model.open_game(context).await.unwrap();
+    model.init_new();
+    assert!(model.next_run().unwrap());
+    assert_eq!(model.current_action(), Some(text_chars("Hello!")));
+    assert!(model.next_run().unwrap());
+    let mut prompt = ActionText::default();
+    prompt.push_back_chars("Your name?");
+    assert_eq!(
+        model.current_action(),
+        Some(Action::Input(ActionInput {
+            prompt,
+            default: Some("Ayaka".to_string()),
+        }))
+    );
+    assert!(!model.input("").unwrap());
+    assert!(model.input("Alice").unwrap());
+    assert!(model.next_run().unwrap());
+    assert_eq!(model.current_action(), Some(text_chars("Hi, Alice!")));
+    assert!(model.input("Bob").is_err());
+    // The input line is in the history with the value.
+    assert_eq!(model.record().history.len(), 3);
+    assert_eq!(
+        model.record().history[1].locals["name"],
+        RawValue::Str("Alice".to_string())
+    );
+
+    // The value is restored on rollback.
+    assert!(model.next_back_run());
+    assert!(matches!(model.current_action(), Some(Action::Input(_))));
+    assert!(model.next_run().unwrap());
+    assert_eq!(model.current_action(), Some(text_chars("Hi, Alice!")));
+
+    model.save_current_to(0);
+    model.init_new();
+    assert!(model.next_run().unwrap());
+    assert!(model.next_run().unwrap());
+    assert!(model.next_run().unwrap());
+    assert_eq!(model.current_action(), Some(text_chars("Hi, Ayaka!")));
+
+    model.init_context_by_index(0);
+    assert_eq!(model.current_action(), Some(text_chars("Hi, Alice!")));
+
+    // The value is restored on load from the input line.
+    model.init_new();
+    assert!(model.next_run().unwrap());
+    assert!(model.next_run().unwrap());
+    assert!(model.input("Carol").unwrap());
+    model.save_current_to(1);
+    assert_eq!(
+        model
+            .records_text()
+            .map(|text| text.to_string())
+            .collect::<Vec<_>>(),
+        ["Hi, Alice!", "Your name?"]
+    );
+    model.init_context_by_index(1);
+    assert!(model.next_run().unwrap());
+    assert_eq!(model.current_action(), Some(text_chars("Hi, Carol!")));
+}
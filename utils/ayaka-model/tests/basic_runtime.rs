@@ -1,16 +1,15 @@
+mod common;
+
 use ayaka_model::*;
-use ayaka_plugin_wasmi::{WasmiLinker, WasmiModule};
+use ayaka_plugin_wasmi::WasmiModule;
+use common::text_chars;
 use std::pin::Pin;
 
 const CONFIG_PATH: &str = "tests/basic/config.yaml";
 
 #[tokio::test(flavor = "current_thread")]
 async fn progress() {
-    let linker = WasmiLinker::new(()).unwrap();
-    let mut context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
-        .with_paths(&[CONFIG_PATH])
-        .unwrap()
-        .open();
+    let mut context = common::builder().with_paths(&[CONFIG_PATH]).unwrap().open();
     let progresses = unsafe { Pin::new_unchecked(&mut context) }
         .collect::<Vec<_>>()
         .await;
@@ -29,13 +28,7 @@ async fn progress() {
 
 #[tokio::test(flavor = "current_thread")]
 async fn config() {
-    let linker = WasmiLinker::new(()).unwrap();
-    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
-        .with_paths(&[CONFIG_PATH])
-        .unwrap()
-        .open()
-        .await
-        .unwrap();
+    let context = common::open(CONFIG_PATH).await;
     let config = &context.game().config;
     assert_eq!(config.title, "Basic");
     assert_eq!(config.author, "Berrysoft");
@@ -44,16 +37,10 @@ async fn config() {
     assert_eq!(config.start, "init");
 }
 
-fn text_chars(s: impl Into<String>) -> Action {
-    let mut text = ActionText::default();
-    text.push_back_chars(s.into());
-    Action::Text(text)
-}
-
 fn paras(mut context: Context<WasmiModule>, loc: Locale, expected_actions: &[Action]) {
     context.set_start_context();
     let actions = std::iter::from_fn(|| {
-        let raw_ctx = context.next_run();
+        let raw_ctx = context.next_run(&loc).unwrap();
         raw_ctx.map(|raw_ctx| context.get_action(&loc, &raw_ctx).unwrap())
     })
     .collect::<Vec<_>>();
@@ -62,13 +49,7 @@ fn paras(mut context: Context<WasmiModule>, loc: Locale, expected_actions: &[Act
 
 #[tokio::test(flavor = "current_thread")]
 async fn paras_en() {
-    let linker = WasmiLinker::new(()).unwrap();
-    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
-        .with_paths(&[CONFIG_PATH])
-        .unwrap()
-        .open()
-        .await
-        .unwrap();
+    let context = common::open(CONFIG_PATH).await;
     let loc = locale!("en");
     paras(
         context,
@@ -84,13 +65,7 @@ async fn paras_en() {
 
 #[tokio::test(flavor = "current_thread")]
 async fn paras_zh() {
-    let linker = WasmiLinker::new(()).unwrap();
-    let context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
-        .with_paths(&[CONFIG_PATH])
-        .unwrap()
-        .open()
-        .await
-        .unwrap();
+    let context = common::open(CONFIG_PATH).await;
     let loc = locale!("zh");
     paras(
         context,
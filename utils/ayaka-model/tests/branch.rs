@@ -0,0 +1,48 @@
+mod common;
+
+use ayaka_model::*;
+use common::text_chars;
+
+const CONFIG_PATH: &str = "tests/branch/config.yaml";
+
+async fn run(switch: usize) -> Vec<Action> {
+    let mut context = common::open(CONFIG_PATH).await;
+    context.set_start_context();
+    let loc = locale!("en");
+    let mut actions = vec![];
+    while let Some(raw_ctx) = context.next_run(&loc).unwrap() {
+        let action = context.get_action(&loc, &raw_ctx).unwrap();
+        if let Action::Switches(_) = &action {
+            context.switch(switch);
+        } else {
+            actions.push(action);
+        }
+    }
+    actions
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn guards_and_switches() {
+    assert_eq!(
+        run(0).await,
+        [Action::Custom(VarMap::default()), text_chars("first")]
+    );
+    assert_eq!(
+        run(1).await,
+        [Action::Custom(VarMap::default()), text_chars("second")]
+    );
+    assert_eq!(run(2).await, [Action::Custom(VarMap::default())]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn eval_expr() {
+    let context = common::open(CONFIG_PATH).await;
+    let loc = locale!("en");
+    assert_eq!(
+        context.eval_locale(&loc, "1 + 1").unwrap(),
+        RawValue::Num(2)
+    );
+    // Only a single expression is evaluated.
+    let err = context.eval_locale(&loc, "1); $x = 2; (1").unwrap_err();
+    assert!(err.to_string().contains("Invalid expression"));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+
+const CONFIG_PATH: &str = "tests/call/config.yaml";
+
+fn texts(context: &mut Context<WasmiModule>) -> Vec<(String, RawContext)> {
+    let loc = locale!("en");
+    let mut actions = vec![];
+    while let Some(raw_ctx) = context.next_run(&loc).unwrap() {
+        if let Action::Text(text) = context.get_action(&loc, &raw_ctx).unwrap() {
+            actions.push((text.to_string(), raw_ctx));
+        }
+    }
+    actions
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn call_return() {
+    let mut context = common::open(CONFIG_PATH).await;
+    context.set_start_context();
+    let actions = texts(&mut context);
+    assert_eq!(
+        actions.iter().map(|(s, _)| s.as_str()).collect::<Vec<_>>(),
+        ["a", "m", "n", "b", "m", "n", "c"]
+    );
+    assert_eq!(
+        actions[2].1.call_stack,
+        [CallFrame {
+            cur_base_para: "init".to_string(),
+            cur_para: "init".to_string(),
+            cur_act: 2,
+            cur_id: None,
+        }]
+    );
+    assert!(actions[6].1.call_stack.is_empty());
+
+    // Restore from a record in the called paragraph.
+    let mut ctx = actions[5].1.clone();
+    ctx.cur_act += 1;
+    context.set_context(ctx);
+    let actions = texts(&mut context);
+    assert_eq!(
+        actions.iter().map(|(s, _)| s.as_str()).collect::<Vec<_>>(),
+        ["c"]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn call_without_to() {
+    let mut context = common::open(CONFIG_PATH).await;
+    context.set_context(RawContext {
+        cur_base_para: "init".to_string(),
+        cur_para: "nested".to_string(),
+        ..Default::default()
+    });
+    let loc = locale!("en");
+    let mut actions = vec![];
+    while let Some(raw_ctx) = context.next_run(&loc).unwrap() {
+        match context.get_action(&loc, &raw_ctx).unwrap() {
+            Action::Text(text) => actions.push(text.to_string()),
+            action => panic!("Unexpected action {:?}", action),
+        }
+    }
+    // The leaf returns to the caller of the sub, and the call lines are skipped.
+    assert_eq!(actions, ["x", "s", "l", "z"]);
+}
+
+fn start_at(context: &mut Context<WasmiModule>, para: &str) {
+    context.set_context(RawContext {
+        cur_base_para: "init".to_string(),
+        cur_para: para.to_string(),
+        ..Default::default()
+    });
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn call_cycle() {
+    let mut context = common::open(CONFIG_PATH).await;
+    let loc = locale!("en");
+    start_at(&mut context, "cycle");
+    let err = context.next_run(&loc).unwrap_err();
+    assert!(err.to_string().contains("cycle"));
+
+    start_at(&mut context, "deep");
+    let err = loop {
+        match context.next_run(&loc) {
+            Ok(raw_ctx) => assert!(raw_ctx.unwrap().call_stack.len() <= MAX_CALL_DEPTH),
+            Err(e) => break e,
+        }
+    };
+    assert!(err.to_string().contains("deeper"));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn call_empty() {
+    let mut context = common::open(CONFIG_PATH).await;
+    start_at(&mut context, "empty_call");
+    let actions = texts(&mut context);
+    // The call line is skipped without a frame.
+    assert_eq!(actions.len(), 1);
+    assert_eq!(actions[0].0, "e");
+    assert!(actions[0].1.call_stack.is_empty());
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use ayaka_model::*;
+
+const CONFIG_PATH: &str = "tests/commands/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn ordered_commands() {
+    let mut context = common::open(CONFIG_PATH).await;
+    context.set_start_context();
+    let raw_ctx = std::iter::from_fn(|| context.next_run(&locale!("en")).unwrap())
+        .last()
+        .unwrap();
+    assert_eq!(raw_ctx.locals["before"], RawValue::Num(0));
+    assert_eq!(raw_ctx.locals["after"], RawValue::Num(1));
+    assert_eq!(
+        raw_ctx.locals["ch_models"],
+        RawValue::List(vec![RawValue::Str("alice".to_string())])
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn scoped_props() {
+    // Stores the keys of the props.
+    fn keys(cmd: &str, ctx: LineProcessContext) -> anyhow::Result<LineProcessResult> {
+        let mut keys = ctx.props.into_keys().map(RawValue::Str).collect::<Vec<_>>();
+        keys.sort();
+        Ok(LineProcessResult {
+            locals: VarMap::from([(cmd.to_string(), RawValue::List(keys))]),
+            ..Default::default()
+        })
+    }
+
+    let plugin = common::native_plugin(
+        PluginType::builder()
+            .line(["first", "second", "legacy"])
+            .line_props("first", ["x"])
+            .line_props("second", ["x", "y"])
+            .build(),
+    )
+    .export("first", |(ctx,): (LineProcessContext,)| keys("first", ctx))
+    .export("second", |(ctx,): (LineProcessContext,)| {
+        keys("second", ctx)
+    })
+    .export("legacy", |(ctx,): (LineProcessContext,)| {
+        keys("legacy", ctx)
+    });
+    let mut context = common::open_with(
+        common::native_builder("props", plugin),
+        "tests/props/config.yaml",
+    )
+    .await;
+    context.set_start_context();
+    let raw_ctx = std::iter::from_fn(|| context.next_run(&locale!("en")).unwrap())
+        .last()
+        .unwrap();
+    let str_list = |keys: &[&str]| {
+        RawValue::List(
+            keys.iter()
+                .map(|key| RawValue::Str(key.to_string()))
+                .collect(),
+        )
+    };
+    assert_eq!(raw_ctx.locals["first"], str_list(&["first", "x"]));
+    assert_eq!(raw_ctx.locals["second"], str_list(&["second", "x", "y"]));
+    // The commands declaring no props get all the keys, as before.
+    assert_eq!(raw_ctx.locals["legacy"], str_list(&["legacy", "typo", "x"]));
+}
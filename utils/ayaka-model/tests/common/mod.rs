@@ -0,0 +1,45 @@
+//! The fixtures shared by the integration tests.
+
+#![allow(dead_code)]
+
+use ayaka_model::*;
+use ayaka_plugin_native::{NativeLinker, NativeModule, NativePlugin};
+use ayaka_plugin_wasmi::{WasmiLinker, WasmiModule};
+
+/// Creates a text action of the chars.
+pub fn text_chars(s: impl Into<String>) -> Action {
+    let mut text = ActionText::default();
+    text.push_back_chars(s.into());
+    Action::Text(text)
+}
+
+/// Creates a builder running the example plugins with Wasmi.
+pub fn builder() -> ContextBuilder<WasmiModule> {
+    let linker = WasmiLinker::new(LinkerConfig::default()).unwrap();
+    ContextBuilder::new(FrontendType::Text, linker)
+}
+
+/// Creates a builder running the native plugin only.
+pub fn native_builder(name: &str, plugin: NativePlugin) -> ContextBuilder<NativeModule> {
+    let mut linker = NativeLinker::new(LinkerConfig::default()).unwrap();
+    linker.register(name, plugin);
+    ContextBuilder::new(FrontendType::Text, linker)
+}
+
+/// Creates a native plugin of the type.
+pub fn native_plugin(plugin_type: PluginType) -> NativePlugin {
+    NativePlugin::new().export("plugin_type", move |()| Ok(plugin_type.clone()))
+}
+
+/// Opens the config with the builder.
+pub async fn open_with<M: RawModule + Send + Sync + 'static>(
+    builder: ContextBuilder<M>,
+    path: &str,
+) -> Context<M> {
+    builder.with_paths(&[path]).unwrap().open().await.unwrap()
+}
+
+/// Opens the config with the example plugins.
+pub async fn open(path: &str) -> Context<WasmiModule> {
+    open_with(builder(), path).await
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+
+const CONFIG_PATH: &str = "tests/fallback/config.yaml";
+
+fn texts(context: &mut Context<WasmiModule>, loc: &Locale) -> Vec<String> {
+    context.set_start_context();
+    let mut texts = vec![];
+    while let Some(ctx) = context.next_run(loc).unwrap() {
+        match context.get_action(loc, &ctx).unwrap() {
+            Action::Text(action) => texts.push(action.text.iter().map(|s| s.as_str()).collect()),
+            action => panic!("unexpected action: {:?}", action),
+        }
+    }
+    texts
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn chain() {
+    let context = common::open(CONFIG_PATH).await;
+    let game = context.game();
+    assert_eq!(
+        game.fallback_chain(&locale!("zh-TW"), &game.paras),
+        [&locale!("zh-Hant"), &locale!("zh-Hans"), &locale!("ja")]
+    );
+    assert_eq!(
+        game.fallback_chain(&locale!("zh-TW"), &game.res),
+        [&locale!("zh-Hans"), &locale!("ja")]
+    );
+    assert_eq!(
+        game.fallback_chain(&locale!("en-GB"), &game.paras),
+        [&locale!("en-001"), &locale!("en"), &locale!("ja")]
+    );
+    assert_eq!(
+        game.fallback_chain(&locale!("ja"), &game.paras),
+        [&locale!("ja")]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn configured() {
+    let mut context = common::open(CONFIG_PATH).await;
+    let loc = locale!("zh-TW");
+    assert_eq!(
+        texts(&mut context, &loc),
+        ["第一行（繁）", "第二行", "三行目", "你好"]
+    );
+    context.set_start_context();
+    assert_eq!(
+        context.current_paragraph_title(&loc).map(|s| s.as_str()),
+        Some("开始")
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn parents() {
+    let mut context = common::open(CONFIG_PATH).await;
+    assert_eq!(
+        texts(&mut context, &locale!("en-GB")),
+        ["Line one, international", "Line two", "Line three", "Hello"]
+    );
+    assert_eq!(
+        texts(&mut context, &locale!("en-US")),
+        ["一行目", "Line two", "Line three", "Hello"]
+    );
+}
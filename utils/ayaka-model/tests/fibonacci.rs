@@ -1,15 +1,11 @@
+mod common;
+
 use ayaka_model::*;
-use ayaka_plugin_wasmi::{WasmiLinker, WasmiModule};
+use common::text_chars;
 use std::collections::HashMap;
 
 const CONFIG_PATH: &str = "tests/fibonacci/config.yaml";
 
-fn text_chars(s: impl Into<String>) -> Action {
-    let mut text = ActionText::default();
-    text.push_back_chars(s.into());
-    Action::Text(text)
-}
-
 fn custom(c: i64) -> Action {
     Action::Custom(HashMap::from([("c".to_string(), RawValue::Num(c))]))
 }
@@ -20,17 +16,11 @@ fn custom_default() -> Action {
 
 #[tokio::test(flavor = "current_thread")]
 async fn calculate() {
-    let linker = WasmiLinker::new(()).unwrap();
-    let mut context = ContextBuilder::<WasmiModule>::new(FrontendType::Text, linker)
-        .with_paths(&[CONFIG_PATH])
-        .unwrap()
-        .open()
-        .await
-        .unwrap();
+    let mut context = common::open(CONFIG_PATH).await;
     context.set_start_context();
     let loc = locale!("en");
     let actions = std::iter::from_fn(|| {
-        let raw_ctx = context.next_run();
+        let raw_ctx = context.next_run(&loc).unwrap();
         raw_ctx.map(|raw_ctx| context.get_action(&loc, &raw_ctx).unwrap())
     })
     .collect::<Vec<_>>();
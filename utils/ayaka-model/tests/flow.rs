@@ -0,0 +1,40 @@
+mod common;
+
+use ayaka_model::*;
+use common::text_chars;
+
+const CONFIG_PATH: &str = "tests/flow/config.yaml";
+
+fn flow_result(flow: FlowDirective) -> anyhow::Result<LineProcessResult> {
+    Ok(LineProcessResult {
+        flow: Some(flow),
+        ..Default::default()
+    })
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn flow() {
+    let plugin = common::native_plugin(PluginType::builder().line(["jump", "skip", "end"]).build())
+        .export("jump", |(ctx,): (LineProcessContext,)| {
+            flow_result(FlowDirective::Jump(
+                ctx.props["jump"].get_str().into_owned(),
+            ))
+        })
+        .export("skip", |(ctx,): (LineProcessContext,)| {
+            flow_result(FlowDirective::Skip(ctx.props["skip"].get_num() as usize))
+        })
+        .export("end", |(_ctx,): (LineProcessContext,)| {
+            flow_result(FlowDirective::EndParagraph)
+        });
+    let mut context = common::open_with(common::native_builder("flow", plugin), CONFIG_PATH).await;
+    context.set_start_context();
+    let loc = locale!("en");
+    let mut actions = vec![];
+    while let Some(raw_ctx) = context.next_run(&loc).unwrap() {
+        let action = context.get_action(&loc, &raw_ctx).unwrap();
+        if let Action::Text(_) = &action {
+            actions.push(action);
+        }
+    }
+    assert_eq!(actions, ["a", "d", "g", "f"].map(text_chars));
+}
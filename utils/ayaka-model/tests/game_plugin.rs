@@ -0,0 +1,71 @@
+mod common;
+
+use ayaka_model::*;
+use common::text_chars;
+use std::collections::HashMap;
+
+const CONFIG_PATH: &str = "tests/game_plugin/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn contribute() {
+    let plugin = common::native_plugin(PluginType::builder().game().build()).export(
+        "process_game",
+        |(ctx,): (GameProcessContext,)| {
+            Ok(GameProcessResult {
+                props: ctx.props,
+                paras: HashMap::from([(
+                    "en".to_string(),
+                    HashMap::from([(
+                        "daily".to_string(),
+                        "- tag: daily\n  texts:\n    - \\res{greeting} \\res{event}\n".to_string(),
+                    )]),
+                )]),
+                res: HashMap::from([(
+                    "en".to_string(),
+                    VarMap::from([
+                        (
+                            "greeting".to_string(),
+                            RawValue::Str("Hello from the plugin!".to_string()),
+                        ),
+                        ("event".to_string(), RawValue::Str("Rainy day.".to_string())),
+                    ]),
+                )]),
+                files: HashMap::from([("/daily/events.txt".to_string(), b"Rainy day.".to_vec())]),
+            })
+        },
+    );
+    let mut context = common::open_with(common::native_builder("daily", plugin), CONFIG_PATH).await;
+
+    let mut events = String::new();
+    context
+        .root_path()
+        .join("daily/events.txt")
+        .unwrap()
+        .open_file()
+        .unwrap()
+        .read_to_string(&mut events)
+        .unwrap();
+    assert_eq!(events, "Rainy day.");
+    // The game files are not shadowed.
+    assert!(context
+        .root_path()
+        .join("config.yaml")
+        .unwrap()
+        .exists()
+        .unwrap());
+
+    context.set_start_context();
+    let loc = locale!("en");
+    let mut actions = vec![];
+    while let Some(raw_ctx) = context.next_run(&loc).unwrap() {
+        actions.push(context.get_action(&loc, &raw_ctx).unwrap());
+    }
+    // The resources of the game take precedence.
+    assert_eq!(
+        actions,
+        [
+            text_chars("Hello from the game!"),
+            text_chars("Hello from the game! Rainy day."),
+        ]
+    );
+}
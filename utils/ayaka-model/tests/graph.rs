@@ -0,0 +1,71 @@
+mod common;
+
+use ayaka_model::*;
+
+const CONFIG_PATH: &str = "tests/graph/config.yaml";
+
+fn edge(
+    from: usize,
+    to: Option<usize>,
+    target: &str,
+    kind: StoryEdgeKind,
+    label: Option<&str>,
+    dynamic: bool,
+) -> StoryEdge {
+    StoryEdge {
+        from,
+        to,
+        target: target.to_string(),
+        kind,
+        label: label.map(|s| s.to_string()),
+        dynamic,
+        undetermined: dynamic && to.is_none(),
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn story_graph() {
+    let context = common::open(CONFIG_PATH).await;
+    let graph = StoryGraph::new(context.game());
+    assert_eq!(
+        graph
+            .nodes
+            .iter()
+            .map(|node| (node.base_para.as_str(), node.para.as_str()))
+            .collect::<Vec<_>>(),
+        [
+            ("init", "init"),
+            ("init", "para0"),
+            ("init", "para1"),
+            ("init", "done"),
+            ("menu", "menu"),
+        ]
+    );
+    assert_eq!(graph.nodes[1].title.as_deref(), Some("Para 0"));
+    assert_eq!(graph.start, Some(0));
+    assert_eq!(
+        graph.edges,
+        [
+            edge(0, Some(1), "para0", StoryEdgeKind::Switch, Some("a"), false),
+            edge(0, Some(2), "para1", StoryEdgeKind::Switch, Some("b"), false),
+            edge(1, Some(4), "menu", StoryEdgeKind::Call, None, false),
+            edge(1, Some(3), "done", StoryEdgeKind::Next, None, true),
+            edge(1, Some(2), "para1", StoryEdgeKind::Next, None, true),
+            edge(2, Some(4), "menu", StoryEdgeKind::Call, None, false),
+            edge(2, None, "missing", StoryEdgeKind::Next, None, false),
+            edge(3, None, "\\var{unknown}", StoryEdgeKind::Next, None, true),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn shared_assignments() {
+    let context = common::open("tests/assigned/config.yaml").await;
+    // The assignment nested in a list is seen by both the graph and the linter.
+    let graph = StoryGraph::new(context.game());
+    assert_eq!(
+        graph.edges,
+        [edge(0, Some(1), "end", StoryEdgeKind::Next, None, true)]
+    );
+    assert_eq!(context.lint(), []);
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_native::NativeModule;
+use std::sync::{Arc, Mutex};
+
+const CONFIG_PATH: &str = "tests/hooks/config.yaml";
+
+async fn open(events: Arc<Mutex<Vec<String>>>) -> Context<NativeModule> {
+    let paragraph_events = events.clone();
+    let switch_events = events;
+    let plugin = common::native_plugin(PluginType::builder().paragraph().switch().record().build())
+        .export(
+            "process_paragraph",
+            move |(ctx,): (ParagraphProcessContext,)| {
+                paragraph_events
+                    .lock()
+                    .unwrap()
+                    .push(format!("{:?} {}", ctx.event, ctx.ctx.cur_para));
+                Ok(ParagraphProcessResult::default())
+            },
+        )
+        .export("process_switch", move |(ctx,): (SwitchProcessContext,)| {
+            switch_events
+                .lock()
+                .unwrap()
+                .push(format!("Switch {}", ctx.index));
+            Ok(SwitchProcessResult {
+                locals: VarMap::from([("choice".to_string(), RawValue::Num(ctx.index as i64))]),
+            })
+        })
+        .export("process_record", |(ctx,): (RecordProcessContext,)| {
+            Ok(RecordProcessResult {
+                locals: VarMap::from([(
+                    "record".to_string(),
+                    RawValue::Str(format!("{:?}", ctx.event)),
+                )]),
+            })
+        });
+    common::open_with(common::native_builder("hooks", plugin), CONFIG_PATH).await
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn hooks() {
+    let events = Arc::new(Mutex::new(vec![]));
+    let mut context = open(events.clone()).await;
+    context.set_start_context();
+    let loc = locale!("en");
+    let mut last_ctx = None;
+    while let Some(raw_ctx) = context.next_run(&loc).unwrap() {
+        if let Action::Switches(_) = context.get_action(&loc, &raw_ctx).unwrap() {
+            context.switch(1);
+        }
+        last_ctx = Some(raw_ctx);
+    }
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "Enter init",
+            "Leave init",
+            "Enter choose",
+            "Switch 1",
+            "Leave choose",
+            "Enter second",
+            "Leave second",
+        ]
+    );
+    let mut ctx = last_ctx.unwrap();
+    assert_eq!(ctx.locals["choice"], RawValue::Num(1));
+
+    context.process_record(RecordEvent::Save, &mut ctx);
+    assert_eq!(ctx.locals["record"], RawValue::Str("Save".to_string()));
+    context.process_record(RecordEvent::Load, &mut ctx);
+    assert_eq!(ctx.locals["record"], RawValue::Str("Load".to_string()));
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use ayaka_model::*;
+
+const CONFIG_PATH: &str = "tests/line_id/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn line_id() {
+    let mut context = common::open(CONFIG_PATH).await;
+    context.set_start_context();
+    let ctxs = std::iter::from_fn(|| context.next_run(&locale!("en")).unwrap()).collect::<Vec<_>>();
+    assert_eq!(
+        ctxs.iter().map(|ctx| ctx.line_key()).collect::<Vec<_>>(),
+        ["0", "hello", "2"]
+    );
+
+    // The lines know where they are loaded from.
+    let para = context
+        .game()
+        .find_para(&locale!("en"), "init", "init")
+        .unwrap();
+    assert_eq!(para.texts[1].source, SourceSpan::line("init", "init", 1));
+
+    // The line was at another index when saved.
+    let mut ctx = RawContext {
+        cur_act: 5,
+        ..ctxs[1].clone()
+    };
+    context.game().relocate(&mut ctx);
+    assert_eq!(ctx.cur_act, 1);
+
+    // The return points are relocated as well.
+    let mut caller = RawContext {
+        call_stack: vec![CallFrame {
+            cur_base_para: "init".to_string(),
+            cur_para: "init".to_string(),
+            cur_act: 5,
+            cur_id: Some("hello".to_string()),
+        }],
+        ..ctxs[0].clone()
+    };
+    context.game().relocate(&mut caller);
+    assert_eq!(caller.cur_act, 0);
+    assert_eq!(caller.call_stack[0].cur_act, 1);
+
+    let mut record = GlobalRecord::default();
+    record.update(&ctx);
+    assert!(record.visited(&RawContext {
+        cur_act: 3,
+        ..ctx.clone()
+    }));
+    assert!(!record.visited(&RawContext {
+        cur_act: 0,
+        cur_id: Some("bye".to_string()),
+        ..ctx.clone()
+    }));
+    assert!(record.visited(&ctxs[0]));
+    assert!(!record.visited(&ctxs[2]));
+
+    // The translated lines are matched by the identifiers.
+    // The lines without identifiers are still matched by the indices.
+    let texts = ctxs
+        .iter()
+        .map(
+            |ctx| match context.get_action(&locale!("ja"), ctx).unwrap() {
+                Action::Text(text) => text.to_string(),
+                _ => unreachable!(),
+            },
+        )
+        .collect::<Vec<_>>();
+    assert_eq!(texts, ["a", "Konnichiwa", "c"]);
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use ayaka_model::*;
+
+const CONFIG_PATH: &str = "tests/lint/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn lint() {
+    let context = common::open(CONFIG_PATH).await;
+    let diagnostics = context.lint();
+    let severities = diagnostics
+        .iter()
+        .map(|diag| diag.severity)
+        .collect::<Vec<_>>();
+    let diagnostics = diagnostics
+        .into_iter()
+        .map(|diag| {
+            (
+                diag.kind,
+                diag.locale.map(|loc| loc.to_string()),
+                diag.para,
+                diag.line,
+            )
+        })
+        .collect::<Vec<_>>();
+    let en = Some("en".to_string());
+    let ja = Some("ja".to_string());
+    let init = "init".to_string();
+    assert_eq!(
+        diagnostics,
+        [
+            (LintKind::MissingNext, None, init.clone(), None),
+            (
+                LintKind::UnreachableParagraph,
+                None,
+                "lonely".to_string(),
+                None
+            ),
+            (LintKind::UnassignedVariable, None, init.clone(), Some(1)),
+            (LintKind::MissingResource, en, init.clone(), Some(1)),
+            (LintKind::UnknownTextCommand, None, init.clone(), Some(3)),
+            (LintKind::UnknownLineCommand, None, init.clone(), Some(4)),
+            // The line 6 is fine, because `exec` declares no props and accepts `typo`.
+            (LintKind::MissingResource, ja.clone(), init.clone(), Some(2)),
+            (LintKind::SwitchMismatch, ja, init, Some(5)),
+        ]
+    );
+    use LintSeverity::*;
+    assert_eq!(
+        severities,
+        [Error, Warning, Warning, Error, Error, Error, Warning, Error]
+    );
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use ayaka_model::*;
+
+const CONFIG_PATH: &str = "tests/locale/config.yaml";
+
+#[tokio::test(flavor = "current_thread")]
+async fn locale() {
+    let plugin = common::native_plugin(
+        PluginType::builder()
+            .line(["lang"])
+            .text(["loc"])
+            .action()
+            .build(),
+    )
+    .export("lang", |(ctx,): (LineProcessContext,)| {
+        Ok(LineProcessResult {
+            locals: VarMap::from([("lang".to_string(), RawValue::Str(ctx.locale.to_string()))]),
+            ..Default::default()
+        })
+    })
+    .export("loc", |(_args, ctx): (Vec<String>, TextProcessContext)| {
+        let mut res = TextProcessResult::default();
+        res.text.push_back_chars(format!(
+            "{} {}",
+            ctx.locale,
+            ctx.ctx.locals["lang"].get_str()
+        ));
+        Ok(res)
+    })
+    .export("process_action", |(mut ctx,): (ActionProcessContext,)| {
+        ctx.action.push_back_chars(format!(" [{}]", ctx.locale));
+        Ok(ActionProcessResult { action: ctx.action })
+    });
+    let mut context =
+        common::open_with(common::native_builder("locale", plugin), CONFIG_PATH).await;
+    context.set_start_context();
+    let raw_ctx = std::iter::from_fn(|| context.next_run(&locale!("ja")).unwrap())
+        .last()
+        .unwrap();
+    assert_eq!(raw_ctx.locals["lang"], RawValue::Str("ja".to_string()));
+    let mut text = ActionText::default();
+    text.push_back_chars("zh ja [zh]");
+    assert_eq!(
+        context.get_action(&locale!("zh"), &raw_ctx).unwrap(),
+        Action::Text(text)
+    );
+}
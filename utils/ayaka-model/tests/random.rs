@@ -0,0 +1,90 @@
+mod common;
+
+use ayaka_model::*;
+use ayaka_plugin_wasmi::WasmiModule;
+
+const CONFIG_PATH: &str = "tests/random/config.yaml";
+
+async fn open(seed: u64) -> Context<WasmiModule> {
+    common::open_with(common::builder().with_seed(seed), CONFIG_PATH).await
+}
+
+fn run(context: &mut Context<WasmiModule>) -> (Vec<RawContext>, Vec<String>) {
+    let mut ctxs = vec![];
+    let mut texts = vec![];
+    let loc = locale!("en");
+    while let Some(ctx) = context.next_run(&loc).unwrap() {
+        if let Action::Text(action) = context.get_action(&loc, &ctx).unwrap() {
+            texts.push(action.text.iter().map(|s| s.as_str()).collect());
+        }
+        ctxs.push(ctx);
+    }
+    (ctxs, texts)
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn seed() {
+    let mut context = open(114514).await;
+    context.set_start_context();
+    let (_, texts) = run(&mut context);
+    assert_eq!(texts.len(), 3);
+
+    let mut context = open(114514).await;
+    context.set_start_context();
+    assert_eq!(run(&mut context).1, texts);
+
+    let mut context = open(1919810).await;
+    context.set_start_context();
+    assert_ne!(run(&mut context).1, texts);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn load() {
+    let mut context = open(114514).await;
+    context.set_start_context();
+    let (ctxs, texts) = run(&mut context);
+
+    // Save the record after the first text, and load it in a new context.
+    let record = ActionRecord {
+        history: ctxs[..2].to_vec(),
+    };
+    let record: ActionRecord =
+        serde_json::from_str(&serde_json::to_string(&record).unwrap()).unwrap();
+    let mut context = open(0).await;
+    let mut ctx = record.last_ctx().unwrap().clone();
+    ctx.cur_act += 1;
+    context.set_context(ctx);
+    assert_eq!(run(&mut context).1, texts[1..]);
+
+    // The history is rendered the same.
+    assert_eq!(
+        context.get_action(&locale!("en"), &ctxs[1]).unwrap(),
+        context.get_action(&locale!("en"), &ctxs[1]).unwrap()
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn scoped() {
+    let mut context = open(114514).await;
+    context.set_start_context();
+    let (_, texts) = run(&mut context);
+
+    // The plugins called by other methods don't change the outcomes of the story.
+    let mut context = open(114514).await;
+    context.set_start_context();
+    let loc = locale!("en");
+    let mut scoped_texts = vec![];
+    let mut last = None;
+    while let Some(ctx) = context.next_run(&loc).unwrap() {
+        let value = context.eval("random.rnd(1000000)").unwrap();
+        assert_eq!(context.eval("random.rnd(1000000)").unwrap(), value);
+        if let Some(last) = &last {
+            context.get_action(&loc, last).unwrap();
+        }
+        if let Action::Text(action) = context.get_action(&loc, &ctx).unwrap() {
+            scoped_texts.push(action.text.iter().map(|s| s.as_str()).collect::<String>());
+        }
+        last = Some(ctx);
+    }
+    assert_eq!(scoped_texts, texts);
+}
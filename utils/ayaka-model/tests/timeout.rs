@@ -0,0 +1,73 @@
+mod common;
+
+use ayaka_model::*;
+
+const CONFIG_PATH: &str = "tests/timeout/config.yaml";
+
+fn switches(items: &[(&str, bool)], timeout: Option<u64>, default: Option<usize>) -> Action {
+    Action::Switches(ActionSwitches {
+        switches: items
+            .iter()
+            .map(|(text, enabled)| Switch {
+                text: text.to_string(),
+                enabled: *enabled,
+            })
+            .collect(),
+        timeout,
+        default,
+    })
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn timed_lines() {
+    let mut context = common::open(CONFIG_PATH).await;
+    context.set_start_context();
+    let loc = locale!("en");
+
+    let raw_ctx = context.next_run(&loc).unwrap().unwrap();
+    let mut text = ActionText::default();
+    text.push_back_chars("Hurry!");
+    text.timeout = Some(3000);
+    assert_eq!(
+        context.get_action(&loc, &raw_ctx).unwrap(),
+        Action::Text(text)
+    );
+    // The timed text is advanced by `next_run`.
+    assert_eq!(context.timeout(), None);
+
+    let raw_ctx = context.next_run(&loc).unwrap().unwrap();
+    assert_eq!(
+        context.get_action(&loc, &raw_ctx).unwrap(),
+        switches(
+            &[("left", true), ("middle", true), ("right", true)],
+            Some(5000),
+            Some(1)
+        )
+    );
+    assert_eq!(context.timeout(), Some(1));
+    assert_eq!(context.timeout(), None);
+
+    context.next_run(&loc).unwrap().unwrap();
+
+    // The first enabled switch is chosen if the default one is disabled.
+    let disabled_ctx = context.next_run(&loc).unwrap().unwrap();
+    let disabled = switches(
+        &[("left", true), ("middle", false), ("right", true)],
+        Some(5000),
+        Some(0),
+    );
+    assert_eq!(context.get_action(&loc, &disabled_ctx).unwrap(), disabled);
+    assert_eq!(context.timeout(), Some(0));
+
+    let raw_ctx = context.next_run(&loc).unwrap().unwrap();
+    assert_eq!(
+        context.get_action(&loc, &raw_ctx).unwrap(),
+        switches(&[("left", true), ("right", true)], None, None)
+    );
+    assert_eq!(context.timeout(), None);
+    context.switch(1);
+    // The action is determined by the context, e.g., in the history.
+    assert_eq!(context.get_action(&loc, &disabled_ctx).unwrap(), disabled);
+
+    assert!(context.next_run(&loc).unwrap().is_none());
+}
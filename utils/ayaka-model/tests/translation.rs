@@ -0,0 +1,105 @@
+mod common;
+
+use ayaka_model::*;
+use std::collections::HashMap;
+
+const CONFIG_PATH: &str = "tests/translation/config.yaml";
+
+fn missing(para: &str, line: Option<usize>) -> MissingTranslation {
+    MissingTranslation {
+        base_para: "init".to_string(),
+        para: para.to_string(),
+        line,
+    }
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn coverage() {
+    let context = common::open(CONFIG_PATH).await;
+    let coverage = context
+        .game()
+        .translation_coverage(&"ja".parse::<Locale>().unwrap());
+    assert_eq!(coverage.total_lines, 5);
+    assert_eq!(coverage.translated_lines, 2);
+    assert_eq!(coverage.missing_paras, [missing("extra", None)]);
+    assert_eq!(
+        coverage.empty_lines,
+        [missing("init", Some(1)), missing("init", Some(3))]
+    );
+    assert_eq!(coverage.total_res, 2);
+    assert_eq!(coverage.missing_res, ["farewell"]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn units() {
+    let context = common::open(CONFIG_PATH).await;
+    let units = context
+        .game()
+        .translation_units(&"ja".parse::<Locale>().unwrap())
+        .into_iter()
+        .map(|unit| (unit.key, unit.source, unit.target))
+        .collect::<Vec<_>>();
+    let unit = |key: &str, source: &str, target: Option<&str>| {
+        (
+            key.to_string(),
+            source.to_string(),
+            target.map(|s| s.to_string()),
+        )
+    };
+    assert_eq!(
+        units,
+        [
+            unit("title/init/init", "Start", Some("開始")),
+            unit("para/init/init/0", "Hello!", Some("こんにちは！")),
+            unit("para/init/init/choose", "Choose one.", None),
+            unit("para/init/init/2/0", "Left", Some("左")),
+            unit("para/init/init/2/1", "Right", Some("右")),
+            unit("para/init/init/3", "The end.", None),
+            unit("para/init/extra/0", "Extra line.", None),
+            unit("res/farewell", "Goodbye", None),
+            unit("res/greeting", "Hello", Some("こんにちは")),
+        ]
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn apply() {
+    let context = common::open(CONFIG_PATH).await;
+    let translations = [
+        ("title/init/init", "Début"),
+        ("para/init/init/choose", "Choisissez."),
+        // Incomplete switches are not translated.
+        ("para/init/init/2/0", "Gauche"),
+        ("res/greeting", "Bonjour"),
+        ("res/unknown", "Inconnu"),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect::<HashMap<_, _>>();
+    let translation = context.game().apply_translation(&translations).unwrap();
+    assert_eq!(
+        translation.paras.get("init").unwrap(),
+        &[TranslatedParagraph {
+            tag: "init".to_string(),
+            title: Some("Début".to_string()),
+            // The line identifiers are kept, so that the lines are matched by them.
+            texts: vec![
+                None,
+                Some(TranslatedLine::IdText {
+                    id: "choose".to_string(),
+                    text: "Choisissez.".to_string()
+                })
+            ],
+        }]
+    );
+    assert_eq!(translation.paras.len(), 1);
+    assert_eq!(
+        translation.res.into_iter().collect::<Vec<_>>(),
+        [("greeting".to_string(), "Bonjour".to_string())]
+    );
+
+    let invalid = [("para/init/init/0".to_string(), "\\cmd{".to_string())]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    assert!(context.game().apply_translation(&invalid).is_err());
+}
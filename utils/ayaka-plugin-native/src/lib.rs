@@ -0,0 +1,234 @@
+//! Native plugin backend.
+//!
+//! The plugins are written in Rust and run in the same process,
+//! without a WASM toolchain.
+//!
+//! ```ignore
+//! use ayaka_plugin_native::*;
+//!
+//! let mut linker = NativeLinker::new(LinkerConfig::default())?;
+//! linker.register(
+//!     "hello",
+//!     NativePlugin::new()
+//!         .export("plugin_type", |()| Ok(PluginType::builder().line(["hello"]).build()))
+//!         .export("hello", |(_ctx,): (LineProcessContext,)| {
+//!             Ok(LineProcessResult::default())
+//!         }),
+//! );
+//! ```
+//!
+//! The args of the exported methods are bounded by [`Tuple`],
+//! so this crate requires a nightly toolchain with `tuple_trait`,
+//! the same as `ayaka-plugin` and the repository `rust-toolchain`.
+
+#![feature(tuple_trait)]
+#![warn(missing_docs)]
+#![deny(unsafe_code)]
+
+use ayaka_bindings_types::{PluginError, ABI_VERSION};
+use ayaka_plugin::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    any::Any, collections::HashMap, convert::Infallible, marker::PhantomData, marker::Tuple,
+    sync::Arc,
+};
+
+/// The args or returns of a native method.
+pub type NativeValue = Box<dyn Any + Send>;
+
+type NativeMethod = Arc<dyn (Fn(NativeValue) -> Result<NativeValue>) + Send + Sync + 'static>;
+
+/// A native plugin, with the exported methods.
+///
+/// The exported methods follow the same contract as the WASM plugins,
+/// e.g., `plugin_type`, `process_action` and the custom commands.
+/// The args and returns are passed as values, without serializing.
+#[derive(Clone)]
+pub struct NativePlugin {
+    methods: HashMap<String, NativeMethod>,
+}
+
+impl NativePlugin {
+    /// Creates a plugin, only exporting the ABI version.
+    pub fn new() -> Self {
+        Self {
+            methods: HashMap::default(),
+        }
+        .export_any(ABI_VERSION_NAME, |_| Ok(Box::new(ABI_VERSION)))
+    }
+
+    /// Exports a method with args and returns as [`NativeValue`].
+    ///
+    /// The returns should be a `Result<T, PluginError>`,
+    /// the same as the WASM plugins.
+    pub fn export_any(
+        mut self,
+        name: impl Into<String>,
+        f: impl (Fn(NativeValue) -> Result<NativeValue>) + Send + Sync + 'static,
+    ) -> Self {
+        self.methods.insert(name.into(), Arc::new(f));
+        self
+    }
+
+    /// Exports a method with args.
+    ///
+    /// The args are a tuple of the owned types, the same as the WASM plugins,
+    /// e.g., `(Vec<String>, TextProcessContext)` for a text command.
+    /// The errors are returned to the runtime as [`PluginError`].
+    pub fn export<P: Tuple + 'static, R: Send + 'static>(
+        self,
+        name: impl Into<String>,
+        f: impl (Fn(P) -> Result<R>) + Send + Sync + 'static,
+    ) -> Self {
+        self.export_any(name, move |args| {
+            let res = args
+                .downcast::<P>()
+                .map_err(|_| anyhow!("mismatched input args"))
+                .and_then(|args| f(*args))
+                .map_err(|e| match e.downcast::<PluginError>() {
+                    Ok(e) => e,
+                    Err(e) => PluginError::new(format!("{:#}", e)),
+                });
+            Ok(Box::new(res))
+        })
+    }
+}
+
+impl Default for NativePlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A native module, created from a registered [`NativePlugin`].
+pub struct NativeModule {
+    plugin: NativePlugin,
+}
+
+impl NativeModule {
+    fn method(&self, name: &str) -> Result<&NativeMethod> {
+        self.plugin
+            .methods
+            .get(name)
+            .ok_or_else(|| anyhow!("Method \"{}\" is not exported", name))
+    }
+}
+
+impl RawModule for NativeModule {
+    type Linker = NativeLinker;
+
+    type LinkerHandle<'a> = NativeLinkerHandle<'a>;
+
+    type Func = ();
+
+    fn call<T>(&self, name: &str, _args: &[u8], _f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
+        self.method(name)?;
+        Err(anyhow!(
+            "Method \"{}\" of a native module cannot be called with bytes",
+            name
+        ))
+    }
+
+    fn call_value<P: Serialize, O: Send + 'static, R: DeserializeOwned + 'static>(
+        &self,
+        name: &str,
+        args: P,
+        owned: impl FnOnce(P) -> O,
+        _limits: &ModuleLimits,
+    ) -> Result<R> {
+        let res = self.method(name)?(Box::new(owned(args)))?;
+        let res = res
+            .downcast::<R>()
+            .map_err(|_| anyhow!("Method \"{}\" returns a mismatched type", name))?;
+        Ok(*res)
+    }
+}
+
+/// The linker of the native plugins.
+///
+/// The modules are created from the registered plugins by names.
+/// The native plugins call the host crates directly,
+/// so the imported functions are ignored,
+/// and the fuel in [`LinkerConfig`] and the limits in [`ModuleLimits`] are not supported.
+#[derive(Default)]
+pub struct NativeLinker {
+    plugins: HashMap<String, NativePlugin>,
+}
+
+impl NativeLinker {
+    /// Registers a plugin by name.
+    ///
+    /// It replaces the registered one with the same name.
+    pub fn register(&mut self, name: impl Into<String>, plugin: NativePlugin) {
+        self.plugins.insert(name.into(), plugin);
+    }
+}
+
+impl Linker<NativeModule> for NativeLinker {
+    type Config = LinkerConfig;
+
+    fn new(_config: LinkerConfig) -> Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn create_with(&self, _binary: &[u8], _granted: impl Fn(&str) -> bool) -> Result<NativeModule> {
+        Err(anyhow!("The native backend cannot load WASM binaries"))
+    }
+
+    fn registered(&self) -> Vec<String> {
+        let mut names = self.plugins.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    fn create_registered(&self, name: &str) -> Result<NativeModule> {
+        let plugin = self
+            .plugins
+            .get(name)
+            .ok_or_else(|| anyhow!("Module \"{}\" is not registered", name))?
+            .clone();
+        Ok(NativeModule { plugin })
+    }
+
+    fn import_wasi(&mut self, _config: wasi::WasiConfig) -> Result<()> {
+        Ok(())
+    }
+
+    fn import(&mut self, _ns: impl Into<String>, _funcs: HashMap<String, ()>) -> Result<()> {
+        Ok(())
+    }
+
+    fn wrap_raw(
+        &self,
+        _f: impl (Fn(NativeLinkerHandle, i32, i32) -> Result<Vec<u8>>) + Send + Sync + 'static,
+    ) {
+    }
+}
+
+/// The linker handle of the native plugins.
+///
+/// It is never created, because the imported functions are ignored.
+pub struct NativeLinkerHandle<'a> {
+    never: Infallible,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl<'a> LinkerHandle<'a, NativeModule> for NativeLinkerHandle<'a> {
+    fn call<T>(
+        &mut self,
+        _m: &NativeModule,
+        _name: &str,
+        _args: &[u8],
+        _f: impl FnOnce(&[u8]) -> Result<T>,
+    ) -> Result<T> {
+        match self.never {}
+    }
+
+    fn slice<T>(&self, _start: i32, _len: i32, _f: impl FnOnce(&[u8]) -> T) -> T {
+        match self.never {}
+    }
+
+    fn slice_mut<T>(&mut self, _start: i32, _len: i32, _f: impl FnOnce(&mut [u8]) -> T) -> T {
+        match self.never {}
+    }
+}
@@ -2,12 +2,27 @@
 
 #![warn(missing_docs)]
 
-use ayaka_plugin::*;
+mod wasi;
+
+use ayaka_plugin::{wasi::*, *};
 use std::{
     collections::HashMap,
+    ptr::NonNull,
     sync::{Arc, Mutex},
 };
-use wasmer::*;
+use wasmer::{
+    vm::{
+        LinearMemory, MemoryStyle, TableStyle, VMMemory, VMMemoryDefinition, VMTable,
+        VMTableDefinition,
+    },
+    wasmparser::Operator,
+    *,
+};
+use wasmer_middlewares::{
+    metering::{get_remaining_points, set_remaining_points, MeteringPoints},
+    Metering,
+};
+use wasmer_wasix::WasiError;
 
 unsafe fn mem_slice<R>(
     store: &impl AsStoreRef,
@@ -39,18 +54,208 @@ unsafe fn mem_slice_mut<R>(
 
 type HostStore = Arc<Mutex<Store>>;
 
+/// Limits the linear memories created later,
+/// and records the exceeded limit.
+#[derive(Debug, Default)]
+struct MemoryLimiter {
+    limits: ModuleLimits,
+    exceeded: Option<LimitError>,
+}
+
+type HostLimiter = Arc<Mutex<MemoryLimiter>>;
+
+// Runs with the exceeded limit of the caller saved.
+// The growing fails and the module usually traps, so the error is replaced.
+fn with_limiter<T>(limiter: &HostLimiter, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let caller = limiter.lock().unwrap().exceeded.take();
+    let res = f();
+    let exceeded = std::mem::replace(&mut limiter.lock().unwrap().exceeded, caller);
+    match (res, exceeded) {
+        (Err(_), Some(e)) => Err(e.into()),
+        (res, _) => res,
+    }
+}
+
+/// A linear memory limited when it grows.
+#[derive(Debug)]
+struct LimitedMemory {
+    memory: VMMemory,
+    limits: ModuleLimits,
+    limiter: HostLimiter,
+}
+
+impl LinearMemory for LimitedMemory {
+    fn ty(&self) -> MemoryType {
+        self.memory.ty()
+    }
+
+    fn size(&self) -> Pages {
+        self.memory.size()
+    }
+
+    fn style(&self) -> MemoryStyle {
+        self.memory.style()
+    }
+
+    fn grow(&mut self, delta: Pages) -> Result<Pages, MemoryError> {
+        let current = self.memory.size();
+        let desired = Pages(current.0.saturating_add(delta.0));
+        if let Err(e) = self.limits.check_memory(desired.bytes().0) {
+            self.limiter.lock().unwrap().exceeded = Some(e);
+            return Err(MemoryError::CouldNotGrow {
+                current,
+                attempted_delta: delta,
+            });
+        }
+        self.memory.grow(delta)
+    }
+
+    fn vmmemory(&self) -> NonNull<VMMemoryDefinition> {
+        self.memory.vmmemory()
+    }
+
+    fn try_clone(&self) -> Result<Box<dyn LinearMemory + 'static>, MemoryError> {
+        Ok(Box::new(Self {
+            memory: self.memory.try_clone()?.into(),
+            limits: self.limits,
+            limiter: self.limiter.clone(),
+        }))
+    }
+
+    fn copy(&mut self) -> Result<Box<dyn LinearMemory + 'static>, MemoryError> {
+        Ok(Box::new(Self {
+            memory: self.memory.copy()?.into(),
+            limits: self.limits,
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+/// The [`BaseTunables`] creating [`LimitedMemory`].
+struct LimitedTunables {
+    base: BaseTunables,
+    limiter: HostLimiter,
+}
+
+impl LimitedTunables {
+    fn limit(
+        &self,
+        ty: &MemoryType,
+        create: impl FnOnce() -> Result<VMMemory, MemoryError>,
+    ) -> Result<VMMemory, MemoryError> {
+        let mut limiter = self.limiter.lock().unwrap();
+        let limits = limiter.limits;
+        if let Err(e) = limits.check_memory(ty.minimum.bytes().0) {
+            limiter.exceeded = Some(e);
+            return Err(MemoryError::Generic(
+                "memory minimum size exceeds the limit".to_string(),
+            ));
+        }
+        drop(limiter);
+        let memory = create()?;
+        Ok(VMMemory(Box::new(LimitedMemory {
+            memory,
+            limits,
+            limiter: self.limiter.clone(),
+        })))
+    }
+}
+
+impl Tunables for LimitedTunables {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        self.base.memory_style(memory)
+    }
+
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.base.table_style(table)
+    }
+
+    fn create_host_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+    ) -> Result<VMMemory, MemoryError> {
+        self.limit(ty, || self.base.create_host_memory(ty, style))
+    }
+
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<VMMemory, MemoryError> {
+        self.limit(ty, || {
+            self.base
+                .create_vm_memory(ty, style, vm_definition_location)
+        })
+    }
+
+    fn create_host_table(&self, ty: &TableType, style: &TableStyle) -> Result<VMTable, String> {
+        self.base.create_host_table(ty, style)
+    }
+
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<VMTable, String> {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
+
+/// The error of a host function, carried by [`RuntimeError`]
+/// to be recovered after the call.
+#[derive(Debug)]
+struct HostCallError(anyhow::Error);
+
+impl std::fmt::Display for HostCallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for HostCallError {}
+
+impl From<HostCallError> for RuntimeError {
+    fn from(e: HostCallError) -> Self {
+        RuntimeError::user(Box::new(e))
+    }
+}
+
+fn host_call_error(e: RuntimeError) -> anyhow::Error {
+    match e.downcast::<HostCallError>() {
+        Ok(HostCallError(e)) => e,
+        Err(e) => match e.downcast::<WasiError>() {
+            Ok(WasiError::Exit(code)) => WasiExit(code.raw()).into(),
+            Ok(e) => e.into(),
+            Err(e) => e.into(),
+        },
+    }
+}
+
 /// A Wasmer [`Instance`].
 pub struct WasmerModule {
     store: HostStore,
+    limiter: HostLimiter,
+    fuel: Option<u64>,
     instance: Instance,
     memory: Memory,
     abi_free: TypedFunction<(i32, i32), ()>,
     abi_alloc: TypedFunction<i32, i32>,
+    // The engine should be dropped after the instance.
+    _engine: Option<Engine>,
 }
 
 impl WasmerModule {
     /// Loads the WASM [`Module`], with some imports.
-    fn new(store: HostStore, instance: Instance) -> Result<Self> {
+    fn new(
+        store: HostStore,
+        limiter: HostLimiter,
+        fuel: Option<u64>,
+        engine: Option<Engine>,
+        instance: Instance,
+    ) -> Result<Self> {
         let memory = instance.exports.get_memory(MEMORY_NAME)?.clone();
         let inner_store = store.lock().unwrap();
         let abi_free = instance
@@ -62,10 +267,13 @@ impl WasmerModule {
         drop(inner_store);
         Ok(Self {
             store,
+            limiter,
+            fuel,
             instance,
             memory,
             abi_free,
             abi_alloc,
+            _engine: engine,
         })
     }
 
@@ -81,6 +289,29 @@ impl WasmerModule {
             .exports
             .get_typed_function::<(i32, i32), u64>(&store, name)?;
 
+        if let Some(fuel) = self.fuel {
+            set_remaining_points(&mut store, &self.instance, fuel);
+        }
+        let res = with_limiter(&self.limiter, || self.call_inner(&mut store, func, data, f));
+        if res.is_err()
+            && self.fuel.is_some()
+            && get_remaining_points(&mut store, &self.instance) == MeteringPoints::Exhausted
+        {
+            return Err(LimitError::FuelExhausted {
+                method: name.to_string(),
+            }
+            .into());
+        }
+        res
+    }
+
+    fn call_inner<T>(
+        &self,
+        mut store: &mut StoreMut,
+        func: TypedFunction<(i32, i32), u64>,
+        data: &[u8],
+        f: impl FnOnce(&[u8]) -> Result<T>,
+    ) -> Result<T> {
         let ptr = self.abi_alloc.call(&mut store, data.len() as i32)?;
         unsafe {
             mem_slice_mut(&store, &self.memory, ptr, data.len() as i32, |s| {
@@ -92,7 +323,7 @@ impl WasmerModule {
 
         self.abi_free.call(&mut store, ptr, data.len() as i32)?;
 
-        let res = res?;
+        let res = res.map_err(host_call_error)?;
         let (len, res) = ((res >> 32) as i32, (res & 0xFFFFFFFF) as i32);
 
         let res_data = unsafe { mem_slice(&store, &self.memory, res, len, |s| f(s)) };
@@ -148,8 +379,7 @@ impl RuntimeInstanceData {
         self.abi_alloc = Some(func);
     }
 
-    pub fn call(mut this: FunctionEnvMut<Self>, len: i32, ptr: i32) -> u64 {
-        // TODO: should we unwrap here?
+    pub fn call(mut this: FunctionEnvMut<Self>, len: i32, ptr: i32) -> Result<u64, RuntimeError> {
         unsafe {
             let memory = this.data().memory().clone();
             let data = {
@@ -158,14 +388,14 @@ impl RuntimeInstanceData {
                     store: this.as_store_mut(),
                     memory: memory.clone(),
                 };
-                (func)(handle, ptr, len).unwrap()
+                (func)(handle, ptr, len).map_err(HostCallError)?
             };
             let abi_alloc = this.data().abi_alloc.clone().unwrap();
-            let ptr = abi_alloc.call(&mut this, data.len() as i32).unwrap();
+            let ptr = abi_alloc.call(&mut this, data.len() as i32)?;
             mem_slice_mut(&this, &memory, ptr, data.len() as i32, |slice| {
                 slice.copy_from_slice(&data);
             });
-            ((data.len() as u64) << 32) | (ptr as u64)
+            Ok(((data.len() as u64) << 32) | (ptr as u64))
         }
     }
 }
@@ -173,10 +403,27 @@ impl RuntimeInstanceData {
 /// A Wasmer [`Store`] with some imports.
 pub struct WasmerLinker {
     store: HostStore,
+    limiter: HostLimiter,
+    fuel: Option<u64>,
     imports: HashMap<String, HashMap<String, WasmerFunction>>,
+    wasi: Option<WasiConfig>,
 }
 
 impl WasmerLinker {
+    // The memories are created by the tunables of the store engine,
+    // so every engine compiling the modules uses the same tunables.
+    fn engine(limiter: &HostLimiter, compiler: Option<Cranelift>) -> Engine {
+        let mut engine = match compiler {
+            Some(compiler) => Engine::from(compiler),
+            None => Engine::default(),
+        };
+        engine.set_tunables(LimitedTunables {
+            base: BaseTunables::for_target(engine.target()),
+            limiter: limiter.clone(),
+        });
+        engine
+    }
+
     fn wrap_impl(
         store: &mut impl AsStoreMut,
         func: WasmerFunction,
@@ -189,20 +436,40 @@ impl WasmerLinker {
 }
 
 impl Linker<WasmerModule> for WasmerLinker {
-    type Config = ();
+    type Config = LinkerConfig;
 
-    fn new(_: ()) -> Result<Self> {
-        let store = Store::default();
+    fn new(config: LinkerConfig) -> Result<Self> {
+        let limiter = HostLimiter::default();
+        let store = Store::new(Self::engine(&limiter, None));
         Ok(Self {
             store: Arc::new(Mutex::new(store)),
+            limiter,
+            fuel: config.fuel,
             imports: HashMap::default(),
+            wasi: None,
         })
     }
 
-    fn create(&self, binary: &[u8]) -> Result<WasmerModule> {
-        let instance = {
+    fn create_with(&self, binary: &[u8], granted: impl Fn(&str) -> bool) -> Result<WasmerModule> {
+        let (instance, engine) = {
             let mut store = self.store.lock().unwrap();
-            let module = Module::from_binary(&store.as_store_ref(), binary)?;
+            // A metering middleware could only be used by one module,
+            // so a new engine is created for each module,
+            // with the same tunables as the store engine instantiating it.
+            let engine = self.fuel.map(|fuel| {
+                let mut compiler = Cranelift::default();
+                compiler.push_middleware(Arc::new(Metering::new(fuel, |_: &Operator| 1)));
+                Self::engine(&self.limiter, Some(compiler))
+            });
+            let module = match &engine {
+                Some(engine) => Module::from_binary(engine, binary)?,
+                None => Module::from_binary(&store.as_store_ref(), binary)?,
+            };
+            let module_imports = module.imports().collect::<Vec<_>>();
+            PermissionError::check(
+                module_imports.iter().map(|i| (i.module(), i.name())),
+                granted,
+            )?;
             let mut imports = Imports::default();
             let mut envs = vec![];
             for (ns, funcs) in &self.imports {
@@ -223,7 +490,18 @@ impl Linker<WasmerModule> for WasmerLinker {
                     }),
                 );
             }
-            let instance = Instance::new(&mut store.as_store_mut(), &module, &imports)?;
+            // Each module has its own WASI environment.
+            let mut wasi_env = match &self.wasi {
+                Some(config) if wasmer_wasix::is_wasi_module(&module) => {
+                    let env = wasi::wasi_env(&mut store.as_store_mut(), config.clone())?;
+                    imports.extend(&env.import_object(&mut store.as_store_mut(), &module)?);
+                    Some(env)
+                }
+                _ => None,
+            };
+            let instance = with_limiter(&self.limiter, || {
+                Ok(Instance::new(&mut store.as_store_mut(), &module, &imports)?)
+            })?;
             let memory = instance.exports.get_memory(MEMORY_NAME)?;
             let abi_alloc = instance
                 .exports
@@ -234,18 +512,36 @@ impl Linker<WasmerModule> for WasmerLinker {
                 env_mut.set_memory(memory.clone());
                 env_mut.set_abi_alloc(abi_alloc.clone());
             }
-            instance
+            if let Some(env) = &mut wasi_env {
+                env.initialize(&mut store, instance.clone())?;
+            }
+            (instance, engine)
         };
-        let host = WasmerModule::new(self.store.clone(), instance)?;
+        let host = WasmerModule::new(
+            self.store.clone(),
+            self.limiter.clone(),
+            self.fuel,
+            engine,
+            instance,
+        )?;
         Ok(host)
     }
 
+    fn set_limits(&mut self, limits: ModuleLimits) {
+        self.limiter.lock().unwrap().limits = limits;
+    }
+
+    fn import_wasi(&mut self, config: WasiConfig) -> Result<()> {
+        self.wasi = Some(config);
+        Ok(())
+    }
+
     fn import(
         &mut self,
         ns: impl Into<String>,
         funcs: HashMap<String, WasmerFunction>,
     ) -> Result<()> {
-        self.imports.insert(ns.into(), funcs);
+        self.imports.entry(ns.into()).or_default().extend(funcs);
         Ok(())
     }
 
@@ -0,0 +1,383 @@
+//! The WASI files of `wasmer-wasix` over a [`WasiConfig`].
+
+use ayaka_plugin::{wasi::*, Result};
+use std::{
+    fmt::Debug,
+    future::Future,
+    io::{self, SeekFrom},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, UNIX_EPOCH},
+};
+use vfs::{error::VfsErrorKind, SeekAndRead, VfsError, VfsFileType, VfsPath};
+use wasmer::AsStoreMut;
+use wasmer_wasix::{
+    runtime::task_manager::TaskWasm,
+    virtual_fs::{
+        self, AsyncRead, AsyncSeek, AsyncWrite, DirEntry, FileOpener, FileSystem, FileType,
+        Metadata, OpenOptions, OpenOptionsConfig, ReadBuf, ReadDir,
+    },
+    FsError, PluggableRuntime, VirtualFile, VirtualTaskManager, WasiEnv, WasiFunctionEnv,
+    WasiThreadError,
+};
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// The size hint of writing the stdout and stderr.
+const LINE_BUFFER: usize = 8192;
+
+fn fs_error(e: VfsError) -> FsError {
+    match e.kind() {
+        VfsErrorKind::FileNotFound => FsError::EntryNotFound,
+        VfsErrorKind::InvalidPath => FsError::InvalidInput,
+        VfsErrorKind::IoError(e) if e.kind() == io::ErrorKind::NotFound => FsError::EntryNotFound,
+        _ => FsError::IOError,
+    }
+}
+
+fn timestamp(config: &WasiConfig) -> u64 {
+    config
+        .created
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// Creates the WASI environment with the config.
+pub(crate) fn wasi_env(store: &mut impl AsStoreMut, config: WasiConfig) -> Result<WasiFunctionEnv> {
+    let time = timestamp(&config);
+    let env = WasiEnv::builder(config.name.clone())
+        .stdin(Box::new(Stdio { log: None, time }))
+        .stdout(Box::new(Stdio {
+            log: Some(config.stdout()),
+            time,
+        }))
+        .stderr(Box::new(Stdio {
+            log: Some(config.stderr()),
+            time,
+        }))
+        .fs(Box::new(VfsFileSystem(Arc::new(config))))
+        .preopen_build(|dir| dir.directory(PREOPEN_NAME).read(true))?
+        .runtime(Arc::new(PluggableRuntime::new(Arc::new(TaskManager))))
+        .finalize(store)?;
+    Ok(env)
+}
+
+/// The task manager without threads,
+/// because the plugins are called synchronously.
+#[derive(Debug)]
+struct TaskManager;
+
+impl VirtualTaskManager for TaskManager {
+    fn sleep_now(&self, time: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + Sync>> {
+        Box::pin(async move { std::thread::sleep(time) })
+    }
+
+    fn task_shared(
+        &self,
+        _task: Box<dyn FnOnce() -> BoxFuture<()> + Send + 'static>,
+    ) -> Result<(), WasiThreadError> {
+        Err(WasiThreadError::Unsupported)
+    }
+
+    fn task_wasm(&self, _task: TaskWasm) -> Result<(), WasiThreadError> {
+        Err(WasiThreadError::Unsupported)
+    }
+
+    fn task_dedicated(
+        &self,
+        _task: Box<dyn FnOnce() + Send + 'static>,
+    ) -> Result<(), WasiThreadError> {
+        Err(WasiThreadError::Unsupported)
+    }
+
+    fn thread_parallelism(&self) -> Result<usize, WasiThreadError> {
+        Ok(1)
+    }
+}
+
+/// The read-only root directory.
+///
+/// The paths are resolved by WASIX before, and are absolute here.
+#[derive(Debug)]
+struct VfsFileSystem(Arc<WasiConfig>);
+
+impl VfsFileSystem {
+    fn path(&self, path: &Path) -> virtual_fs::Result<VfsPath> {
+        let path = path.to_str().ok_or(FsError::InvalidInput)?;
+        let components = resolve_path(&[], path.trim_start_matches('/')).map_err(|e| match e {
+            PathError::NotCapable => FsError::PermissionDenied,
+            PathError::Invalid => FsError::InvalidInput,
+        })?;
+        self.0.path(&components).map_err(fs_error)
+    }
+
+    fn path_metadata(&self, path: &VfsPath) -> virtual_fs::Result<Metadata> {
+        let meta = path.metadata().map_err(fs_error)?;
+        let time = timestamp(&self.0);
+        Ok(Metadata {
+            ft: FileType {
+                dir: meta.file_type == VfsFileType::Directory,
+                file: meta.file_type == VfsFileType::File,
+                ..Default::default()
+            },
+            accessed: time,
+            created: time,
+            modified: time,
+            len: meta.len,
+        })
+    }
+}
+
+impl FileSystem for VfsFileSystem {
+    fn read_dir(&self, path: &Path) -> virtual_fs::Result<ReadDir> {
+        let entries = self
+            .path(path)?
+            .read_dir()
+            .map_err(fs_error)?
+            .map(|p| DirEntry {
+                path: path.join(p.filename()),
+                metadata: self.path_metadata(&p),
+            })
+            .collect();
+        Ok(ReadDir::new(entries))
+    }
+
+    fn create_dir(&self, _path: &Path) -> virtual_fs::Result<()> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn remove_dir(&self, _path: &Path) -> virtual_fs::Result<()> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn rename<'a>(
+        &'a self,
+        _from: &'a Path,
+        _to: &'a Path,
+    ) -> Pin<Box<dyn Future<Output = virtual_fs::Result<()>> + Send + 'a>> {
+        Box::pin(async { Err(FsError::PermissionDenied) })
+    }
+
+    fn metadata(&self, path: &Path) -> virtual_fs::Result<Metadata> {
+        self.path_metadata(&self.path(path)?)
+    }
+
+    fn remove_file(&self, _path: &Path) -> virtual_fs::Result<()> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn new_open_options(&self) -> OpenOptions {
+        OpenOptions::new(self)
+    }
+}
+
+impl FileOpener for VfsFileSystem {
+    fn open(
+        &self,
+        path: &Path,
+        conf: &OpenOptionsConfig,
+    ) -> virtual_fs::Result<Box<dyn VirtualFile + Send + Sync + 'static>> {
+        if conf.write || conf.create_new || conf.create || conf.append || conf.truncate {
+            return Err(FsError::PermissionDenied);
+        }
+        let path = self.path(path)?;
+        let meta = path.metadata().map_err(fs_error)?;
+        if meta.file_type != VfsFileType::File {
+            return Err(FsError::NotAFile);
+        }
+        // WASIX has no error for too many opened files.
+        let token = self.0.open_token().ok_or(FsError::IOError)?;
+        Ok(Box::new(VfsFile {
+            file: Mutex::new(path.open_file().map_err(fs_error)?),
+            size: meta.len,
+            time: timestamp(&self.0),
+            _token: token,
+        }))
+    }
+}
+
+/// A read-only file in the root directory.
+struct VfsFile {
+    file: Mutex<Box<dyn SeekAndRead + Send>>,
+    size: u64,
+    time: u64,
+    _token: FileToken,
+}
+
+impl VfsFile {
+    fn file(self: Pin<&mut Self>) -> &mut (dyn SeekAndRead + Send) {
+        self.get_mut().file.get_mut().unwrap().as_mut()
+    }
+}
+
+impl Debug for VfsFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VfsFile")
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncRead for VfsFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let read = self.file().read(buf.initialize_unfilled());
+        Poll::Ready(read.map(|len| buf.advance(len)))
+    }
+}
+
+impl AsyncSeek for VfsFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        self.file().seek(position).map(|_| ())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(self.file().stream_position())
+    }
+}
+
+impl AsyncWrite for VfsFile {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(io::ErrorKind::PermissionDenied.into()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl VirtualFile for VfsFile {
+    fn last_accessed(&self) -> u64 {
+        self.time
+    }
+
+    fn last_modified(&self) -> u64 {
+        self.time
+    }
+
+    fn created_time(&self) -> u64 {
+        self.time
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn set_len(&mut self, _new_size: u64) -> virtual_fs::Result<()> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn unlink(&mut self) -> BoxFuture<virtual_fs::Result<()>> {
+        Box::pin(async { Err(FsError::PermissionDenied) })
+    }
+
+    fn poll_read_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let size = self.size;
+        let pos = self.file().stream_position();
+        Poll::Ready(pos.map(|pos| size.saturating_sub(pos) as usize))
+    }
+
+    fn poll_write_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(io::ErrorKind::PermissionDenied.into()))
+    }
+}
+
+/// The stdout or stderr written to the log,
+/// or the stdin which is not supported.
+#[derive(Debug)]
+struct Stdio {
+    log: Option<WasiLog>,
+    time: u64,
+}
+
+impl AsyncRead for Stdio {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Err(io::ErrorKind::Unsupported.into()))
+    }
+}
+
+impl AsyncSeek for Stdio {
+    fn start_seek(self: Pin<&mut Self>, _position: SeekFrom) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(0))
+    }
+}
+
+impl AsyncWrite for Stdio {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(match &mut self.get_mut().log {
+            Some(log) => {
+                log.write(buf);
+                Ok(buf.len())
+            }
+            None => Err(io::ErrorKind::Unsupported.into()),
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl VirtualFile for Stdio {
+    fn last_accessed(&self) -> u64 {
+        self.time
+    }
+
+    fn last_modified(&self) -> u64 {
+        self.time
+    }
+
+    fn created_time(&self) -> u64 {
+        self.time
+    }
+
+    fn size(&self) -> u64 {
+        0
+    }
+
+    fn set_len(&mut self, _new_size: u64) -> virtual_fs::Result<()> {
+        Err(FsError::PermissionDenied)
+    }
+
+    fn unlink(&mut self) -> BoxFuture<virtual_fs::Result<()>> {
+        Box::pin(async { Err(FsError::PermissionDenied) })
+    }
+
+    fn poll_read_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        Poll::Ready(Err(io::ErrorKind::Unsupported.into()))
+    }
+
+    fn poll_write_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(LINE_BUFFER))
+    }
+}
@@ -2,12 +2,18 @@
 
 #![warn(missing_docs)]
 
-use ayaka_plugin::*;
+mod wasi;
+
+use ayaka_plugin::{wasi::*, *};
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
 };
-use wasmi::{core::Trap, *};
+use wasmi::{
+    core::{HostError, Trap, TrapCode},
+    errors::{MemoryError, TableError},
+    *,
+};
 
 unsafe fn mem_slice<'a, T: 'a>(
     store: impl Into<StoreContext<'a, T>>,
@@ -33,11 +39,111 @@ unsafe fn mem_slice_mut<'a, T: 'a>(
         .get_unchecked_mut(..len as usize)
 }
 
-type HostStore = Arc<Mutex<Store<()>>>;
+/// Limits the linear memory of the running module.
+#[derive(Default)]
+struct MemoryLimiter {
+    limits: ModuleLimits,
+    exceeded: Option<LimitError>,
+}
+
+impl MemoryLimiter {
+    fn new(limits: ModuleLimits) -> Self {
+        Self {
+            limits,
+            exceeded: None,
+        }
+    }
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> Result<bool, MemoryError> {
+        match self.limits.check_memory(desired) {
+            Ok(()) => Ok(true),
+            Err(e) => {
+                self.exceeded = Some(e);
+                Err(MemoryError::OutOfBoundsGrowth)
+            }
+        }
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: u32,
+        _desired: u32,
+        _maximum: Option<u32>,
+    ) -> Result<bool, TableError> {
+        Ok(true)
+    }
+}
+
+// Runs with the limits of a module, and restores the limits of the caller.
+// The growing traps, so the error is replaced.
+fn with_limits<T>(
+    mut store: StoreContextMut<MemoryLimiter>,
+    limits: ModuleLimits,
+    f: impl FnOnce(StoreContextMut<MemoryLimiter>) -> Result<T>,
+) -> Result<T> {
+    let caller = std::mem::replace(store.data_mut(), MemoryLimiter::new(limits));
+    let res = f(store.as_context_mut());
+    let limiter = std::mem::replace(store.data_mut(), caller);
+    match (res, limiter.exceeded) {
+        (Err(_), Some(e)) => Err(e.into()),
+        (res, _) => res,
+    }
+}
+
+type HostStore = Arc<Mutex<Store<MemoryLimiter>>>;
+
+fn set_fuel(store: &mut Store<MemoryLimiter>, fuel: u64) -> Result<()> {
+    let remaining = store.consume_fuel(0).map_err(|e| anyhow!("{}", e))?;
+    store
+        .consume_fuel(remaining)
+        .map_err(|e| anyhow!("{}", e))?;
+    store.add_fuel(fuel).map_err(|e| anyhow!("{}", e))?;
+    Ok(())
+}
+
+fn fuel_exhausted<T>(res: &Result<T>) -> bool {
+    match res {
+        Err(e) => matches!(
+            e.downcast_ref::<Trap>().and_then(|trap| trap.trap_code()),
+            Some(TrapCode::OutOfFuel)
+        ),
+        Ok(_) => false,
+    }
+}
+
+/// The error of a host function, carried by [`Trap`]
+/// to be recovered after the call.
+#[derive(Debug)]
+struct HostCallError(anyhow::Error);
+
+impl std::fmt::Display for HostCallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl HostError for HostCallError {}
+
+fn host_call_error(trap: Trap) -> anyhow::Error {
+    if trap.downcast_ref::<HostCallError>().is_some() {
+        trap.downcast::<HostCallError>().unwrap().0
+    } else {
+        trap.into()
+    }
+}
 
 /// A Wasmi [`Instance`].
 pub struct WasmiModule {
     store: HostStore,
+    fuel: Option<u64>,
+    limits: ModuleLimits,
     instance: Instance,
     memory: Memory,
     abi_free: TypedFunc<(i32, i32), ()>,
@@ -45,11 +151,23 @@ pub struct WasmiModule {
 }
 
 impl WasmiModule {
-    fn new(store: HostStore, module: &Module, linker: &wasmi::Linker<()>) -> Result<Self> {
+    fn new(
+        store: HostStore,
+        fuel: Option<u64>,
+        limits: ModuleLimits,
+        module: &Module,
+        linker: &wasmi::Linker<MemoryLimiter>,
+    ) -> Result<Self> {
         let mut inner_store = store.lock().unwrap();
-        let instance = linker
-            .instantiate(inner_store.as_context_mut(), module)?
-            .start(inner_store.as_context_mut())?;
+        if let Some(fuel) = fuel {
+            set_fuel(&mut inner_store, fuel)?;
+        }
+        let instance = with_limits(inner_store.as_context_mut(), limits, |mut store| {
+            let instance = linker
+                .instantiate(store.as_context_mut(), module)?
+                .start(store)?;
+            Ok(instance)
+        })?;
         let memory = instance
             .get_export(inner_store.as_context(), MEMORY_NAME)
             .ok_or_else(|| anyhow!("cannot get memory"))?
@@ -70,6 +188,8 @@ impl WasmiModule {
         drop(inner_store);
         Ok(Self {
             store,
+            fuel,
+            limits,
             instance,
             memory,
             abi_free,
@@ -79,7 +199,19 @@ impl WasmiModule {
 
     fn call_impl<T>(
         &self,
-        mut store: StoreContextMut<()>,
+        store: StoreContextMut<MemoryLimiter>,
+        name: &str,
+        data: &[u8],
+        f: impl FnOnce(&[u8]) -> Result<T>,
+    ) -> Result<T> {
+        with_limits(store, self.limits, |store| {
+            self.call_inner(store, name, data, f)
+        })
+    }
+
+    fn call_inner<T>(
+        &self,
+        mut store: StoreContextMut<MemoryLimiter>,
         name: &str,
         data: &[u8],
         f: impl FnOnce(&[u8]) -> Result<T>,
@@ -101,7 +233,7 @@ impl WasmiModule {
 
         self.abi_free.call(&mut store, (ptr, data.len() as i32))?;
 
-        let res = res?;
+        let res = res.map_err(host_call_error)?;
         let (len, res) = ((res >> 32) as i32, (res & 0xFFFFFFFF) as i32);
 
         let res_data = unsafe { mem_slice(&store, &self.memory, res, len) };
@@ -123,7 +255,18 @@ impl RawModule for WasmiModule {
     type Func = Func;
 
     fn call<T>(&self, name: &str, data: &[u8], f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
-        self.call_impl(self.store.lock().unwrap().as_context_mut(), name, data, f)
+        let mut store = self.store.lock().unwrap();
+        if let Some(fuel) = self.fuel {
+            set_fuel(&mut store, fuel)?;
+        }
+        let res = self.call_impl(store.as_context_mut(), name, data, f);
+        if fuel_exhausted(&res) {
+            return Err(LimitError::FuelExhausted {
+                method: name.to_string(),
+            }
+            .into());
+        }
+        res
     }
 }
 
@@ -131,34 +274,53 @@ impl RawModule for WasmiModule {
 pub struct WasmiLinker {
     engine: Engine,
     store: HostStore,
-    linker: wasmi::Linker<()>,
+    fuel: Option<u64>,
+    limits: ModuleLimits,
+    imports: HashMap<String, HashMap<String, Func>>,
 }
 
 impl ayaka_plugin::Linker<WasmiModule> for WasmiLinker {
-    type Config = ();
+    type Config = LinkerConfig;
 
-    fn new(_: ()) -> Result<Self> {
-        let engine = Engine::default();
-        let store = Store::new(&engine, ());
-        let linker = wasmi::Linker::new(&engine);
+    fn new(config: LinkerConfig) -> Result<Self> {
+        let mut engine_config = Config::default();
+        engine_config.consume_fuel(config.fuel.is_some());
+        let engine = Engine::new(&engine_config);
+        let mut store = Store::new(&engine, MemoryLimiter::default());
+        store.limiter(|limiter| limiter);
         Ok(Self {
             engine,
             store: Arc::new(Mutex::new(store)),
-            linker,
+            fuel: config.fuel,
+            limits: ModuleLimits::default(),
+            imports: HashMap::default(),
         })
     }
 
-    fn create(&self, binary: &[u8]) -> Result<WasmiModule> {
+    fn create_with(&self, binary: &[u8], granted: impl Fn(&str) -> bool) -> Result<WasmiModule> {
         let module = Module::new(&self.engine, binary)?;
-        let host = WasmiModule::new(self.store.clone(), &module, &self.linker)?;
+        PermissionError::check(module.imports().map(|i| (i.module(), i.name())), granted)?;
+        let mut linker = wasmi::Linker::new(&self.engine);
+        for (ns, funcs) in &self.imports {
+            for (name, func) in funcs {
+                linker.define(ns, name, *func)?;
+            }
+        }
+        let host = WasmiModule::new(self.store.clone(), self.fuel, self.limits, &module, &linker)?;
         Ok(host)
     }
 
+    fn set_limits(&mut self, limits: ModuleLimits) {
+        self.limits = limits;
+    }
+
+    fn import_wasi(&mut self, config: WasiConfig) -> Result<()> {
+        let funcs = wasi::wasi_funcs(&mut self.store.lock().unwrap(), config);
+        self.import(WASI_NAMESPACE, funcs)
+    }
+
     fn import(&mut self, ns: impl Into<String>, funcs: HashMap<String, Func>) -> Result<()> {
-        let ns = ns.into();
-        for (name, func) in funcs {
-            self.linker.define(&ns, &name, func)?;
-        }
+        self.imports.entry(ns.into()).or_default().extend(funcs);
         Ok(())
     }
 
@@ -168,7 +330,7 @@ impl ayaka_plugin::Linker<WasmiModule> for WasmiLinker {
     ) -> Func {
         Func::wrap(
             self.store.lock().unwrap().as_context_mut(),
-            move |mut store: Caller<()>, len: i32, data: i32| unsafe {
+            move |mut store: Caller<MemoryLimiter>, len: i32, data: i32| unsafe {
                 let memory = store
                     .get_export(MEMORY_NAME)
                     .ok_or_else(|| Trap::new("cannot get memory"))?
@@ -177,7 +339,7 @@ impl ayaka_plugin::Linker<WasmiModule> for WasmiLinker {
                 let data = {
                     let store = store.as_context_mut();
                     let handle = WasmiLinkerHandle { store, memory };
-                    f(handle, data, len).map_err(|e| Trap::new(e.to_string()))?
+                    f(handle, data, len).map_err(HostCallError)?
                 };
                 let abi_alloc = store
                     .get_export(ABI_ALLOC_NAME)
@@ -197,7 +359,7 @@ impl ayaka_plugin::Linker<WasmiModule> for WasmiLinker {
 
 /// A Wasmi [`StoreContextMut`].
 pub struct WasmiLinkerHandle<'a> {
-    store: StoreContextMut<'a, ()>,
+    store: StoreContextMut<'a, MemoryLimiter>,
     memory: Memory,
 }
 
@@ -0,0 +1,942 @@
+//! The WASI preview1 functions implemented over a [`WasiConfig`],
+//! because Wasmi doesn't provide them.
+
+use crate::{HostCallError, MemoryLimiter};
+use ayaka_plugin::{wasi::*, *};
+use rand::RngCore;
+use std::{
+    collections::HashMap,
+    io::{Seek, SeekFrom},
+    sync::{Arc, Mutex},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use vfs::{error::VfsErrorKind, SeekAndRead, VfsError, VfsFileType, VfsPath};
+use wasmi::{core::Trap, AsContextMut, Caller, Extern, Func, Store};
+
+type Errno = i32;
+
+const ERRNO_SUCCESS: Errno = 0;
+const ERRNO_BADF: Errno = 8;
+const ERRNO_FAULT: Errno = 21;
+const ERRNO_INVAL: Errno = 28;
+const ERRNO_IO: Errno = 29;
+const ERRNO_ISDIR: Errno = 31;
+const ERRNO_MFILE: Errno = 33;
+const ERRNO_NAMETOOLONG: Errno = 37;
+const ERRNO_NOENT: Errno = 44;
+const ERRNO_NOTDIR: Errno = 54;
+const ERRNO_NOTSUP: Errno = 58;
+const ERRNO_PERM: Errno = 63;
+const ERRNO_ROFS: Errno = 69;
+const ERRNO_SPIPE: Errno = 70;
+const ERRNO_NOTCAPABLE: Errno = 76;
+
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+const FILETYPE_DIRECTORY: u8 = 3;
+const FILETYPE_REGULAR_FILE: u8 = 4;
+
+const RIGHTS_FD_READ: u64 = 1 << 1;
+const RIGHTS_FD_SEEK: u64 = 1 << 2;
+const RIGHTS_FD_TELL: u64 = 1 << 5;
+const RIGHTS_FD_WRITE: u64 = 1 << 6;
+const RIGHTS_PATH_OPEN: u64 = 1 << 13;
+const RIGHTS_FD_READDIR: u64 = 1 << 14;
+const RIGHTS_PATH_FILESTAT_GET: u64 = 1 << 18;
+const RIGHTS_FD_FILESTAT_GET: u64 = 1 << 21;
+
+const FILE_RIGHTS: u64 = RIGHTS_FD_READ | RIGHTS_FD_SEEK | RIGHTS_FD_TELL | RIGHTS_FD_FILESTAT_GET;
+const DIR_RIGHTS: u64 =
+    RIGHTS_PATH_OPEN | RIGHTS_FD_READDIR | RIGHTS_PATH_FILESTAT_GET | RIGHTS_FD_FILESTAT_GET;
+
+const OFLAGS_CREAT: i32 = 1 << 0;
+const OFLAGS_DIRECTORY: i32 = 1 << 1;
+const OFLAGS_EXCL: i32 = 1 << 2;
+const OFLAGS_TRUNC: i32 = 1 << 3;
+
+const FDFLAGS_APPEND: i32 = 1 << 0;
+
+const PREOPEN_FD: u32 = 3;
+
+type WasiResult<T = ()> = std::result::Result<T, Errno>;
+
+fn errno(res: WasiResult) -> Errno {
+    match res {
+        Ok(()) => ERRNO_SUCCESS,
+        Err(e) => e,
+    }
+}
+
+fn io_errno(e: std::io::Error) -> Errno {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => ERRNO_NOENT,
+        std::io::ErrorKind::PermissionDenied => ERRNO_PERM,
+        std::io::ErrorKind::InvalidInput => ERRNO_INVAL,
+        _ => ERRNO_IO,
+    }
+}
+
+fn vfs_errno(e: VfsError) -> Errno {
+    match e.kind() {
+        VfsErrorKind::FileNotFound => ERRNO_NOENT,
+        VfsErrorKind::InvalidPath => ERRNO_INVAL,
+        VfsErrorKind::NotSupported => ERRNO_NOTSUP,
+        VfsErrorKind::IoError(e) if e.kind() == std::io::ErrorKind::NotFound => ERRNO_NOENT,
+        _ => ERRNO_IO,
+    }
+}
+
+fn mem_slice(mem: &[u8], ptr: i32, len: u32) -> WasiResult<&[u8]> {
+    let start = ptr as u32 as usize;
+    let end = start.checked_add(len as usize).ok_or(ERRNO_FAULT)?;
+    mem.get(start..end).ok_or(ERRNO_FAULT)
+}
+
+fn mem_slice_mut(mem: &mut [u8], ptr: i32, len: u32) -> WasiResult<&mut [u8]> {
+    let start = ptr as u32 as usize;
+    let end = start.checked_add(len as usize).ok_or(ERRNO_FAULT)?;
+    mem.get_mut(start..end).ok_or(ERRNO_FAULT)
+}
+
+fn read_u32(mem: &[u8], ptr: i32) -> WasiResult<u32> {
+    let data = mem_slice(mem, ptr, 4)?;
+    Ok(u32::from_le_bytes(data.try_into().unwrap()))
+}
+
+fn write_bytes(mem: &mut [u8], ptr: i32, data: &[u8]) -> WasiResult {
+    mem_slice_mut(mem, ptr, data.len() as u32)?.copy_from_slice(data);
+    Ok(())
+}
+
+fn write_u32(mem: &mut [u8], ptr: i32, value: u32) -> WasiResult {
+    write_bytes(mem, ptr, &value.to_le_bytes())
+}
+
+fn write_u64(mem: &mut [u8], ptr: i32, value: u64) -> WasiResult {
+    write_bytes(mem, ptr, &value.to_le_bytes())
+}
+
+fn read_str(mem: &[u8], ptr: i32, len: i32) -> WasiResult<&str> {
+    std::str::from_utf8(mem_slice(mem, ptr, len as u32)?).map_err(|_| ERRNO_INVAL)
+}
+
+/// Reads the `iovec` or `ciovec` array as pairs of pointers and lengths.
+fn iovecs(mem: &[u8], iovs: i32, iovs_len: i32) -> WasiResult<Vec<(i32, u32)>> {
+    (0..iovs_len as u32)
+        .map(|i| {
+            let ptr = (iovs as u32).wrapping_add(i * 8) as i32;
+            let buf = read_u32(mem, ptr)?;
+            let buf_len = read_u32(mem, ptr.wrapping_add(4))?;
+            Ok((buf as i32, buf_len))
+        })
+        .collect()
+}
+
+fn read_iovecs(
+    file: &mut (dyn SeekAndRead + Send),
+    mem: &mut [u8],
+    iovs: &[(i32, u32)],
+) -> WasiResult<u32> {
+    let mut total = 0;
+    for &(ptr, len) in iovs {
+        let buf = mem_slice_mut(mem, ptr, len)?;
+        let read = file.read(buf).map_err(io_errno)?;
+        total += read as u32;
+        if read < len as usize {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+fn filestat(filetype: u8, size: u64, time: u64) -> [u8; 64] {
+    let mut data = [0; 64];
+    data[16] = filetype;
+    data[24..32].copy_from_slice(&1u64.to_le_bytes());
+    data[32..40].copy_from_slice(&size.to_le_bytes());
+    for offset in [40, 48, 56] {
+        data[offset..offset + 8].copy_from_slice(&time.to_le_bytes());
+    }
+    data
+}
+
+enum WasiFd {
+    Stdin,
+    Stdout,
+    Stderr,
+    Dir(Vec<String>),
+    File(Vec<String>, Box<dyn SeekAndRead + Send>),
+}
+
+/// The WASI context of a plugin module.
+pub(crate) struct WasiCtx {
+    config: WasiConfig,
+    fds: HashMap<u32, WasiFd>,
+    stdout: WasiLog,
+    stderr: WasiLog,
+    start: Instant,
+}
+
+impl WasiCtx {
+    pub fn new(config: WasiConfig) -> Self {
+        Self {
+            fds: HashMap::from([
+                (0, WasiFd::Stdin),
+                (1, WasiFd::Stdout),
+                (2, WasiFd::Stderr),
+                (PREOPEN_FD, WasiFd::Dir(vec![])),
+            ]),
+            stdout: config.stdout(),
+            stderr: config.stderr(),
+            start: Instant::now(),
+            config,
+        }
+    }
+
+    fn file(&mut self, fd: i32) -> WasiResult<&mut Box<dyn SeekAndRead + Send>> {
+        match self.fds.get_mut(&(fd as u32)) {
+            Some(WasiFd::File(_, file)) => Ok(file),
+            Some(WasiFd::Stdin | WasiFd::Stdout | WasiFd::Stderr) => Err(ERRNO_SPIPE),
+            Some(WasiFd::Dir(_)) => Err(ERRNO_ISDIR),
+            None => Err(ERRNO_BADF),
+        }
+    }
+
+    /// Resolves a path relative to a directory,
+    /// and rejects it if it escapes the root directory.
+    fn resolve(&self, fd: i32, path: &str) -> WasiResult<(Vec<String>, VfsPath)> {
+        let base = match self.fds.get(&(fd as u32)) {
+            Some(WasiFd::Dir(components)) => components,
+            Some(_) => return Err(ERRNO_NOTDIR),
+            None => return Err(ERRNO_BADF),
+        };
+        let components = resolve_path(base, path).map_err(|e| match e {
+            PathError::NotCapable => ERRNO_NOTCAPABLE,
+            PathError::Invalid => ERRNO_INVAL,
+        })?;
+        let path = self.path(&components)?;
+        Ok((components, path))
+    }
+
+    /// Finds the lowest free fd after the preopened directory,
+    /// if the opened files don't reach the limit.
+    fn alloc_fd(&self) -> WasiResult<u32> {
+        let opened = self.fds.keys().filter(|fd| **fd > PREOPEN_FD).count();
+        if matches!(self.config.files, Some(limit) if opened >= limit) {
+            return Err(ERRNO_MFILE);
+        }
+        (PREOPEN_FD + 1..=u32::MAX)
+            .find(|fd| !self.fds.contains_key(fd))
+            .ok_or(ERRNO_MFILE)
+    }
+
+    fn path(&self, components: &[String]) -> WasiResult<VfsPath> {
+        self.config.path(components).map_err(vfs_errno)
+    }
+
+    fn time(&self) -> u64 {
+        self.config
+            .created
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default()
+    }
+
+    fn path_filestat(&self, path: &VfsPath) -> WasiResult<[u8; 64]> {
+        let meta = path.metadata().map_err(vfs_errno)?;
+        let filetype = match meta.file_type {
+            VfsFileType::File => FILETYPE_REGULAR_FILE,
+            VfsFileType::Directory => FILETYPE_DIRECTORY,
+        };
+        Ok(filestat(filetype, meta.len, self.time()))
+    }
+}
+
+/// The WASI preview1 functions with the same names.
+///
+/// They take the linear memory and the raw args,
+/// and return the error number.
+/// The functions writing the files return `ROFS`.
+#[allow(missing_docs, clippy::too_many_arguments)]
+impl WasiCtx {
+    pub fn args_get(&mut self, _mem: &mut [u8], _argv: i32, _argv_buf: i32) -> Errno {
+        ERRNO_SUCCESS
+    }
+
+    pub fn args_sizes_get(&mut self, mem: &mut [u8], argc: i32, argv_buf_size: i32) -> Errno {
+        errno(write_u32(mem, argc, 0).and_then(|()| write_u32(mem, argv_buf_size, 0)))
+    }
+
+    pub fn environ_get(&mut self, _mem: &mut [u8], _environ: i32, _environ_buf: i32) -> Errno {
+        ERRNO_SUCCESS
+    }
+
+    pub fn environ_sizes_get(
+        &mut self,
+        mem: &mut [u8],
+        environc: i32,
+        environ_buf_size: i32,
+    ) -> Errno {
+        errno(write_u32(mem, environc, 0).and_then(|()| write_u32(mem, environ_buf_size, 0)))
+    }
+
+    pub fn clock_res_get(&mut self, mem: &mut [u8], id: i32, resolution: i32) -> Errno {
+        match id {
+            0 | 1 => errno(write_u64(mem, resolution, 1)),
+            _ => ERRNO_INVAL,
+        }
+    }
+
+    pub fn clock_time_get(&mut self, mem: &mut [u8], id: i32, _precision: i64, time: i32) -> Errno {
+        let now = match id {
+            0 => match SystemTime::now().duration_since(UNIX_EPOCH) {
+                Ok(now) => now,
+                Err(_) => return ERRNO_INVAL,
+            },
+            1 => self.start.elapsed(),
+            _ => return ERRNO_INVAL,
+        };
+        errno(write_u64(mem, time, now.as_nanos() as u64))
+    }
+
+    pub fn random_get(&mut self, mem: &mut [u8], buf: i32, buf_len: i32) -> Errno {
+        // The entropy is not from the game, to keep the game random numbers unchanged.
+        errno(mem_slice_mut(mem, buf, buf_len as u32).map(|buf| rand::thread_rng().fill_bytes(buf)))
+    }
+
+    pub fn sched_yield(&mut self, _mem: &mut [u8]) -> Errno {
+        ERRNO_SUCCESS
+    }
+
+    pub fn poll_oneoff(
+        &mut self,
+        _mem: &mut [u8],
+        _in: i32,
+        _out: i32,
+        _nsubscriptions: i32,
+        _nevents: i32,
+    ) -> Errno {
+        ERRNO_NOTSUP
+    }
+
+    pub fn proc_raise(&mut self, _mem: &mut [u8], _sig: i32) -> Errno {
+        ERRNO_NOTSUP
+    }
+
+    pub fn fd_write(
+        &mut self,
+        mem: &mut [u8],
+        fd: i32,
+        iovs: i32,
+        iovs_len: i32,
+        nwritten: i32,
+    ) -> Errno {
+        let log = match self.fds.get(&(fd as u32)) {
+            Some(WasiFd::Stdout) => &mut self.stdout,
+            Some(WasiFd::Stderr) => &mut self.stderr,
+            _ => return ERRNO_BADF,
+        };
+        errno((|| {
+            let mut data = vec![];
+            for (ptr, len) in iovecs(mem, iovs, iovs_len)? {
+                data.extend_from_slice(mem_slice(mem, ptr, len)?);
+            }
+            log.write(&data);
+            write_u32(mem, nwritten, data.len() as u32)
+        })())
+    }
+
+    pub fn fd_pwrite(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _iovs: i32,
+        _iovs_len: i32,
+        _offset: i64,
+        _nwritten: i32,
+    ) -> Errno {
+        ERRNO_BADF
+    }
+
+    pub fn fd_read(
+        &mut self,
+        mem: &mut [u8],
+        fd: i32,
+        iovs: i32,
+        iovs_len: i32,
+        nread: i32,
+    ) -> Errno {
+        errno((|| {
+            let iovs = iovecs(mem, iovs, iovs_len)?;
+            let read = match self.fds.get_mut(&(fd as u32)) {
+                Some(WasiFd::Stdin) => return Err(ERRNO_NOTSUP),
+                Some(WasiFd::File(_, file)) => read_iovecs(file.as_mut(), mem, &iovs)?,
+                Some(WasiFd::Dir(_)) => return Err(ERRNO_ISDIR),
+                _ => return Err(ERRNO_BADF),
+            };
+            write_u32(mem, nread, read)
+        })())
+    }
+
+    pub fn fd_pread(
+        &mut self,
+        mem: &mut [u8],
+        fd: i32,
+        iovs: i32,
+        iovs_len: i32,
+        offset: i64,
+        nread: i32,
+    ) -> Errno {
+        errno((|| {
+            let iovs = iovecs(mem, iovs, iovs_len)?;
+            let file = self.file(fd)?;
+            let pos = file.stream_position().map_err(io_errno)?;
+            file.seek(SeekFrom::Start(offset as u64))
+                .map_err(io_errno)?;
+            let read = read_iovecs(file.as_mut(), mem, &iovs);
+            file.seek(SeekFrom::Start(pos)).map_err(io_errno)?;
+            write_u32(mem, nread, read?)
+        })())
+    }
+
+    pub fn fd_seek(
+        &mut self,
+        mem: &mut [u8],
+        fd: i32,
+        offset: i64,
+        whence: i32,
+        newoffset: i32,
+    ) -> Errno {
+        let pos = match whence {
+            0 if offset >= 0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return ERRNO_INVAL,
+        };
+        errno(
+            self.file(fd)
+                .and_then(|file| file.seek(pos).map_err(io_errno))
+                .and_then(|pos| write_u64(mem, newoffset, pos)),
+        )
+    }
+
+    pub fn fd_tell(&mut self, mem: &mut [u8], fd: i32, offset: i32) -> Errno {
+        self.fd_seek(mem, fd, 0, 1, offset)
+    }
+
+    pub fn fd_close(&mut self, _mem: &mut [u8], fd: i32) -> Errno {
+        match self.fds.remove(&(fd as u32)) {
+            Some(_) => ERRNO_SUCCESS,
+            None => ERRNO_BADF,
+        }
+    }
+
+    pub fn fd_renumber(&mut self, _mem: &mut [u8], fd: i32, to: i32) -> Errno {
+        if !self.fds.contains_key(&(to as u32)) {
+            return ERRNO_BADF;
+        }
+        match self.fds.remove(&(fd as u32)) {
+            Some(entry) => {
+                self.fds.insert(to as u32, entry);
+                ERRNO_SUCCESS
+            }
+            None => ERRNO_BADF,
+        }
+    }
+
+    pub fn fd_sync(&mut self, _mem: &mut [u8], _fd: i32) -> Errno {
+        ERRNO_SUCCESS
+    }
+
+    pub fn fd_datasync(&mut self, _mem: &mut [u8], _fd: i32) -> Errno {
+        ERRNO_SUCCESS
+    }
+
+    pub fn fd_advise(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _offset: i64,
+        _len: i64,
+        _advice: i32,
+    ) -> Errno {
+        ERRNO_SUCCESS
+    }
+
+    pub fn fd_allocate(&mut self, _mem: &mut [u8], _fd: i32, _offset: i64, _len: i64) -> Errno {
+        ERRNO_ROFS
+    }
+
+    pub fn fd_fdstat_get(&mut self, mem: &mut [u8], fd: i32, buf: i32) -> Errno {
+        let (filetype, base, inheriting) = match self.fds.get(&(fd as u32)) {
+            Some(WasiFd::Stdin) => (FILETYPE_CHARACTER_DEVICE, RIGHTS_FD_READ, 0),
+            Some(WasiFd::Stdout | WasiFd::Stderr) => {
+                (FILETYPE_CHARACTER_DEVICE, RIGHTS_FD_WRITE, 0)
+            }
+            Some(WasiFd::Dir(_)) => (FILETYPE_DIRECTORY, DIR_RIGHTS, DIR_RIGHTS | FILE_RIGHTS),
+            Some(WasiFd::File(_, _)) => (FILETYPE_REGULAR_FILE, FILE_RIGHTS, 0),
+            None => return ERRNO_BADF,
+        };
+        let mut data = [0; 24];
+        data[0] = filetype;
+        data[8..16].copy_from_slice(&base.to_le_bytes());
+        data[16..24].copy_from_slice(&inheriting.to_le_bytes());
+        errno(write_bytes(mem, buf, &data))
+    }
+
+    pub fn fd_fdstat_set_flags(&mut self, _mem: &mut [u8], _fd: i32, _flags: i32) -> Errno {
+        ERRNO_NOTSUP
+    }
+
+    pub fn fd_fdstat_set_rights(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _base: i64,
+        _inheriting: i64,
+    ) -> Errno {
+        ERRNO_NOTSUP
+    }
+
+    pub fn fd_filestat_get(&mut self, mem: &mut [u8], fd: i32, buf: i32) -> Errno {
+        let data = match self.fds.get(&(fd as u32)) {
+            Some(WasiFd::Stdin | WasiFd::Stdout | WasiFd::Stderr) => {
+                Ok(filestat(FILETYPE_CHARACTER_DEVICE, 0, self.time()))
+            }
+            Some(WasiFd::Dir(components) | WasiFd::File(components, _)) => self
+                .path(components)
+                .and_then(|path| self.path_filestat(&path)),
+            None => Err(ERRNO_BADF),
+        };
+        errno(data.and_then(|data| write_bytes(mem, buf, &data)))
+    }
+
+    pub fn fd_filestat_set_size(&mut self, _mem: &mut [u8], _fd: i32, _size: i64) -> Errno {
+        ERRNO_ROFS
+    }
+
+    pub fn fd_filestat_set_times(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _atim: i64,
+        _mtim: i64,
+        _fst_flags: i32,
+    ) -> Errno {
+        ERRNO_ROFS
+    }
+
+    pub fn fd_prestat_get(&mut self, mem: &mut [u8], fd: i32, buf: i32) -> Errno {
+        if fd as u32 != PREOPEN_FD || !self.fds.contains_key(&PREOPEN_FD) {
+            return ERRNO_BADF;
+        }
+        let mut data = [0; 8];
+        data[4..8].copy_from_slice(&(PREOPEN_NAME.len() as u32).to_le_bytes());
+        errno(write_bytes(mem, buf, &data))
+    }
+
+    pub fn fd_prestat_dir_name(&mut self, mem: &mut [u8], fd: i32, path: i32, len: i32) -> Errno {
+        if fd as u32 != PREOPEN_FD || !self.fds.contains_key(&PREOPEN_FD) {
+            return ERRNO_BADF;
+        }
+        if (len as u32 as usize) < PREOPEN_NAME.len() {
+            return ERRNO_NAMETOOLONG;
+        }
+        errno(write_bytes(mem, path, PREOPEN_NAME.as_bytes()))
+    }
+
+    pub fn fd_readdir(
+        &mut self,
+        mem: &mut [u8],
+        fd: i32,
+        buf: i32,
+        buf_len: i32,
+        cookie: i64,
+        bufused: i32,
+    ) -> Errno {
+        errno((|| {
+            let path = match self.fds.get(&(fd as u32)) {
+                Some(WasiFd::Dir(components)) => self.path(components)?,
+                Some(_) => return Err(ERRNO_NOTDIR),
+                None => return Err(ERRNO_BADF),
+            };
+            let mut entries = path
+                .read_dir()
+                .map_err(vfs_errno)?
+                .map(|p| {
+                    let filetype = if p.is_dir().unwrap_or_default() {
+                        FILETYPE_DIRECTORY
+                    } else {
+                        FILETYPE_REGULAR_FILE
+                    };
+                    (p.filename(), filetype)
+                })
+                .collect::<Vec<_>>();
+            entries.sort();
+            let dots = [
+                (".".to_string(), FILETYPE_DIRECTORY),
+                ("..".to_string(), FILETYPE_DIRECTORY),
+            ];
+            let mut data = vec![];
+            for (i, (name, filetype)) in dots
+                .iter()
+                .chain(&entries)
+                .enumerate()
+                .skip(cookie as usize)
+            {
+                let next = i as u64 + 1;
+                data.extend_from_slice(&next.to_le_bytes());
+                data.extend_from_slice(&next.to_le_bytes());
+                data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                data.extend_from_slice(&[*filetype, 0, 0, 0]);
+                data.extend_from_slice(name.as_bytes());
+            }
+            // Fill the buffer as much as possible, and the last entry may be truncated.
+            data.truncate(buf_len as u32 as usize);
+            write_bytes(mem, buf, &data)?;
+            write_u32(mem, bufused, data.len() as u32)
+        })())
+    }
+
+    pub fn path_open(
+        &mut self,
+        mem: &mut [u8],
+        fd: i32,
+        _dirflags: i32,
+        path: i32,
+        path_len: i32,
+        oflags: i32,
+        rights_base: i64,
+        _rights_inheriting: i64,
+        fdflags: i32,
+        opened_fd: i32,
+    ) -> Errno {
+        if oflags & (OFLAGS_CREAT | OFLAGS_EXCL | OFLAGS_TRUNC) != 0
+            || fdflags & FDFLAGS_APPEND != 0
+            || rights_base as u64 & RIGHTS_FD_WRITE != 0
+        {
+            return ERRNO_ROFS;
+        }
+        errno((|| {
+            let (components, path) = self.resolve(fd, read_str(mem, path, path_len)?)?;
+            let meta = path.metadata().map_err(vfs_errno)?;
+            let entry = match meta.file_type {
+                VfsFileType::Directory => WasiFd::Dir(components),
+                VfsFileType::File if oflags & OFLAGS_DIRECTORY != 0 => return Err(ERRNO_NOTDIR),
+                VfsFileType::File => WasiFd::File(components, path.open_file().map_err(vfs_errno)?),
+            };
+            let fd = self.alloc_fd()?;
+            self.fds.insert(fd, entry);
+            write_u32(mem, opened_fd, fd)
+        })())
+    }
+
+    pub fn path_filestat_get(
+        &mut self,
+        mem: &mut [u8],
+        fd: i32,
+        _flags: i32,
+        path: i32,
+        path_len: i32,
+        buf: i32,
+    ) -> Errno {
+        errno((|| {
+            let (_, path) = self.resolve(fd, read_str(mem, path, path_len)?)?;
+            write_bytes(mem, buf, &self.path_filestat(&path)?)
+        })())
+    }
+
+    pub fn path_filestat_set_times(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _flags: i32,
+        _path: i32,
+        _path_len: i32,
+        _atim: i64,
+        _mtim: i64,
+        _fst_flags: i32,
+    ) -> Errno {
+        ERRNO_ROFS
+    }
+
+    pub fn path_readlink(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _path: i32,
+        _path_len: i32,
+        _buf: i32,
+        _buf_len: i32,
+        _bufused: i32,
+    ) -> Errno {
+        ERRNO_INVAL
+    }
+
+    pub fn path_create_directory(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _path: i32,
+        _path_len: i32,
+    ) -> Errno {
+        ERRNO_ROFS
+    }
+
+    pub fn path_remove_directory(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _path: i32,
+        _path_len: i32,
+    ) -> Errno {
+        ERRNO_ROFS
+    }
+
+    pub fn path_unlink_file(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _path: i32,
+        _path_len: i32,
+    ) -> Errno {
+        ERRNO_ROFS
+    }
+
+    pub fn path_rename(
+        &mut self,
+        _mem: &mut [u8],
+        _fd: i32,
+        _old_path: i32,
+        _old_path_len: i32,
+        _new_fd: i32,
+        _new_path: i32,
+        _new_path_len: i32,
+    ) -> Errno {
+        ERRNO_ROFS
+    }
+
+    pub fn path_symlink(
+        &mut self,
+        _mem: &mut [u8],
+        _old_path: i32,
+        _old_path_len: i32,
+        _fd: i32,
+        _new_path: i32,
+        _new_path_len: i32,
+    ) -> Errno {
+        ERRNO_ROFS
+    }
+
+    pub fn path_link(
+        &mut self,
+        _mem: &mut [u8],
+        _old_fd: i32,
+        _old_flags: i32,
+        _old_path: i32,
+        _old_path_len: i32,
+        _new_fd: i32,
+        _new_path: i32,
+        _new_path_len: i32,
+    ) -> Errno {
+        ERRNO_ROFS
+    }
+}
+
+/// Wraps the WASI preview1 functions in [`WasiCtx`] with the signatures.
+macro_rules! wasi_funcs {
+    (($store:expr, $ctx:expr) $($name:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        HashMap::from([$(
+            (stringify!($name).to_string(), {
+                let ctx = $ctx.clone();
+                Func::wrap(
+                    $store.as_context_mut(),
+                    move |mut caller: Caller<MemoryLimiter>, $($arg: $ty),*| -> i32 {
+                        match caller.get_export(MEMORY_NAME).and_then(Extern::into_memory) {
+                            Some(memory) => ctx
+                                .lock()
+                                .unwrap()
+                                .$name(memory.data_mut(&mut caller), $($arg),*),
+                            None => ERRNO_FAULT,
+                        }
+                    },
+                )
+            }),
+        )*])
+    };
+}
+
+/// Creates the WASI preview1 functions with the config.
+pub(crate) fn wasi_funcs(
+    store: &mut Store<MemoryLimiter>,
+    config: WasiConfig,
+) -> HashMap<String, Func> {
+    let ctx = Arc::new(Mutex::new(WasiCtx::new(config)));
+    let mut funcs = wasi_funcs! {
+        (store, ctx)
+    args_get(argv: i32, argv_buf: i32),
+    args_sizes_get(argc: i32, argv_buf_size: i32),
+    environ_get(environ: i32, environ_buf: i32),
+    environ_sizes_get(environc: i32, environ_buf_size: i32),
+    clock_res_get(id: i32, resolution: i32),
+    clock_time_get(id: i32, precision: i64, time: i32),
+    random_get(buf: i32, buf_len: i32),
+    sched_yield(),
+    poll_oneoff(r#in: i32, out: i32, nsubscriptions: i32, nevents: i32),
+    proc_raise(sig: i32),
+    fd_write(fd: i32, iovs: i32, iovs_len: i32, nwritten: i32),
+    fd_pwrite(fd: i32, iovs: i32, iovs_len: i32, offset: i64, nwritten: i32),
+    fd_read(fd: i32, iovs: i32, iovs_len: i32, nread: i32),
+    fd_pread(fd: i32, iovs: i32, iovs_len: i32, offset: i64, nread: i32),
+    fd_seek(fd: i32, offset: i64, whence: i32, newoffset: i32),
+    fd_tell(fd: i32, offset: i32),
+    fd_close(fd: i32),
+    fd_renumber(fd: i32, to: i32),
+    fd_sync(fd: i32),
+    fd_datasync(fd: i32),
+    fd_advise(fd: i32, offset: i64, len: i64, advice: i32),
+    fd_allocate(fd: i32, offset: i64, len: i64),
+    fd_fdstat_get(fd: i32, buf: i32),
+    fd_fdstat_set_flags(fd: i32, flags: i32),
+    fd_fdstat_set_rights(fd: i32, base: i64, inheriting: i64),
+    fd_filestat_get(fd: i32, buf: i32),
+    fd_filestat_set_size(fd: i32, size: i64),
+    fd_filestat_set_times(fd: i32, atim: i64, mtim: i64, fst_flags: i32),
+    fd_prestat_get(fd: i32, buf: i32),
+    fd_prestat_dir_name(fd: i32, path: i32, path_len: i32),
+    fd_readdir(fd: i32, buf: i32, buf_len: i32, cookie: i64, bufused: i32),
+    path_open(
+        fd: i32,
+        dirflags: i32,
+        path: i32,
+        path_len: i32,
+        oflags: i32,
+        rights_base: i64,
+        rights_inheriting: i64,
+        fdflags: i32,
+        opened_fd: i32
+    ),
+    path_filestat_get(fd: i32, flags: i32, path: i32, path_len: i32, buf: i32),
+    path_filestat_set_times(
+        fd: i32,
+        flags: i32,
+        path: i32,
+        path_len: i32,
+        atim: i64,
+        mtim: i64,
+        fst_flags: i32
+    ),
+    path_readlink(
+        fd: i32,
+        path: i32,
+        path_len: i32,
+        buf: i32,
+        buf_len: i32,
+        bufused: i32
+    ),
+    path_create_directory(fd: i32, path: i32, path_len: i32),
+    path_remove_directory(fd: i32, path: i32, path_len: i32),
+    path_unlink_file(fd: i32, path: i32, path_len: i32),
+    path_rename(
+        fd: i32,
+        old_path: i32,
+        old_path_len: i32,
+        new_fd: i32,
+        new_path: i32,
+        new_path_len: i32
+    ),
+    path_symlink(
+        old_path: i32,
+        old_path_len: i32,
+        fd: i32,
+        new_path: i32,
+        new_path_len: i32
+    ),
+    path_link(
+        old_fd: i32,
+        old_flags: i32,
+        old_path: i32,
+        old_path_len: i32,
+        new_fd: i32,
+        new_path: i32,
+        new_path_len: i32
+    ),
+    };
+    // `proc_exit` traps instead of returning.
+    funcs.insert(
+        "proc_exit".to_string(),
+        Func::wrap(store.as_context_mut(), |code: i32| -> Result<(), Trap> {
+            Err(HostCallError(WasiExit(code).into()).into())
+        }),
+    );
+    funcs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use vfs::MemoryFS;
+
+    fn ctx(files: Option<usize>) -> WasiCtx {
+        let root: VfsPath = MemoryFS::new().into();
+        root.join("a.txt").unwrap().create_file().unwrap();
+        WasiCtx::new(WasiConfig::new("test", root).with_files_limit(files))
+    }
+
+    fn open(ctx: &mut WasiCtx, mem: &mut [u8]) -> WasiResult<u32> {
+        mem[..5].copy_from_slice(b"a.txt");
+        match ctx.path_open(mem, PREOPEN_FD as i32, 0, 0, 5, 0, 0, 0, 0, 16) {
+            ERRNO_SUCCESS => Ok(read_u32(mem, 16).unwrap()),
+            e => Err(e),
+        }
+    }
+
+    #[test]
+    fn files_limit() {
+        let mut ctx = ctx(Some(2));
+        let mut mem = [0; 32];
+
+        let fd = open(&mut ctx, &mut mem).unwrap();
+        assert_eq!(open(&mut ctx, &mut mem), Ok(fd + 1));
+        assert_eq!(open(&mut ctx, &mut mem), Err(ERRNO_MFILE));
+        // The closed fd is reused.
+        assert_eq!(ctx.fd_close(&mut mem, fd as i32), ERRNO_SUCCESS);
+        assert_eq!(open(&mut ctx, &mut mem), Ok(fd));
+    }
+
+    #[test]
+    fn readdir() {
+        let mut ctx = ctx(None);
+        let mut mem = [0; 256];
+        assert_eq!(
+            ctx.fd_readdir(&mut mem, PREOPEN_FD as i32, 0, 128, 0, 128),
+            ERRNO_SUCCESS
+        );
+        let used = read_u32(&mem, 128).unwrap() as usize;
+        let mut names = vec![];
+        let mut offset = 0;
+        while offset < used {
+            let len = read_u32(&mem, offset as i32 + 16).unwrap() as usize;
+            names.push(std::str::from_utf8(&mem[offset + 24..][..len]).unwrap());
+            offset += 24 + len;
+        }
+        assert_eq!(names, [".", "..", "a.txt"]);
+    }
+
+    #[test]
+    fn filestat_time() {
+        let mut ctx = ctx(None);
+        let mut mem = [0; 64];
+        let fd = open(&mut ctx, &mut mem).unwrap();
+        assert_eq!(ctx.fd_filestat_get(&mut mem, fd as i32, 0), ERRNO_SUCCESS);
+        let time = u64::from_le_bytes(mem[48..56].try_into().unwrap());
+        assert_ne!(time, 0);
+        assert_eq!(time, ctx.time());
+    }
+
+    #[test]
+    fn stdin() {
+        let mut ctx = ctx(None);
+        let mut mem = [0; 16];
+        assert_eq!(ctx.fd_read(&mut mem, 0, 0, 0, 8), ERRNO_NOTSUP);
+    }
+}
@@ -2,12 +2,15 @@
 
 #![warn(missing_docs)]
 
-use ayaka_plugin::*;
+mod wasi;
+
+use ayaka_plugin::{wasi::*, *};
 use std::{
     collections::HashMap,
     sync::{Arc, Mutex},
 };
 use wasmtime::*;
+use wasmtime_wasi::{I32Exit, WasiCtx};
 
 unsafe fn mem_slice<'a, T: 'a>(
     store: impl Into<StoreContext<'a, T>>,
@@ -33,11 +36,100 @@ unsafe fn mem_slice_mut<'a, T: 'a>(
         .get_unchecked_mut(..len as usize)
 }
 
-type HostStore = Arc<Mutex<Store<()>>>;
+/// Limits the linear memory of the running module.
+#[derive(Default)]
+struct MemoryLimiter {
+    limits: ModuleLimits,
+    exceeded: Option<LimitError>,
+}
+
+impl MemoryLimiter {
+    fn new(limits: ModuleLimits) -> Self {
+        Self {
+            limits,
+            exceeded: None,
+        }
+    }
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(&mut self, _current: usize, desired: usize, _maximum: Option<usize>) -> bool {
+        match self.limits.check_memory(desired) {
+            Ok(()) => true,
+            Err(e) => {
+                self.exceeded = Some(e);
+                false
+            }
+        }
+    }
+
+    fn table_growing(&mut self, _current: u32, _desired: u32, _maximum: Option<u32>) -> bool {
+        true
+    }
+}
+
+/// The data of the running module.
+#[derive(Default)]
+struct HostData {
+    limiter: MemoryLimiter,
+    wasi: Option<WasiCtx>,
+}
+
+// Runs with the limits and WASI context of a module,
+// and restores the ones of the caller.
+// The growing fails and the module usually traps, so the error is replaced.
+fn with_limits<T>(
+    mut store: StoreContextMut<HostData>,
+    limits: ModuleLimits,
+    wasi: &Option<WasiCtx>,
+    f: impl FnOnce(StoreContextMut<HostData>) -> Result<T>,
+) -> Result<T> {
+    let data = HostData {
+        limiter: MemoryLimiter::new(limits),
+        wasi: wasi.clone(),
+    };
+    let caller = std::mem::replace(store.data_mut(), data);
+    let res = f(store.as_context_mut());
+    let data = std::mem::replace(store.data_mut(), caller);
+    match (res, data.limiter.exceeded) {
+        (Err(_), Some(e)) => Err(e.into()),
+        (Err(e), None) => match e.downcast_ref::<I32Exit>() {
+            Some(exit) => Err(WasiExit(exit.0).into()),
+            None => Err(e),
+        },
+        (res, _) => res,
+    }
+}
+
+type HostStore = Arc<Mutex<Store<HostData>>>;
+
+fn set_fuel(store: &mut Store<HostData>, fuel: u64) -> Result<()> {
+    // The remaining fuel may be negative after exhausted.
+    if store.consume_fuel(0).is_err() {
+        store.add_fuel(fuel)?;
+    }
+    let remaining = store.consume_fuel(0)?;
+    if remaining > fuel {
+        store.consume_fuel(remaining - fuel)?;
+    } else {
+        store.add_fuel(fuel - remaining)?;
+    }
+    Ok(())
+}
+
+fn fuel_exhausted<T>(res: &Result<T>) -> bool {
+    match res {
+        Err(e) => e.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel),
+        Ok(_) => false,
+    }
+}
 
 /// A Wasmtime [`Instance`].
 pub struct WasmtimeModule {
     store: HostStore,
+    fuel: Option<u64>,
+    limits: ModuleLimits,
+    wasi: Option<WasiCtx>,
     instance: Instance,
     memory: Memory,
     abi_free: TypedFunc<(i32, i32), ()>,
@@ -45,9 +137,21 @@ pub struct WasmtimeModule {
 }
 
 impl WasmtimeModule {
-    fn new(store: HostStore, module: &Module, linker: &wasmtime::Linker<()>) -> Result<Self> {
+    fn new(
+        store: HostStore,
+        fuel: Option<u64>,
+        limits: ModuleLimits,
+        wasi: Option<WasiCtx>,
+        module: &Module,
+        linker: &wasmtime::Linker<HostData>,
+    ) -> Result<Self> {
         let mut inner_store = store.lock().unwrap();
-        let instance = linker.instantiate(inner_store.as_context_mut(), module)?;
+        if let Some(fuel) = fuel {
+            set_fuel(&mut inner_store, fuel)?;
+        }
+        let instance = with_limits(inner_store.as_context_mut(), limits, &wasi, |store| {
+            linker.instantiate(store, module)
+        })?;
         let memory = instance
             .get_memory(inner_store.as_context_mut(), MEMORY_NAME)
             .ok_or_else(|| anyhow!("cannot get memory"))?;
@@ -56,6 +160,9 @@ impl WasmtimeModule {
         drop(inner_store);
         Ok(Self {
             store,
+            fuel,
+            limits,
+            wasi,
             instance,
             memory,
             abi_free,
@@ -65,7 +172,19 @@ impl WasmtimeModule {
 
     fn call_impl<T>(
         &self,
-        mut store: StoreContextMut<()>,
+        store: StoreContextMut<HostData>,
+        name: &str,
+        data: &[u8],
+        f: impl FnOnce(&[u8]) -> Result<T>,
+    ) -> Result<T> {
+        with_limits(store, self.limits, &self.wasi, |store| {
+            self.call_inner(store, name, data, f)
+        })
+    }
+
+    fn call_inner<T>(
+        &self,
+        mut store: StoreContextMut<HostData>,
         name: &str,
         data: &[u8],
         f: impl FnOnce(&[u8]) -> Result<T>,
@@ -105,7 +224,18 @@ impl RawModule for WasmtimeModule {
     type Func = Func;
 
     fn call<T>(&self, name: &str, data: &[u8], f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
-        self.call_impl(self.store.lock().unwrap().as_context_mut(), name, data, f)
+        let mut store = self.store.lock().unwrap();
+        if let Some(fuel) = self.fuel {
+            set_fuel(&mut store, fuel)?;
+        }
+        let res = self.call_impl(store.as_context_mut(), name, data, f);
+        if fuel_exhausted(&res) {
+            return Err(LimitError::FuelExhausted {
+                method: name.to_string(),
+            }
+            .into());
+        }
+        res
     }
 }
 
@@ -113,29 +243,64 @@ impl RawModule for WasmtimeModule {
 pub struct WasmtimeLinker {
     engine: Engine,
     store: HostStore,
-    linker: wasmtime::Linker<()>,
+    fuel: Option<u64>,
+    limits: ModuleLimits,
+    wasi: Option<WasiCtx>,
+    linker: wasmtime::Linker<HostData>,
 }
 
 impl ayaka_plugin::Linker<WasmtimeModule> for WasmtimeLinker {
-    type Config = ();
+    type Config = LinkerConfig;
 
-    fn new(_: ()) -> Result<Self> {
-        let engine = Engine::default();
-        let store = Store::new(&engine, ());
-        let linker = wasmtime::Linker::new(&engine);
+    fn new(config: LinkerConfig) -> Result<Self> {
+        let mut engine_config = Config::default();
+        engine_config.consume_fuel(config.fuel.is_some());
+        let engine = Engine::new(&engine_config)?;
+        let mut store = Store::new(&engine, HostData::default());
+        store.limiter(|data| &mut data.limiter);
+        let mut linker = wasmtime::Linker::new(&engine);
+        linker.allow_shadowing(true);
         Ok(Self {
             engine,
             store: Arc::new(Mutex::new(store)),
+            fuel: config.fuel,
+            limits: ModuleLimits::default(),
+            wasi: None,
             linker,
         })
     }
 
-    fn create(&self, binary: &[u8]) -> Result<WasmtimeModule> {
+    fn create_with(&self, binary: &[u8], granted: impl Fn(&str) -> bool) -> Result<WasmtimeModule> {
         let module = Module::new(&self.engine, binary)?;
-        let host = WasmtimeModule::new(self.store.clone(), &module, &self.linker)?;
+        PermissionError::check(module.imports().map(|i| (i.module(), i.name())), granted)?;
+        let host = WasmtimeModule::new(
+            self.store.clone(),
+            self.fuel,
+            self.limits,
+            self.wasi.clone(),
+            &module,
+            &self.linker,
+        )?;
         Ok(host)
     }
 
+    fn set_limits(&mut self, limits: ModuleLimits) {
+        self.limits = limits;
+    }
+
+    fn import_wasi(&mut self, config: WasiConfig) -> Result<()> {
+        if self.wasi.is_none() {
+            // The functions get the context of the running module.
+            wasmtime_wasi::add_to_linker(&mut self.linker, |data: &mut HostData| {
+                data.wasi
+                    .as_mut()
+                    .expect("WASI is not imported by the module")
+            })?;
+        }
+        self.wasi = Some(wasi::wasi_ctx(config)?);
+        Ok(())
+    }
+
     fn import(&mut self, ns: impl Into<String>, funcs: HashMap<String, Func>) -> Result<()> {
         let ns = ns.into();
         let store = self.store.lock().unwrap();
@@ -151,7 +316,7 @@ impl ayaka_plugin::Linker<WasmtimeModule> for WasmtimeLinker {
     ) -> Func {
         Func::wrap(
             self.store.lock().unwrap().as_context_mut(),
-            move |mut store: Caller<()>, len: i32, data: i32| unsafe {
+            move |mut store: Caller<HostData>, len: i32, data: i32| unsafe {
                 let memory = store
                     .get_export(MEMORY_NAME)
                     .ok_or_else(|| anyhow!("cannot get memory"))?
@@ -179,7 +344,7 @@ impl ayaka_plugin::Linker<WasmtimeModule> for WasmtimeLinker {
 
 /// A Wasmtime [`StoreContextMut`].
 pub struct WasmtimeLinkerHandle<'a> {
-    store: StoreContextMut<'a, ()>,
+    store: StoreContextMut<'a, HostData>,
     memory: Memory,
 }
 
@@ -0,0 +1,308 @@
+//! The WASI files of `wasmtime-wasi` over a [`WasiConfig`].
+
+use ayaka_plugin::{wasi::*, Result};
+use std::{
+    any::Any,
+    io::{IoSlice, IoSliceMut, Read, Seek, SeekFrom},
+    sync::{Arc, Mutex},
+};
+use vfs::{error::VfsErrorKind, SeekAndRead, VfsError, VfsFileType, VfsPath};
+use wasi_common::{
+    dir::{OpenResult, ReaddirCursor, ReaddirEntity},
+    file::{FdFlags, FileType, Filestat, OFlags},
+    snapshots::preview_1::types::Errno,
+    Error, ErrorExt, SystemTimeSpec, WasiDir, WasiFile,
+};
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder};
+
+fn vfs_error(e: VfsError) -> Error {
+    match e.kind() {
+        VfsErrorKind::FileNotFound => Error::not_found(),
+        VfsErrorKind::InvalidPath => Error::invalid_argument(),
+        VfsErrorKind::NotSupported => Error::not_supported(),
+        VfsErrorKind::IoError(e) if e.kind() == std::io::ErrorKind::NotFound => Error::not_found(),
+        _ => Error::io(),
+    }
+}
+
+fn rofs<T>() -> Result<T, Error> {
+    Err(Errno::Rofs.into())
+}
+
+/// Creates the WASI context with the config.
+pub(crate) fn wasi_ctx(config: WasiConfig) -> Result<WasiCtx> {
+    let ctx = WasiCtxBuilder::new()
+        .stdin(Box::new(Stdin))
+        .stdout(Box::new(LogFile(Mutex::new(config.stdout()))))
+        .stderr(Box::new(LogFile(Mutex::new(config.stderr()))))
+        .build();
+    let root = VfsDir {
+        config: Arc::new(config),
+        components: vec![],
+        _token: None,
+    };
+    ctx.push_preopened_dir(Box::new(root), PREOPEN_NAME)?;
+    Ok(ctx)
+}
+
+/// A read-only directory in the root directory.
+struct VfsDir {
+    config: Arc<WasiConfig>,
+    components: Vec<String>,
+    _token: Option<FileToken>,
+}
+
+impl VfsDir {
+    fn resolve(&self, path: &str) -> Result<(Vec<String>, VfsPath), Error> {
+        let components = resolve_path(&self.components, path).map_err(|e| match e {
+            PathError::NotCapable => Errno::Notcapable.into(),
+            PathError::Invalid => Error::invalid_argument(),
+        })?;
+        let path = self.config.path(&components).map_err(vfs_error)?;
+        Ok((components, path))
+    }
+
+    fn filestat(&self, path: &VfsPath) -> Result<Filestat, Error> {
+        let meta = path.metadata().map_err(vfs_error)?;
+        let filetype = match meta.file_type {
+            VfsFileType::File => FileType::RegularFile,
+            VfsFileType::Directory => FileType::Directory,
+        };
+        Ok(filestat(&self.config, filetype, meta.len))
+    }
+}
+
+fn filestat(config: &WasiConfig, filetype: FileType, size: u64) -> Filestat {
+    Filestat {
+        device_id: 0,
+        inode: 0,
+        filetype,
+        nlink: 1,
+        size,
+        atim: Some(config.created),
+        mtim: Some(config.created),
+        ctim: Some(config.created),
+    }
+}
+
+#[async_trait::async_trait]
+impl WasiDir for VfsDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn open_file(
+        &self,
+        _symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        _read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<OpenResult, Error> {
+        if write
+            || oflags.intersects(OFlags::CREATE | OFlags::EXCLUSIVE | OFlags::TRUNCATE)
+            || fdflags.contains(FdFlags::APPEND)
+        {
+            return rofs();
+        }
+        let (components, path) = self.resolve(path)?;
+        let meta = path.metadata().map_err(vfs_error)?;
+        if meta.file_type == VfsFileType::File && oflags.contains(OFlags::DIRECTORY) {
+            return Err(Error::not_dir());
+        }
+        let token = self.config.open_token().ok_or(Errno::Mfile)?;
+        let res = match meta.file_type {
+            VfsFileType::Directory => OpenResult::Dir(Box::new(VfsDir {
+                config: self.config.clone(),
+                components,
+                _token: Some(token),
+            })),
+            VfsFileType::File => OpenResult::File(Box::new(VfsFile {
+                config: self.config.clone(),
+                file: Mutex::new(path.open_file().map_err(vfs_error)?),
+                size: meta.len,
+                _token: token,
+            })),
+        };
+        Ok(res)
+    }
+
+    async fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<ReaddirEntity, Error>> + Send>, Error> {
+        let path = self.config.path(&self.components).map_err(vfs_error)?;
+        let mut entries = path
+            .read_dir()
+            .map_err(vfs_error)?
+            .map(|p| {
+                let filetype = if p.is_dir().unwrap_or_default() {
+                    FileType::Directory
+                } else {
+                    FileType::RegularFile
+                };
+                (p.filename(), filetype)
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        let dots = [".", ".."].map(|name| (name.to_string(), FileType::Directory));
+        let entries = dots
+            .into_iter()
+            .chain(entries)
+            .enumerate()
+            .map(|(i, (name, filetype))| {
+                Ok(ReaddirEntity {
+                    next: ReaddirCursor::from(i as u64 + 1),
+                    inode: 0,
+                    name,
+                    filetype,
+                })
+            })
+            .skip(u64::from(cursor) as usize)
+            .collect::<Vec<_>>();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        self.filestat(&self.config.path(&self.components).map_err(vfs_error)?)
+    }
+
+    async fn get_path_filestat(
+        &self,
+        path: &str,
+        _follow_symlinks: bool,
+    ) -> Result<Filestat, Error> {
+        self.filestat(&self.resolve(path)?.1)
+    }
+
+    async fn create_dir(&self, _path: &str) -> Result<(), Error> {
+        rofs()
+    }
+
+    async fn symlink(&self, _old_path: &str, _new_path: &str) -> Result<(), Error> {
+        rofs()
+    }
+
+    async fn remove_dir(&self, _path: &str) -> Result<(), Error> {
+        rofs()
+    }
+
+    async fn unlink_file(&self, _path: &str) -> Result<(), Error> {
+        rofs()
+    }
+
+    async fn rename(
+        &self,
+        _path: &str,
+        _dest_dir: &dyn WasiDir,
+        _dest_path: &str,
+    ) -> Result<(), Error> {
+        rofs()
+    }
+
+    async fn hard_link(
+        &self,
+        _path: &str,
+        _target_dir: &dyn WasiDir,
+        _target_path: &str,
+    ) -> Result<(), Error> {
+        rofs()
+    }
+
+    async fn set_times(
+        &self,
+        _path: &str,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+        _follow_symlinks: bool,
+    ) -> Result<(), Error> {
+        rofs()
+    }
+}
+
+/// A read-only file in the root directory.
+struct VfsFile {
+    config: Arc<WasiConfig>,
+    file: Mutex<Box<dyn SeekAndRead + Send>>,
+    size: u64,
+    _token: FileToken,
+}
+
+#[async_trait::async_trait]
+impl WasiFile for VfsFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+
+    async fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(filestat(&self.config, FileType::RegularFile, self.size))
+    }
+
+    async fn read_vectored<'a>(&self, bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        Ok(self.file.lock().unwrap().read_vectored(bufs)? as u64)
+    }
+
+    async fn read_vectored_at<'a>(
+        &self,
+        bufs: &mut [IoSliceMut<'a>],
+        offset: u64,
+    ) -> Result<u64, Error> {
+        let mut file = self.file.lock().unwrap();
+        let pos = file.stream_position()?;
+        file.seek(SeekFrom::Start(offset))?;
+        let read = file.read_vectored(bufs);
+        file.seek(SeekFrom::Start(pos))?;
+        Ok(read? as u64)
+    }
+
+    async fn seek(&self, pos: SeekFrom) -> Result<u64, Error> {
+        Ok(self.file.lock().unwrap().seek(pos)?)
+    }
+}
+
+/// The stdin, which is not supported.
+struct Stdin;
+
+#[async_trait::async_trait]
+impl WasiFile for Stdin {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+
+    async fn read_vectored<'a>(&self, _bufs: &mut [IoSliceMut<'a>]) -> Result<u64, Error> {
+        Err(Error::not_supported())
+    }
+}
+
+/// The stdout or stderr written to the log.
+struct LogFile(Mutex<WasiLog>);
+
+#[async_trait::async_trait]
+impl WasiFile for LogFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    async fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::CharacterDevice)
+    }
+
+    async fn write_vectored<'a>(&self, bufs: &[IoSlice<'a>]) -> Result<u64, Error> {
+        let mut log = self.0.lock().unwrap();
+        let mut len = 0;
+        for buf in bufs {
+            log.write(buf);
+            len += buf.len() as u64;
+        }
+        Ok(len)
+    }
+}
@@ -10,6 +10,8 @@
 #[doc(no_inline)]
 pub use anyhow::{anyhow, Result};
 
+pub mod wasi;
+
 use serde::{de::DeserializeOwned, Serialize};
 use std::{collections::HashMap, marker::Tuple};
 
@@ -17,9 +19,153 @@ use std::{collections::HashMap, marker::Tuple};
 pub const ABI_ALLOC_NAME: &str = "__abi_alloc";
 /// The exported method `__abi_free`.
 pub const ABI_FREE_NAME: &str = "__abi_free";
+/// The exported method `__abi_version`.
+pub const ABI_VERSION_NAME: &str = "__abi_version";
 /// The default exported memory name.
 pub const MEMORY_NAME: &str = "memory";
 
+/// The config of the WASM backends.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LinkerConfig {
+    /// The fuel of each call from the host.
+    ///
+    /// Roughly, one unit of fuel is consumed by one instruction.
+    /// The calls are not limited if it is [`None`].
+    ///
+    /// A call returns [`LimitError::FuelExhausted`] when the fuel is exhausted.
+    /// The module is interrupted at any place,
+    /// and the later calls to it may fail.
+    pub fuel: Option<u64>,
+}
+
+/// The limits of a plugin module.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleLimits {
+    /// The max size of the linear memory, in bytes.
+    /// It is limited by the backend when the memory grows,
+    /// see [`Linker::set_limits`].
+    pub memory: Option<usize>,
+    /// The max size of the args or the returns passed in a call, in bytes.
+    pub payload: Option<usize>,
+}
+
+impl ModuleLimits {
+    /// Checks the size of the linear memory to grow to.
+    pub fn check_memory(&self, size: usize) -> std::result::Result<(), LimitError> {
+        match self.memory {
+            Some(limit) if size > limit => Err(LimitError::MemoryExceeded { size, limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the size of the data passed in a call.
+    pub fn check_payload(&self, size: usize) -> std::result::Result<(), LimitError> {
+        match self.payload {
+            Some(limit) if size > limit => Err(LimitError::PayloadExceeded { size, limit }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The error when a plugin call exceeds the limits
+/// in [`LinkerConfig`] or [`ModuleLimits`].
+///
+/// It could be found by [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LimitError {
+    /// The fuel is exhausted.
+    FuelExhausted {
+        /// The called method.
+        method: String,
+    },
+    /// The linear memory grows larger than the limit.
+    MemoryExceeded {
+        /// The size of the memory to grow to.
+        size: usize,
+        /// The limit.
+        limit: usize,
+    },
+    /// The data passed in a call is larger than the limit.
+    PayloadExceeded {
+        /// The size of the data.
+        size: usize,
+        /// The limit.
+        limit: usize,
+    },
+    /// Too many files are opened.
+    FilesExceeded {
+        /// The limit.
+        limit: usize,
+    },
+}
+
+impl std::fmt::Display for LimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FuelExhausted { method } => {
+                write!(f, "fuel exhausted when calling \"{}\"", method)
+            }
+            Self::MemoryExceeded { size, limit } => write!(
+                f,
+                "growing memory to {} bytes exceeds the limit {} bytes",
+                size, limit
+            ),
+            Self::PayloadExceeded { size, limit } => write!(
+                f,
+                "payload size {} bytes exceeds the limit {} bytes",
+                size, limit
+            ),
+            Self::FilesExceeded { limit } => {
+                write!(f, "open files exceed the limit {}", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+/// The error when a module imports a function from a namespace not granted.
+///
+/// It could be found by [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionError {
+    /// The namespace of the import.
+    pub namespace: String,
+    /// The name of the import.
+    pub name: String,
+}
+
+impl PermissionError {
+    /// Checks the imports of a module, as pairs of namespaces and names.
+    pub fn check<'a>(
+        imports: impl IntoIterator<Item = (&'a str, &'a str)>,
+        granted: impl Fn(&str) -> bool,
+    ) -> std::result::Result<(), Self> {
+        for (namespace, name) in imports {
+            if !granted(namespace) {
+                return Err(Self {
+                    namespace: namespace.to_string(),
+                    name: name.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PermissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "import \"{}.{}\" is not granted",
+            self.namespace, self.name
+        )
+    }
+}
+
+impl std::error::Error for PermissionError {}
+
 /// Represents a raw plugin module.
 pub trait RawModule: Sized {
     /// The linker type that can create raw module.
@@ -35,28 +181,75 @@ pub trait RawModule: Sized {
     ///
     /// The args and returns are bytes.
     fn call<T>(&self, name: &str, args: &[u8], f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T>;
+
+    /// Calls a method by name, with the args and returns as values.
+    ///
+    /// The args and returns are passed by MessagePack with [`rmp_serde`] by default,
+    /// and the payloads are checked against the limits.
+    /// The backends in the same process take the owned args converted by `owned`,
+    /// and return the values directly.
+    fn call_value<P: Serialize, O: Send + 'static, R: DeserializeOwned + 'static>(
+        &self,
+        name: &str,
+        args: P,
+        _owned: impl FnOnce(P) -> O,
+        limits: &ModuleLimits,
+    ) -> Result<R> {
+        let data = rmp_serde::to_vec(&args)?;
+        limits.check_payload(data.len())?;
+        self.call(name, &data, |res| {
+            limits.check_payload(res.len())?;
+            let res = rmp_serde::from_slice(res)?;
+            Ok(res)
+        })
+    }
 }
 
 /// High-level wrapper for plugin module.
 pub struct PluginModule<M: RawModule> {
     module: M,
+    limits: ModuleLimits,
 }
 
 impl<M: RawModule> PluginModule<M> {
     /// Creates a wrapper on raw module.
     pub fn new(module: M) -> Self {
-        Self { module }
+        Self::with_limits(module, ModuleLimits::default())
+    }
+
+    /// Creates a wrapper on raw module, with limits.
+    pub fn with_limits(module: M, limits: ModuleLimits) -> Self {
+        Self { module, limits }
     }
 
     /// Call a method by name.
     ///
-    /// The args and returns are passed by MessagePack with [`rmp_serde`].
-    pub fn call<P: Serialize, R: DeserializeOwned>(&self, name: &str, args: P) -> Result<R> {
-        let data = rmp_serde::to_vec(&args)?;
-        self.module.call(name, &data, |res| {
-            let res = rmp_serde::from_slice(res)?;
-            Ok(res)
-        })
+    /// The args and returns are passed by MessagePack with [`rmp_serde`],
+    /// or directly by the backends in the same process.
+    pub fn call<P: Serialize + Send + 'static, R: DeserializeOwned + 'static>(
+        &self,
+        name: &str,
+        args: P,
+    ) -> Result<R> {
+        self.call_with(name, args, |args| args)
+    }
+
+    /// Call a method by name, with the borrowed args.
+    ///
+    /// The backends in the same process take the owned args converted by `owned`.
+    /// See [`RawModule::call_value`].
+    pub fn call_with<P: Serialize, O: Send + 'static, R: DeserializeOwned + 'static>(
+        &self,
+        name: &str,
+        args: P,
+        owned: impl FnOnce(P) -> O,
+    ) -> Result<R> {
+        self.module.call_value(name, args, owned, &self.limits)
+    }
+
+    /// Get the limits.
+    pub fn limits(&self) -> &ModuleLimits {
+        &self.limits
     }
 
     /// Get inner raw module.
@@ -74,9 +267,47 @@ pub trait Linker<M: RawModule>: Sized {
     fn new(config: Self::Config) -> Result<Self>;
 
     /// Create a raw module from binary.
-    fn create(&self, binary: &[u8]) -> Result<M>;
+    fn create(&self, binary: &[u8]) -> Result<M> {
+        self.create_with(binary, |_| true)
+    }
+
+    /// Create a raw module from binary,
+    /// if it only imports from the granted namespaces.
+    ///
+    /// It fails with [`PermissionError`] before instantiating the module otherwise.
+    fn create_with(&self, binary: &[u8], granted: impl Fn(&str) -> bool) -> Result<M>;
+
+    /// The names of the modules registered in the linker,
+    /// which are created without binaries.
+    fn registered(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Create a raw module registered in the linker by name.
+    fn create_registered(&self, name: &str) -> Result<M> {
+        Err(anyhow!("Module \"{}\" is not registered", name))
+    }
+
+    /// Set the limits of the modules created later.
+    ///
+    /// The linear memory of a module is limited when it grows,
+    /// and the call fails with [`LimitError::MemoryExceeded`].
+    /// The backends without linear memories ignore the limits.
+    fn set_limits(&mut self, _limits: ModuleLimits) {}
+
+    /// Import the WASI preview1 functions with the config,
+    /// for the modules created later.
+    ///
+    /// It fails if the backend doesn't support WASI.
+    fn import_wasi(&mut self, config: wasi::WasiConfig) -> Result<()> {
+        drop(config);
+        Err(anyhow!("WASI is not supported"))
+    }
 
     /// Import functions by namespace and names.
+    ///
+    /// The functions replace the imported ones with the same names,
+    /// and only affect the modules created later.
     fn import(&mut self, ns: impl Into<String>, funcs: HashMap<String, M::Func>) -> Result<()>;
 
     /// Wrap a function with args in bytes.
@@ -0,0 +1,211 @@
+//! WASI preview1 support for the plugins.
+//!
+//! The backends implement WASI over the same [`WasiConfig`]:
+//! the root directory is preopened read-only as `/`,
+//! the stdout and stderr are written to the log by lines,
+//! and reading the stdin is not supported.
+
+use std::{sync::Arc, time::SystemTime};
+use vfs::{VfsPath, VfsResult};
+
+/// The namespace of the WASI preview1 imports.
+pub const WASI_NAMESPACE: &str = "wasi_snapshot_preview1";
+
+/// The guest path of the preopened root directory.
+pub const PREOPEN_NAME: &str = "/";
+
+/// The max length of a line buffered from the stdout or stderr.
+/// A longer line is written to the log in pieces.
+const LINE_LIMIT: usize = 4096;
+
+/// The error when a plugin calls `proc_exit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WasiExit(pub i32);
+
+impl std::fmt::Display for WasiExit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "plugin exited with code {}", self.0)
+    }
+}
+
+impl std::error::Error for WasiExit {}
+
+/// The error when resolving a path in the root directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    /// The path is absolute, or escapes the root directory.
+    NotCapable,
+    /// The path contains invalid characters.
+    Invalid,
+}
+
+/// Resolves a path relative to a directory, by the components from the root.
+pub fn resolve_path(base: &[String], path: &str) -> Result<Vec<String>, PathError> {
+    if path.starts_with('/') {
+        return Err(PathError::NotCapable);
+    }
+    let mut components = base.to_vec();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop().ok_or(PathError::NotCapable)?;
+            }
+            _ if component.contains(['\\', '\0']) => return Err(PathError::Invalid),
+            _ => components.push(component.to_string()),
+        }
+    }
+    Ok(components)
+}
+
+/// The WASI config of a plugin module.
+#[derive(Debug, Clone)]
+pub struct WasiConfig {
+    /// The name of the plugin, as the target of the log.
+    pub name: String,
+    /// The root directory.
+    pub root: VfsPath,
+    /// The limit of the files and directories opened at the same time.
+    pub files: Option<usize>,
+    /// The timestamps of all files,
+    /// because the VFS doesn't provide them.
+    pub created: SystemTime,
+    opened: Arc<()>,
+}
+
+impl WasiConfig {
+    /// Creates the config for the plugin by name.
+    pub fn new(name: impl Into<String>, root: VfsPath) -> Self {
+        Self {
+            name: name.into(),
+            root,
+            files: None,
+            created: SystemTime::now(),
+            opened: Arc::new(()),
+        }
+    }
+
+    /// Limits the count of the files and directories opened at the same time.
+    ///
+    /// Opening a file returns `MFILE` when the limit is reached.
+    pub fn with_files_limit(mut self, limit: Option<usize>) -> Self {
+        self.files = limit;
+        self
+    }
+
+    /// Gets the path in the root directory by the components.
+    pub fn path(&self, components: &[String]) -> VfsResult<VfsPath> {
+        self.root.join(components.join("/"))
+    }
+
+    /// Counts a file or directory to be opened,
+    /// and returns [`None`] if the limit is reached.
+    ///
+    /// The file is counted until the token is dropped.
+    pub fn open_token(&self) -> Option<FileToken> {
+        let opened = Arc::strong_count(&self.opened) - 1;
+        if matches!(self.files, Some(limit) if opened >= limit) {
+            None
+        } else {
+            Some(FileToken(self.opened.clone()))
+        }
+    }
+
+    /// Creates the log of the stdout.
+    pub fn stdout(&self) -> WasiLog {
+        WasiLog::new(&self.name, log::Level::Info)
+    }
+
+    /// Creates the log of the stderr.
+    pub fn stderr(&self) -> WasiLog {
+        WasiLog::new(&self.name, log::Level::Warn)
+    }
+}
+
+/// The token held by an opened file or directory.
+#[derive(Debug)]
+pub struct FileToken(#[allow(dead_code)] Arc<()>);
+
+/// The stdout or stderr of a plugin, written to the log by lines.
+#[derive(Debug)]
+pub struct WasiLog {
+    target: String,
+    level: log::Level,
+    buf: Vec<u8>,
+}
+
+impl WasiLog {
+    fn new(name: &str, level: log::Level) -> Self {
+        Self {
+            target: format!("{}::<plugin>::{}", module_path!(), name),
+            level,
+            buf: vec![],
+        }
+    }
+
+    fn log(&self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        log::log!(target: &self.target, self.level, "{}", line.trim_end_matches('\r'));
+    }
+
+    /// Writes the data, and logs the complete lines.
+    pub fn write(&mut self, data: &[u8]) {
+        let mut buf = std::mem::take(&mut self.buf);
+        buf.extend_from_slice(data);
+        while let Some(i) = buf.iter().position(|b| *b == b'\n') {
+            let line = buf.drain(..=i).collect::<Vec<_>>();
+            self.log(&line[..i]);
+        }
+        while buf.len() >= LINE_LIMIT {
+            let line = buf.drain(..LINE_LIMIT).collect::<Vec<_>>();
+            self.log(&line);
+        }
+        self.buf = buf;
+    }
+}
+
+impl Drop for WasiLog {
+    fn drop(&mut self) {
+        if !self.buf.is_empty() {
+            self.log(&self.buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use vfs::MemoryFS;
+
+    #[test]
+    fn resolve() {
+        let base = vec!["a".to_string()];
+        assert_eq!(
+            resolve_path(&base, "./b/../c"),
+            Ok(vec!["a".into(), "c".into()])
+        );
+        assert_eq!(resolve_path(&base, ".."), Ok(vec![]));
+        assert_eq!(resolve_path(&base, "../.."), Err(PathError::NotCapable));
+        assert_eq!(resolve_path(&base, "/a"), Err(PathError::NotCapable));
+        assert_eq!(resolve_path(&base, "b\\c"), Err(PathError::Invalid));
+    }
+
+    #[test]
+    fn files_limit() {
+        let config = WasiConfig::new("test", MemoryFS::new().into()).with_files_limit(Some(2));
+        let token = config.open_token().unwrap();
+        let _token2 = config.open_token().unwrap();
+        assert!(config.open_token().is_none());
+        drop(token);
+        assert!(config.open_token().is_some());
+    }
+
+    #[test]
+    fn line_limit() {
+        let mut log = WasiConfig::new("test", MemoryFS::new().into()).stdout();
+        log.write(&[b'a'; LINE_LIMIT * 2 + 1]);
+        assert_eq!(log.buf.len(), 1);
+        log.write(b"b\nc");
+        assert_eq!(log.buf, b"c");
+    }
+}
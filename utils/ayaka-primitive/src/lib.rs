@@ -5,8 +5,10 @@
 
 mod line;
 mod raw_value;
+mod span;
 mod text;
 
 pub use line::*;
 pub use raw_value::*;
+pub use span::*;
 pub use text::*;
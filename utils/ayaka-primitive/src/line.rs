@@ -1,11 +1,13 @@
 use crate::*;
-use serde::Deserialize;
-use serde_with::rust::maps_duplicate_key_is_error;
-use std::collections::HashMap;
+use serde::{
+    de::{Error, MapAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use std::fmt::Display;
 
 /// Represents a line in a prograph.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
-#[serde(untagged)]
+#[serde(try_from = "RawLine")]
 pub enum Line {
     /// An empty line, usually fallbacks to the base language one.
     Empty,
@@ -16,9 +18,330 @@ pub enum Line {
         /// The switch items.
         switches: Vec<String>,
     },
+    /// Call a paragraph, and return to the next line after it ends.
+    Call {
+        /// The tag of the called paragraph.
+        call: Text,
+    },
+    /// Let the player input a text.
+    Input {
+        /// The name of the local variable to store the input.
+        input: String,
+        /// The prompt text.
+        prompt: Option<Text>,
+        /// The default value, stored before the player inputs.
+        default: Option<String>,
+        /// The validation script expression.
+        /// The input is rejected if it evaluates to false.
+        validate: Option<String>,
+    },
     /// Custom line types.
-    #[serde(with = "maps_duplicate_key_is_error")]
-    Custom(HashMap<String, RawValue>),
+    ///
+    /// The props are in the order of the source,
+    /// so that the commands in one line could be executed in order.
+    Custom(Vec<(String, RawValue)>),
+}
+
+/// A [`Line`] in a paragraph, with an optional identifier.
+///
+/// The identifier is specified by the `id` key of the line,
+/// and a text line with an identifier is specified by the `text` key:
+/// ``` yaml
+/// - id: greeting
+///   text: Hello!
+/// - id: choose
+///   switches:
+///     - Yes
+///     - No
+/// ```
+/// The identifier keeps the records and resources of a line,
+/// even if other lines are inserted before it.
+///
+/// A text line or switches could be timed by the `timeout` key, in milliseconds.
+/// The timed switches choose the `default` one when timed out:
+/// ``` yaml
+/// - text: Hurry up!
+///   timeout: 3000
+/// - switches:
+///     - Left
+///     - Right
+///   timeout: 5000
+///   default: 1
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawLine")]
+pub struct ParaLine {
+    /// The identifier of the line.
+    pub id: Option<String>,
+    /// The timeout of the line.
+    pub timeout: Option<LineTimeout>,
+    /// The line.
+    pub line: Line,
+    /// The location of the line in the paragraph files.
+    /// It is filled when the paragraphs are loaded.
+    pub source: SourceSpan,
+}
+
+/// The timeout of a [`ParaLine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineTimeout {
+    /// The duration in milliseconds.
+    pub duration: u64,
+    /// The index of the switch chosen when timed out.
+    pub default: Option<usize>,
+}
+
+/// The error when parsing a [`Line`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineParseError {
+    /// The text in the line is invalid.
+    Text(TextParseError),
+    /// The line contains keys which are not allowed by its type.
+    UnknownKeys {
+        /// The key which determines the type of the line.
+        kind: String,
+        /// The keys not allowed.
+        keys: Vec<String>,
+    },
+    /// The line contains invalid values for its type.
+    InvalidValues {
+        /// The key which determines the type of the line.
+        kind: String,
+    },
+}
+
+impl From<TextParseError> for LineParseError {
+    fn from(e: TextParseError) -> Self {
+        Self::Text(e)
+    }
+}
+
+impl Display for LineParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text(e) => write!(f, "{}", e),
+            Self::UnknownKeys { kind, keys } => write!(
+                f,
+                "unknown keys in a `{}` line: {}",
+                kind,
+                keys.iter()
+                    .map(|key| format!("`{}`", key))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::InvalidValues { kind } => write!(f, "invalid values in a `{}` line", kind),
+        }
+    }
+}
+
+impl std::error::Error for LineParseError {}
+
+// The text is parsed after the line type is determined,
+// so that the text parse error won't be swallowed by `untagged`.
+// The unknown keys make the line fallback to `Custom`, and they are reported there.
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum RawLine {
+    Empty,
+    Switch {
+        id: Option<RawText>,
+        switches: Vec<String>,
+        timeout: Option<u64>,
+        default: Option<usize>,
+    },
+    Call {
+        id: Option<RawText>,
+        call: RawText,
+    },
+    Input {
+        id: Option<RawText>,
+        input: String,
+        prompt: Option<RawText>,
+        default: Option<RawText>,
+        validate: Option<String>,
+    },
+    #[serde(deserialize_with = "ordered_map")]
+    Custom(Vec<(String, RawValue)>),
+    Text(RawText),
+}
+
+// Only the scalars could be texts.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawText {
+    Bool(bool),
+    Num(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl From<RawText> for RawValue {
+    fn from(value: RawText) -> Self {
+        match value {
+            RawText::Bool(b) => Self::Bool(b),
+            RawText::Num(i) => Self::Num(i),
+            RawText::Float(f) => Self::Float(f),
+            RawText::Str(s) => Self::Str(s),
+        }
+    }
+}
+
+// Keeps the order of the keys, and rejects the duplicate keys.
+fn ordered_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, RawValue)>, D::Error> {
+    struct OrderedMapVisitor;
+
+    impl<'de> Visitor<'de> for OrderedMapVisitor {
+        type Value = Vec<(String, RawValue)>;
+
+        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+            formatter.write_str("a map")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+            let mut props: Vec<(String, RawValue)> =
+                Vec::with_capacity(map.size_hint().unwrap_or_default());
+            while let Some((key, value)) = map.next_entry()? {
+                if props.iter().any(|(k, _)| k == &key) {
+                    return Err(A::Error::custom(format!(
+                        "invalid entry: found duplicate key `{}`",
+                        key
+                    )));
+                }
+                props.push((key, value));
+            }
+            Ok(props)
+        }
+    }
+
+    deserializer.deserialize_map(OrderedMapVisitor)
+}
+
+fn remove_prop(props: &mut Vec<(String, RawValue)>, key: &str) -> Option<RawValue> {
+    props
+        .iter()
+        .position(|(k, _)| k == key)
+        .map(|i| props.remove(i).1)
+}
+
+// The keys allowed by the builtin line types, except `text`.
+const BUILTIN_KEYS: &[(&str, &[&str])] = &[
+    ("switches", &["id", "switches", "timeout", "default"]),
+    ("call", &["id", "call"]),
+    ("input", &["id", "input", "prompt", "default", "validate"]),
+];
+
+fn check_builtin_keys(props: &[(String, RawValue)]) -> Result<(), LineParseError> {
+    for (kind, allowed) in BUILTIN_KEYS {
+        if props.iter().any(|(key, _)| key == kind) {
+            let keys = props
+                .iter()
+                .map(|(key, _)| key)
+                .filter(|key| !allowed.contains(&key.as_str()))
+                .cloned()
+                .collect::<Vec<_>>();
+            let kind = kind.to_string();
+            // All keys are allowed, but the line failed to be deserialized as its type.
+            return Err(if keys.is_empty() {
+                LineParseError::InvalidValues { kind }
+            } else {
+                LineParseError::UnknownKeys { kind, keys }
+            });
+        }
+    }
+    Ok(())
+}
+
+fn parse_id(id: RawText) -> String {
+    RawValue::from(id).into_str()
+}
+
+fn parse_text(text: RawValue) -> Result<Text, TextParseError> {
+    Text::try_from(text.get_str().as_ref())
+}
+
+impl TryFrom<RawLine> for ParaLine {
+    type Error = LineParseError;
+
+    fn try_from(value: RawLine) -> Result<Self, Self::Error> {
+        let (id, timeout, line) = match value {
+            RawLine::Empty => (None, None, Line::Empty),
+            RawLine::Switch {
+                id,
+                switches,
+                timeout,
+                default,
+            } => (
+                id.map(parse_id),
+                timeout.map(|duration| LineTimeout { duration, default }),
+                Line::Switch { switches },
+            ),
+            RawLine::Call { id, call } => (
+                id.map(parse_id),
+                None,
+                Line::Call {
+                    call: parse_text(call.into())?,
+                },
+            ),
+            RawLine::Input {
+                id,
+                input,
+                prompt,
+                default,
+                validate,
+            } => (
+                id.map(parse_id),
+                None,
+                Line::Input {
+                    input,
+                    prompt: prompt.map(|text| parse_text(text.into())).transpose()?,
+                    default: default.map(|text| RawValue::from(text).into_str()),
+                    validate,
+                },
+            ),
+            RawLine::Custom(mut props) => {
+                check_builtin_keys(&props)?;
+                let id = remove_prop(&mut props, "id").map(RawValue::into_str);
+                let is_text = props.iter().any(|(key, _)| key == "text")
+                    && props
+                        .iter()
+                        .all(|(key, _)| key == "text" || key == "timeout");
+                if is_text {
+                    let timeout = remove_prop(&mut props, "timeout")
+                        .map(|duration| match duration {
+                            RawValue::Num(duration) if duration >= 0 => Ok(LineTimeout {
+                                duration: duration as u64,
+                                default: None,
+                            }),
+                            _ => Err(LineParseError::InvalidValues {
+                                kind: "text".to_string(),
+                            }),
+                        })
+                        .transpose()?;
+                    let text = remove_prop(&mut props, "text").unwrap();
+                    (id, timeout, Line::Text(parse_text(text)?))
+                } else {
+                    (id, None, Line::Custom(props))
+                }
+            }
+            RawLine::Text(text) => (None, None, Line::Text(parse_text(text.into())?)),
+        };
+        Ok(Self {
+            id,
+            timeout,
+            line,
+            source: SourceSpan::default(),
+        })
+    }
+}
+
+impl TryFrom<RawLine> for Line {
+    type Error = LineParseError;
+
+    fn try_from(value: RawLine) -> Result<Self, Self::Error> {
+        ParaLine::try_from(value).map(|line| line.line)
+    }
 }
 
 #[cfg(test)]
@@ -27,7 +350,6 @@ mod test {
         text::test::{str, text},
         *,
     };
-    use std::collections::HashMap;
 
     #[test]
     fn parse() {
@@ -39,16 +361,18 @@ mod test {
   - b
 - video: 0
 -
+- call: menu
+- input: name
+  prompt: Name?
+  default: Ayaka
+  validate: len($name) > 0
         "#;
         let lines: Vec<Line> = serde_yaml::from_str(lines).unwrap();
-        assert_eq!(lines.len(), 5);
+        assert_eq!(lines.len(), 7);
         assert_eq!(lines[0], Line::Text(text(vec![str("abc")])));
         assert_eq!(
             lines[1],
-            Line::Custom(HashMap::from([(
-                "exec".to_string(),
-                RawValue::Str("$a".to_string())
-            )]))
+            Line::Custom(vec![("exec".to_string(), RawValue::Str("$a".to_string()))])
         );
         assert_eq!(
             lines[2],
@@ -58,8 +382,155 @@ mod test {
         );
         assert_eq!(
             lines[3],
-            Line::Custom(HashMap::from([("video".to_string(), RawValue::Num(0))]))
+            Line::Custom(vec![("video".to_string(), RawValue::Num(0))])
         );
         assert_eq!(lines[4], Line::Empty);
+        assert_eq!(
+            lines[5],
+            Line::Call {
+                call: text(vec![str("menu")])
+            }
+        );
+        assert_eq!(
+            lines[6],
+            Line::Input {
+                input: "name".to_string(),
+                prompt: Some(text(vec![str("Name?")])),
+                default: Some("Ayaka".to_string()),
+                validate: Some("len($name) > 0".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn custom_order() {
+        let lines = r#"
+- bgm: b
+  bg: a
+  show: c
+- bg: a
+  bg: b
+        "#;
+        let line: Vec<Line> =
+            serde_yaml::from_str(&lines[..lines.find("- bg: a").unwrap()]).unwrap();
+        assert_eq!(
+            line[0],
+            Line::Custom(vec![
+                ("bgm".to_string(), RawValue::Str("b".to_string())),
+                ("bg".to_string(), RawValue::Str("a".to_string())),
+                ("show".to_string(), RawValue::Str("c".to_string())),
+            ])
+        );
+        assert!(serde_yaml::from_str::<Vec<Line>>(lines).is_err());
+    }
+
+    #[test]
+    fn id() {
+        let lines = r#"
+- abc
+- id: hello
+  text: Hello
+- id: 1
+  switches:
+  - a
+- id: 0
+  bg: a
+  bgm: b
+        "#;
+        let lines: Vec<ParaLine> = serde_yaml::from_str(lines).unwrap();
+        assert_eq!(lines[0].id, None);
+        assert_eq!(lines[0].line, Line::Text(text(vec![str("abc")])));
+        assert_eq!(lines[1].id.as_deref(), Some("hello"));
+        assert_eq!(lines[1].line, Line::Text(text(vec![str("Hello")])));
+        assert_eq!(lines[2].id.as_deref(), Some("1"));
+        assert_eq!(
+            lines[2].line,
+            Line::Switch {
+                switches: vec!["a".to_string()]
+            }
+        );
+        assert_eq!(lines[3].id.as_deref(), Some("0"));
+        assert_eq!(
+            lines[3].line,
+            Line::Custom(vec![
+                ("bg".to_string(), RawValue::Str("a".to_string())),
+                ("bgm".to_string(), RawValue::Str("b".to_string())),
+            ])
+        );
+    }
+
+    #[test]
+    fn timeout() {
+        let lines = r#"
+- abc
+- text: Hurry
+  timeout: 3000
+- switches:
+  - a
+  - b
+  timeout: 5000
+  default: 1
+- timeout: 1000
+  bg: a
+        "#;
+        let lines: Vec<ParaLine> = serde_yaml::from_str(lines).unwrap();
+        assert_eq!(lines[0].timeout, None);
+        assert_eq!(
+            lines[1].timeout,
+            Some(LineTimeout {
+                duration: 3000,
+                default: None
+            })
+        );
+        assert_eq!(lines[1].line, Line::Text(text(vec![str("Hurry")])));
+        assert_eq!(
+            lines[2].timeout,
+            Some(LineTimeout {
+                duration: 5000,
+                default: Some(1)
+            })
+        );
+        // The props of the custom lines are kept.
+        assert_eq!(lines[3].timeout, None);
+        assert_eq!(
+            lines[3].line,
+            Line::Custom(vec![
+                ("timeout".to_string(), RawValue::Num(1000)),
+                ("bg".to_string(), RawValue::Str("a".to_string())),
+            ])
+        );
+        // The negative and non-numeric timeouts are rejected.
+        for (line, kind) in [
+            ("- text: a\n  timeout: -1", "text"),
+            ("- text: a\n  timeout: soon", "text"),
+            ("- switches: [a]\n  timeout: -1", "switches"),
+        ] {
+            let err = serde_yaml::from_str::<Vec<ParaLine>>(line)
+                .unwrap_err()
+                .to_string();
+            assert!(err.starts_with(&format!("invalid values in a `{}` line", kind)));
+        }
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let err = serde_yaml::from_str::<Vec<Line>>("- switches: [a]\n  typo: 1")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("unknown keys in a `switches` line: `typo`"));
+        let err = serde_yaml::from_str::<Vec<Line>>("- call: menu\n  to: end")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("unknown keys in a `call` line: `to`"));
+        let err = serde_yaml::from_str::<Vec<Line>>("- switches: a")
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("invalid values in a `switches` line"));
+    }
+
+    #[test]
+    fn parse_error() {
+        let err = serde_yaml::from_str::<Vec<Line>>("- abc\n- a}c").unwrap_err();
+        assert!(err.to_string().starts_with("unexpected `}`"));
     }
 }
@@ -1,15 +1,39 @@
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+};
 
 /// The basic and only type used in scripts.
 /// ```
 /// # use ayaka_primitive::RawValue;
+/// # use std::collections::BTreeMap;
 /// assert_eq!(serde_yaml::from_str::<RawValue>("~").unwrap(), RawValue::Unit);
 /// assert_eq!(serde_yaml::from_str::<RawValue>("true").unwrap(), RawValue::Bool(true));
 /// assert_eq!(serde_yaml::from_str::<RawValue>("123").unwrap(), RawValue::Num(123));
+/// assert_eq!(serde_yaml::from_str::<RawValue>("1.5").unwrap(), RawValue::Float(1.5));
 /// assert_eq!(serde_yaml::from_str::<RawValue>("\"hello\"").unwrap(), RawValue::Str("hello".to_string()));
+/// assert_eq!(
+///     serde_yaml::from_str::<RawValue>("[1, a]").unwrap(),
+///     RawValue::List(vec![RawValue::Num(1), RawValue::Str("a".to_string())])
+/// );
+/// assert_eq!(
+///     serde_yaml::from_str::<RawValue>("{a: 1}").unwrap(),
+///     RawValue::Map(BTreeMap::from([("a".to_string(), RawValue::Num(1))]))
+/// );
 /// ```
-#[derive(Debug, Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+///
+/// The values are compared structurally, and a [`RawValue::Float`] is compared
+/// with [`f64::total_cmp`], so that [`RawValue`] could be used as keys.
+/// A [`RawValue::Num`] and a [`RawValue::Float`] are compared numerically:
+/// ```
+/// # use ayaka_primitive::RawValue;
+/// assert_eq!(RawValue::Num(1), RawValue::Float(1.0));
+/// assert!(RawValue::Num(1) < RawValue::Float(1.5));
+/// ```
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 #[serde(untagged)]
 pub enum RawValue {
     /// The unit type. It is empty, just like [`None`] or [`()`] in Rust.
@@ -19,12 +43,18 @@ pub enum RawValue {
     Bool(bool),
     /// The number type. It's [`i64`].
     Num(i64),
+    /// The float number type. It's [`f64`].
+    Float(f64),
     /// The string type.
     Str(String),
+    /// The list type.
+    List(Vec<RawValue>),
+    /// The map type. The keys are strings.
+    Map(BTreeMap<String, RawValue>),
 }
 
 /// Represents the type of [`RawValue`].
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum ValueType {
     /// The unit type.
     Unit,
@@ -32,8 +62,14 @@ pub enum ValueType {
     Bool,
     /// The number type.
     Num,
+    /// The float number type.
+    Float,
     /// The string type.
     Str,
+    /// The list type.
+    List,
+    /// The map type.
+    Map,
 }
 
 impl RawValue {
@@ -43,14 +79,17 @@ impl RawValue {
             Self::Unit => ValueType::Unit,
             Self::Bool(_) => ValueType::Bool,
             Self::Num(_) => ValueType::Num,
+            Self::Float(_) => ValueType::Float,
             Self::Str(_) => ValueType::Str,
+            Self::List(_) => ValueType::List,
+            Self::Map(_) => ValueType::Map,
         }
     }
 
     /// Gets a boolean from the value:
     /// * A [`RawValue::Unit`] converts to `false`.
-    /// * A [`RawValue::Num`] converts to `false` if and only if it's zero.
-    /// * A [`RawValue::Str`] converts to `false` if and only if it's empty.
+    /// * A [`RawValue::Num`] or [`RawValue::Float`] converts to `false` if and only if it's zero.
+    /// * A [`RawValue::Str`], [`RawValue::List`] or [`RawValue::Map`] converts to `false` if and only if it's empty.
     ///
     /// ```
     /// # use ayaka_primitive::RawValue;
@@ -58,24 +97,33 @@ impl RawValue {
     /// assert!(!unit_value.get_bool());
     /// let num_value = RawValue::Num(123);
     /// assert!(num_value.get_bool());
+    /// let float_value = RawValue::Float(0.0);
+    /// assert!(!float_value.get_bool());
     /// let str_value = RawValue::Str("hello".to_string());
     /// assert!(str_value.get_bool());
     /// let empty_str_value = RawValue::Str(String::default());
     /// assert!(!empty_str_value.get_bool());
+    /// let empty_list_value = RawValue::List(vec![]);
+    /// assert!(!empty_list_value.get_bool());
     /// ```
     pub fn get_bool(&self) -> bool {
         match self {
             Self::Unit => false,
             Self::Bool(b) => *b,
             Self::Num(i) => *i != 0,
+            Self::Float(f) => *f != 0.0,
             Self::Str(s) => !s.is_empty(),
+            Self::List(l) => !l.is_empty(),
+            Self::Map(m) => !m.is_empty(),
         }
     }
 
     /// Gets a number from the value:
     /// * A [`RawValue::Unit`] converts to 0.
     /// * A [`RawValue::Bool`] converts `false` to 0 and `true` to 1.
+    /// * A [`RawValue::Float`] truncates to the integer part.
     /// * A [`RawValue::Str`] converts to the length of the string.
+    /// * A [`RawValue::List`] or [`RawValue::Map`] converts to the count of the items.
     ///
     /// ```
     /// # use ayaka_primitive::RawValue;
@@ -83,22 +131,49 @@ impl RawValue {
     /// assert_eq!(unit_value.get_num(), 0);
     /// let bool_value = RawValue::Bool(true);
     /// assert_eq!(bool_value.get_num(), 1);
+    /// let float_value = RawValue::Float(-1.5);
+    /// assert_eq!(float_value.get_num(), -1);
     /// let str_value = RawValue::Str("hello".to_string());
     /// assert_eq!(str_value.get_num(), 5);
+    /// let list_value = RawValue::List(vec![RawValue::Unit; 3]);
+    /// assert_eq!(list_value.get_num(), 3);
     /// ```
     pub fn get_num(&self) -> i64 {
         match self {
             Self::Unit => 0,
             Self::Bool(b) => *b as i64,
             Self::Num(i) => *i,
+            Self::Float(f) => *f as i64,
             Self::Str(s) => s.len() as i64,
+            Self::List(l) => l.len() as i64,
+            Self::Map(m) => m.len() as i64,
+        }
+    }
+
+    /// Gets a float number from the value:
+    /// * A [`RawValue::Float`] returns itself.
+    /// * Other values convert to the number from [`RawValue::get_num`].
+    ///
+    /// ```
+    /// # use ayaka_primitive::RawValue;
+    /// let num_value = RawValue::Num(123);
+    /// assert_eq!(num_value.get_float(), 123.0);
+    /// let float_value = RawValue::Float(1.5);
+    /// assert_eq!(float_value.get_float(), 1.5);
+    /// ```
+    pub fn get_float(&self) -> f64 {
+        match self {
+            Self::Float(f) => *f,
+            _ => self.get_num() as f64,
         }
     }
 
     /// Gets a string from the value:
     /// * A [`RawValue::Unit`] converts to empty string.
     /// * A [`RawValue::Bool`] converts to "false" or "true".
-    /// * A [`RawValue::Num`] converts to the string representation of the number.\
+    /// * A [`RawValue::Num`] or [`RawValue::Float`] converts to the string representation of the number.
+    /// * A [`RawValue::List`] converts to the items joined with `, `.
+    /// * A [`RawValue::Map`] converts to the `key: value` pairs joined with `, `.
     ///
     /// Be careful to use `get_str().into_owned()`, if possible, use `into_str()` instead.
     ///
@@ -110,26 +185,124 @@ impl RawValue {
     /// assert_eq!(bool_value.get_str(), "true");
     /// let num_value = RawValue::Num(123);
     /// assert_eq!(num_value.get_str(), "123");
+    /// let list_value = RawValue::List(vec![RawValue::Num(1), RawValue::Str("a".to_string())]);
+    /// assert_eq!(list_value.get_str(), "1, a");
     /// ```
     pub fn get_str(&self) -> Cow<str> {
         match self {
             Self::Unit => Cow::default(),
             Self::Bool(b) => b.to_string().into(),
             Self::Num(i) => i.to_string().into(),
+            Self::Float(f) => f.to_string().into(),
             Self::Str(s) => s.as_str().into(),
+            Self::List(l) => l
+                .iter()
+                .map(|v| v.get_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+                .into(),
+            Self::Map(m) => m
+                .iter()
+                .map(|(k, v)| format!("{}: {}", k, v.get_str()))
+                .collect::<Vec<_>>()
+                .join(", ")
+                .into(),
         }
     }
 
     /// Gets a string from the value:
     /// * A [`RawValue::Unit`] converts to empty string.
     /// * A [`RawValue::Bool`] converts to "false" or "true".
-    /// * A [`RawValue::Num`] converts to the string representation of the number.
+    /// * A [`RawValue::Num`] or [`RawValue::Float`] converts to the string representation of the number.
+    /// * A [`RawValue::List`] or [`RawValue::Map`] converts the same as [`RawValue::get_str`].
     pub fn into_str(self) -> String {
         match self {
-            Self::Unit => String::default(),
-            Self::Bool(b) => b.to_string(),
-            Self::Num(i) => i.to_string(),
             Self::Str(s) => s,
+            _ => self.get_str().into_owned(),
+        }
+    }
+
+    /// Gets a list from the value:
+    /// * A [`RawValue::Unit`] converts to empty list.
+    /// * A [`RawValue::Map`] converts to the values.
+    /// * Other values convert to a list containing only itself.
+    ///
+    /// ```
+    /// # use ayaka_primitive::RawValue;
+    /// let unit_value = RawValue::Unit;
+    /// assert!(unit_value.into_list().is_empty());
+    /// let str_value = RawValue::Str("hello".to_string());
+    /// assert_eq!(str_value.into_list(), vec![RawValue::Str("hello".to_string())]);
+    /// ```
+    pub fn into_list(self) -> Vec<RawValue> {
+        match self {
+            Self::Unit => vec![],
+            Self::List(l) => l,
+            Self::Map(m) => m.into_values().collect(),
+            _ => vec![self],
+        }
+    }
+}
+
+impl PartialEq for RawValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RawValue {}
+
+impl PartialOrd for RawValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RawValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Unit, Self::Unit) => Ordering::Equal,
+            (Self::Bool(lhs), Self::Bool(rhs)) => lhs.cmp(rhs),
+            (Self::Num(lhs), Self::Num(rhs)) => lhs.cmp(rhs),
+            (Self::Float(lhs), Self::Float(rhs)) => lhs.total_cmp(rhs),
+            (Self::Num(lhs), Self::Float(rhs)) => cmp_num_float(*lhs, *rhs),
+            (Self::Float(lhs), Self::Num(rhs)) => cmp_num_float(*rhs, *lhs).reverse(),
+            (Self::Str(lhs), Self::Str(rhs)) => lhs.cmp(rhs),
+            (Self::List(lhs), Self::List(rhs)) => lhs.cmp(rhs),
+            (Self::Map(lhs), Self::Map(rhs)) => lhs.cmp(rhs),
+            _ => self.get_type().cmp(&other.get_type()),
+        }
+    }
+}
+
+// Compares exactly, even if the integer cannot be represented by a float.
+fn cmp_num_float(lhs: i64, rhs: f64) -> Ordering {
+    match (lhs as f64).total_cmp(&rhs) {
+        // The float is an integer here.
+        Ordering::Equal => (lhs as i128).cmp(&(rhs as i128)),
+        ord => ord,
+    }
+}
+
+impl Hash for RawValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            // The floats equal to the numbers are hashed as the numbers.
+            Self::Float(f) if cmp_num_float(*f as i64, *f) == Ordering::Equal => {
+                ValueType::Num.hash(state);
+                (*f as i64).hash(state);
+                return;
+            }
+            _ => self.get_type().hash(state),
+        }
+        match self {
+            Self::Unit => {}
+            Self::Bool(b) => b.hash(state),
+            Self::Num(i) => i.hash(state),
+            Self::Float(f) => f.to_bits().hash(state),
+            Self::Str(s) => s.hash(state),
+            Self::List(l) => l.hash(state),
+            Self::Map(m) => m.hash(state),
         }
     }
 }
@@ -137,6 +310,7 @@ impl RawValue {
 #[cfg(test)]
 mod test {
     use crate::*;
+    use std::collections::BTreeMap;
 
     #[test]
     fn serde_value() {
@@ -193,4 +367,36 @@ mod test {
             "aaa\n"
         );
     }
+
+    #[test]
+    fn serde_collection() {
+        assert_eq!(
+            serde_yaml::from_str::<RawValue>("-0.25").unwrap(),
+            RawValue::Float(-0.25)
+        );
+        assert_eq!(
+            serde_yaml::to_string(&RawValue::Float(2.0)).unwrap(),
+            "2.0\n"
+        );
+
+        let value = RawValue::Map(BTreeMap::from([
+            (
+                "items".to_string(),
+                RawValue::List(vec![
+                    RawValue::Str("sword".into()),
+                    RawValue::Num(3),
+                    RawValue::Float(0.5),
+                    RawValue::Unit,
+                ]),
+            ),
+            (
+                "affection".to_string(),
+                RawValue::Map(BTreeMap::from([("alice".to_string(), RawValue::Num(10))])),
+            ),
+        ]));
+        let yaml = serde_yaml::to_string(&value).unwrap();
+        assert_eq!(serde_yaml::from_str::<RawValue>(&yaml).unwrap(), value);
+        let msgpack = rmp_serde::to_vec(&value).unwrap();
+        assert_eq!(rmp_serde::from_slice::<RawValue>(&msgpack).unwrap(), value);
+    }
 }
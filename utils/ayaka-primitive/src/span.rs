@@ -0,0 +1,112 @@
+//! The source spans.
+
+use std::fmt::Display;
+
+/// A byte range in the source of a line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// The start byte, inclusive.
+    pub start: usize,
+    /// The end byte, exclusive.
+    pub end: usize,
+}
+
+impl Span {
+    /// Creates a [`Span`] from the byte range.
+    pub const fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Gets the length in bytes.
+    pub const fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Determines if the span is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    /// Renders the line of the source which contains the span,
+    /// and marks the span with `^`.
+    ///
+    /// ```
+    /// # use ayaka_primitive::Span;
+    /// let snippet = Span::new(4, 5).render("abc {def");
+    /// assert_eq!(snippet, "  |\n1 | abc {def\n  |     ^\n");
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let start = self.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = source[start..]
+            .find('\n')
+            .map(|i| start + i)
+            .unwrap_or(source.len());
+        let line_no = source[..line_start].matches('\n').count() + 1;
+        let line = &source[line_start..line_end];
+        let end = self.end.clamp(start, line_end);
+        let indent = source[line_start..start].chars().count();
+        let marks = source[start..end].chars().count().max(1);
+        let gutter = " ".repeat(line_no.to_string().len());
+        format!(
+            "{gutter} |\n{line_no} | {line}\n{gutter} | {}{}\n",
+            " ".repeat(indent),
+            "^".repeat(marks)
+        )
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// The location of a part of a line in the paragraph files.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct SourceSpan {
+    /// The paragraph file, without extension.
+    pub file: String,
+    /// The paragraph tag.
+    pub para: String,
+    /// The index of the line in the paragraph.
+    pub index: usize,
+    /// The byte range in the line.
+    pub span: Span,
+}
+
+impl SourceSpan {
+    /// Creates a [`SourceSpan`] of the whole line.
+    pub fn line(file: impl Into<String>, para: impl Into<String>, index: usize) -> Self {
+        Self {
+            file: file.into(),
+            para: para.into(),
+            index,
+            span: Span::default(),
+        }
+    }
+
+    /// Creates a [`SourceSpan`] of the same line, with the specified byte range.
+    pub fn with_span(&self, span: Span) -> Self {
+        Self {
+            span,
+            ..self.clone()
+        }
+    }
+}
+
+impl Display for SourceSpan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: paragraph \"{}\", line {}",
+            self.file,
+            self.para.escape_default(),
+            self.index
+        )?;
+        if !self.span.is_empty() {
+            write!(f, ", bytes {}", self.span)?;
+        }
+        Ok(())
+    }
+}
@@ -6,15 +6,29 @@ use nom::{
     bytes::complete::{take_till, take_till1, take_until, take_while, take_while1},
     character::complete::{char, one_of},
     combinator::{all_consuming, iterator, map},
-    error::VerboseError,
+    error::{ErrorKind, VerboseError, VerboseErrorKind},
     multi::many0,
     sequence::{delimited, terminated},
     *,
 };
 use serde::Deserialize;
+use std::fmt::Display;
 
 /// A collection of [`SubText`].
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
+///
+/// The spans are ignored when comparing,
+/// so that the same texts parsed from different sources are equal.
+///
+/// ```
+/// # use ayaka_primitive::Text;
+/// let text = Text::try_from("/ch/alias/abc").unwrap();
+/// let mut other = Text::try_from("abc").unwrap();
+/// other.ch_tag = text.ch_tag.clone();
+/// other.ch_alias = text.ch_alias.clone();
+/// assert_ne!(text.span, other.span);
+/// assert_eq!(text, other);
+/// ```
+#[derive(Debug, Clone, Default)]
 pub struct Text {
     /// The tag of current character.
     pub ch_tag: Option<String>,
@@ -22,19 +36,166 @@ pub struct Text {
     pub ch_alias: Option<String>,
     /// The texts.
     pub sub_texts: Vec<SubText>,
+    /// The span of the texts, without the character.
+    pub span: Span,
 }
 
 /// A part of a line, either some texts or a command.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// The last field of each variant is the [`Span`] in the line,
+/// which is ignored when comparing.
+#[derive(Debug, Clone)]
 pub enum SubText {
     /// Special character
-    Char(char),
+    Char(char, Span),
     /// Raw texts.
-    Str(String),
+    Str(String, Span),
     /// A TeX-like command in the text.
-    Cmd(String, Vec<SubText>),
+    Cmd(String, Vec<SubText>, Span),
+}
+
+impl PartialEq for Text {
+    fn eq(&self, other: &Self) -> bool {
+        self.ch_tag == other.ch_tag
+            && self.ch_alias == other.ch_alias
+            && self.sub_texts == other.sub_texts
+    }
+}
+
+impl Eq for Text {}
+
+impl PartialEq for SubText {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Char(c, _), Self::Char(other_c, _)) => c == other_c,
+            (Self::Str(s, _), Self::Str(other_s, _)) => s == other_s,
+            (Self::Cmd(cmd, args, _), Self::Cmd(other_cmd, other_args, _)) => {
+                cmd == other_cmd && args == other_args
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for SubText {}
+
+impl SubText {
+    /// Gets the [`Span`] in the line.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Char(_, span) | Self::Str(_, span) | Self::Cmd(_, _, span) => *span,
+        }
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Self::Char(_, span) | Self::Str(_, span) | Self::Cmd(_, _, span) => span,
+        }
+    }
+
+    // The parsers only know the remaining length of the input,
+    // so the spans are stored as remaining lengths, and fixed up here.
+    fn fix_span(&mut self, total: usize) {
+        if let Self::Cmd(_, args, _) = self {
+            for arg in args {
+                arg.fix_span(total);
+            }
+        }
+        let span = self.span_mut();
+        *span = Span::new(total - span.start, total - span.end);
+    }
+}
+
+impl Display for SubText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Char(c, _) => write!(f, "\\{}", c),
+            Self::Str(s, _) => write!(f, "{}", s),
+            Self::Cmd(cmd, args, _) => {
+                write!(f, "\\{}", cmd)?;
+                for arg in args {
+                    write!(f, "{{{}}}", arg)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Writes the source of the [`Text`].
+/// The whitespaces are normalized as the parser does.
+///
+/// ```
+/// # use ayaka_primitive::Text;
+/// let text = Text::try_from("/ch/alias/Hello, \\var{name}\\{").unwrap();
+/// assert_eq!(text.to_string(), "/ch/alias/Hello, \\var{name}\\{");
+/// ```
+impl Display for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.ch_tag.is_some() || self.ch_alias.is_some() {
+            write!(
+                f,
+                "/{}/{}/",
+                self.ch_tag.as_deref().unwrap_or_default(),
+                self.ch_alias.as_deref().unwrap_or_default()
+            )?;
+        }
+        for sub_text in &self.sub_texts {
+            write!(f, "{}", sub_text)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error when parsing a [`Text`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextParseError {
+    /// The error message.
+    pub message: String,
+    /// The source of the line.
+    pub source: String,
+    /// The span of the error.
+    pub span: Span,
 }
 
+impl TextParseError {
+    fn new(source: &str, e: VerboseError<&str>) -> Self {
+        let (input, kind) = e
+            .errors
+            .first()
+            .cloned()
+            .unwrap_or(("", VerboseErrorKind::Nom(ErrorKind::Eof)));
+        let start = source.len() - input.len();
+        let found = input.chars().next();
+        let end = start + found.map(char::len_utf8).unwrap_or_default();
+        let found = found
+            .map(|c| format!("`{}`", c.escape_default()))
+            .unwrap_or_else(|| "end of line".to_string());
+        let message = match kind {
+            VerboseErrorKind::Char(c) => format!("expected `{}`, found {}", c, found),
+            VerboseErrorKind::Context(ctx) => format!("{}, found {}", ctx, found),
+            VerboseErrorKind::Nom(ErrorKind::Eof) => format!("unexpected {}", found),
+            VerboseErrorKind::Nom(kind) => {
+                format!("{}, found {}", kind.description().to_lowercase(), found)
+            }
+        };
+        Self {
+            message,
+            source: source.to_string(),
+            span: Span::new(start, end),
+        }
+    }
+}
+
+impl Display for TextParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.message)?;
+        write!(f, "{}", self.span.render(&self.source))
+    }
+}
+
+impl std::error::Error for TextParseError {}
+
 type Res<I, O> = IResult<I, O, VerboseError<I>>;
 
 fn take_space(i: &str) -> Res<&str, &str> {
@@ -58,12 +219,12 @@ fn parse_arg(i: &str) -> Res<&str, SubText> {
 fn parse_escape_command(i: &str) -> Res<&str, SubText> {
     let (i, cmd) = take_cmd(i)?;
     let (i, args) = many0(parse_arg)(i)?;
-    Ok((i, SubText::Cmd(cmd.to_string(), args)))
+    Ok((i, SubText::Cmd(cmd.to_string(), args, Span::default())))
 }
 
 fn parse_escape_char(i: &str) -> Res<&str, SubText> {
     let (i, c) = one_of("\\{}/")(i)?;
-    Ok((i, SubText::Char(c)))
+    Ok((i, SubText::Char(c, Span::default())))
 }
 
 fn parse_sub_text_escape(i: &str) -> Res<&str, SubText> {
@@ -85,11 +246,24 @@ fn parse_sub_text_str(i: &str) -> Res<&str, SubText> {
         str,
         if !post_space.is_empty() { " " } else { "" }
     );
-    Ok((i, SubText::Str(str)))
+    Ok((i, SubText::Str(str, Span::default())))
+}
+
+fn with_span<'a>(
+    mut f: impl FnMut(&'a str) -> Res<&'a str, SubText>,
+) -> impl FnMut(&'a str) -> Res<&'a str, SubText> {
+    move |start| {
+        let (i, mut sub_text) = f(start)?;
+        *sub_text.span_mut() = Span::new(start.len(), i.len());
+        Ok((i, sub_text))
+    }
 }
 
 fn parse_sub_text(i: &str) -> Res<&str, SubText> {
-    alt((parse_sub_text_escape, parse_sub_text_str))(i)
+    alt((
+        with_span(parse_sub_text_escape),
+        with_span(parse_sub_text_str),
+    ))(i)
 }
 
 fn parse_sub_texts(i: &str) -> Res<&str, Vec<SubText>> {
@@ -99,12 +273,13 @@ fn parse_sub_texts(i: &str) -> Res<&str, Vec<SubText>> {
     Ok((i, sub_texts))
 }
 
-fn parse_text_without_ch(i: &str) -> Res<&str, Text> {
-    let (i, sub_texts) = parse_sub_texts(i)?;
+fn parse_text_without_ch(start: &str) -> Res<&str, Text> {
+    let (i, sub_texts) = parse_sub_texts(start)?;
     let text = Text {
         ch_tag: None,
         ch_alias: None,
         sub_texts,
+        span: Span::new(start.len(), i.len()),
     };
     Ok((i, text))
 }
@@ -127,11 +302,19 @@ fn parse_text(i: &str) -> Res<&str, Text> {
     all_consuming(alt((parse_text_with_ch, parse_text_without_ch)))(i)
 }
 
-impl<'a> TryFrom<&'a str> for Text {
-    type Error = VerboseError<&'a str>;
-
-    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
-        parse_text(value).finish().map(|(_, text)| text)
+impl TryFrom<&str> for Text {
+    type Error = TextParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (_, mut text) = parse_text(value)
+            .finish()
+            .map_err(|e| TextParseError::new(value, e))?;
+        let total = value.len();
+        text.span = Span::new(total - text.span.start, total - text.span.end);
+        for sub_text in &mut text.sub_texts {
+            sub_text.fix_span(total);
+        }
+        Ok(text)
     }
 }
 
@@ -149,7 +332,10 @@ impl<'de> Deserialize<'de> for Text {
 
 #[cfg(test)]
 pub mod test {
-    use crate::text::{SubText, Text};
+    use crate::{
+        text::{SubText, Text},
+        Span,
+    };
 
     pub fn parse(s: &str) -> Text {
         Text::try_from(s).unwrap()
@@ -160,6 +346,7 @@ pub mod test {
             ch_tag: None,
             ch_alias: None,
             sub_texts,
+            span: Span::default(),
         }
     }
 
@@ -168,19 +355,20 @@ pub mod test {
             ch_tag: tag.map(|s| s.into()),
             ch_alias: alias.map(|s| s.into()),
             sub_texts,
+            span: Span::default(),
         }
     }
 
     pub fn char(c: char) -> SubText {
-        SubText::Char(c)
+        SubText::Char(c, Span::default())
     }
 
     pub fn str(s: impl Into<String>) -> SubText {
-        SubText::Str(s.into())
+        SubText::Str(s.into(), Span::default())
     }
 
     pub fn cmd(cmd: impl Into<String>, args: Vec<SubText>) -> SubText {
-        SubText::Cmd(cmd.into(), args)
+        SubText::Cmd(cmd.into(), args, Span::default())
     }
 
     #[test]
@@ -236,4 +424,49 @@ pub mod test {
         );
         assert_eq!(parse("/ / /"), text_ch(None, None, vec![]));
     }
+
+    #[test]
+    fn span() {
+        let text = parse("/ch//abc \\cmd{def}\\{");
+        assert_eq!(text.span.start, 5);
+        assert_eq!(text.span.end, 20);
+        let spans = text
+            .sub_texts
+            .iter()
+            .map(|sub_text| (sub_text.span().start, sub_text.span().end))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, [(5, 9), (9, 18), (18, 20)]);
+        if let SubText::Cmd(_, args, _) = &text.sub_texts[1] {
+            assert_eq!(args[0].span().start, 14);
+            assert_eq!(args[0].span().end, 17);
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn display() {
+        for s in ["abc \\cmd{def}\\{", "\\par \\cmd{\\exec{1}}{2}", "/ch//abc"] {
+            assert_eq!(parse(s).to_string(), s);
+            assert_eq!(parse(&parse(s).to_string()), parse(s));
+        }
+    }
+
+    #[test]
+    fn error() {
+        let err = Text::try_from("abc {def").unwrap_err();
+        assert_eq!(err.message, "unexpected `{`");
+        assert_eq!((err.span.start, err.span.end), (4, 5));
+        assert_eq!(
+            err.to_string(),
+            "unexpected `{`\n  |\n1 | abc {def\n  |     ^\n"
+        );
+
+        let err = Text::try_from("line\n\\cmd{abc").unwrap_err();
+        assert_eq!(err.message, "unexpected `{`");
+        assert_eq!(
+            err.to_string(),
+            "unexpected `{`\n  |\n2 | \\cmd{abc\n  |     ^\n"
+        );
+    }
 }
@@ -0,0 +1,79 @@
+//! The static analysis shared by the story graph and the linter.
+
+use crate::*;
+use ayaka_script::{BinaryOp, Expr, Program, Ref};
+
+/// The line command running the scripts.
+pub(crate) const SCRIPT_COMMAND: &str = "exec";
+
+/// Gets the argument of a text command, if it is a plain string.
+pub(crate) fn text_arg(args: &[SubText]) -> Option<String> {
+    match args {
+        [SubText::Str(s, _)] => Some(s.clone()),
+        [SubText::Char(c, _)] => Some(c.to_string()),
+        _ => None,
+    }
+}
+
+/// Parses the scripts in the props of a custom line.
+/// The scripts failing to parse are skipped.
+pub(crate) fn line_programs(props: &[(String, RawValue)]) -> impl Iterator<Item = Program> + '_ {
+    props
+        .iter()
+        .filter(|(key, _)| key == SCRIPT_COMMAND)
+        .filter_map(|(_, program)| program.get_str().parse::<Program>().ok())
+}
+
+/// An assignment to a context variable.
+pub(crate) struct Assignment<'a> {
+    /// The name of the variable.
+    pub name: &'a str,
+    /// The assignment operator.
+    pub op: &'a BinaryOp,
+    /// The assigned expression.
+    pub value: &'a Expr,
+    /// Whether the variable is assigned as a whole, rather than an item of it.
+    pub whole: bool,
+}
+
+/// Walks the expression, and visits the assignments to the context variables,
+/// including the ones nested in other expressions.
+pub(crate) fn visit_assignments<'a>(expr: &'a Expr, f: &mut impl FnMut(Assignment<'a>)) {
+    match expr {
+        Expr::Binary(lhs, op, rhs) => {
+            if let BinaryOp::Assign | BinaryOp::Inplace(_) = op {
+                // The assigned place may be an item of a collection.
+                let mut place = lhs.as_ref();
+                while let Expr::Index(expr, _) = place {
+                    place = expr;
+                }
+                if let Expr::Ref(Ref::Ctx(name)) = place {
+                    f(Assignment {
+                        name,
+                        op,
+                        value: rhs,
+                        whole: matches!(lhs.as_ref(), Expr::Ref(_)),
+                    });
+                }
+            }
+            visit_assignments(lhs, f);
+            visit_assignments(rhs, f);
+        }
+        Expr::Unary(_, expr) => visit_assignments(expr, f),
+        Expr::Call(_, _, exprs) | Expr::List(exprs) => {
+            exprs.iter().for_each(|expr| visit_assignments(expr, f))
+        }
+        Expr::Map(items) => items
+            .iter()
+            .for_each(|(_, expr)| visit_assignments(expr, f)),
+        Expr::Index(expr, index) => {
+            visit_assignments(expr, f);
+            visit_assignments(index, f);
+        }
+        Expr::For(_, expr, body) => {
+            visit_assignments(expr, f);
+            body.iter().for_each(|expr| visit_assignments(expr, f));
+        }
+        Expr::Ref(_) | Expr::Const(_) => {}
+    }
+}
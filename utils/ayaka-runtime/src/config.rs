@@ -1,8 +1,7 @@
 use crate::*;
 use ayaka_bindings_types::VarMap;
-use fallback::Fallback;
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// The paragraph in a paragraph config.
 #[derive(Debug, Deserialize)]
@@ -15,10 +14,131 @@ pub struct Paragraph {
     pub title: Option<String>,
     /// The texts.
     /// They will be parsed into [`ayaka_primitive::Text`] later.
-    pub texts: Vec<Line>,
+    pub texts: Vec<ParaLine>,
     /// The next paragraph.
     /// If [`None`], the game meets the end.
-    pub next: Option<Text>,
+    pub next: Option<Next>,
+}
+
+impl Paragraph {
+    /// Gets the line by the text index.
+    pub fn line(&self, index: usize) -> Option<&Line> {
+        self.texts.get(index).map(|line| &line.line)
+    }
+
+    /// Gets the line identifier by the text index.
+    pub fn line_id(&self, index: usize) -> Option<&str> {
+        self.texts.get(index).and_then(|line| line.id.as_deref())
+    }
+
+    /// Gets the line timeout by the text index.
+    pub fn line_timeout(&self, index: usize) -> Option<&LineTimeout> {
+        self.texts.get(index).and_then(|line| line.timeout.as_ref())
+    }
+
+    /// Finds the line by the line identifier of the base language, and the text index.
+    ///
+    /// The line with the identifier is preferred.
+    /// Otherwise the line at the index is returned,
+    /// only if it has no identifier.
+    pub fn find_line(&self, id: Option<&str>, index: usize) -> Option<&ParaLine> {
+        id.and_then(|id| {
+            self.texts
+                .iter()
+                .find(|line| line.id.as_deref() == Some(id))
+        })
+        .or_else(|| self.texts.get(index).filter(|line| line.id.is_none()))
+    }
+
+    /// Finds the text index by the line identifier.
+    pub fn line_index(&self, id: &str) -> Option<usize> {
+        self.texts
+            .iter()
+            .position(|line| line.id.as_deref() == Some(id))
+    }
+}
+
+/// The next paragraph of a [`Paragraph`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawNext")]
+pub enum Next {
+    /// A text evaluated to the tag.
+    Text(Text),
+    /// The tags indexed by the chosen switch index, `$?`.
+    Switch(BTreeMap<usize, Text>),
+    /// The guards. The first passed one is chosen.
+    Guards(Vec<NextGuard>),
+    /// Call a paragraph, and then go to the next paragraph after it ends.
+    Call {
+        /// The tag of the called paragraph.
+        call: Text,
+        /// The next paragraph after the called one ends.
+        /// If [`None`], the called paragraph returns to the caller of the current one,
+        /// and the game meets the end if there is no caller.
+        to: Option<Text>,
+    },
+}
+
+/// A guard in [`Next::Guards`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NextGuard {
+    /// The script expression.
+    /// If [`None`], the guard always passes.
+    pub cond: Option<String>,
+    /// The next paragraph if passed.
+    pub to: Text,
+}
+
+// The texts are parsed after the form is determined,
+// so that the text parse error won't be swallowed by `untagged`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawNext {
+    Switch(BTreeMap<usize, RawValue>),
+    Call {
+        call: RawValue,
+        to: Option<RawValue>,
+    },
+    Guards(Vec<RawNextGuard>),
+    Text(RawValue),
+}
+
+#[derive(Deserialize)]
+struct RawNextGuard {
+    #[serde(rename = "if")]
+    cond: Option<String>,
+    to: RawValue,
+}
+
+impl TryFrom<RawNext> for Next {
+    type Error = TextParseError;
+
+    fn try_from(value: RawNext) -> Result<Self, Self::Error> {
+        let parse = |text: RawValue| Text::try_from(text.get_str().as_ref());
+        match value {
+            RawNext::Switch(tags) => Ok(Self::Switch(
+                tags.into_iter()
+                    .map(|(i, text)| Ok((i, parse(text)?)))
+                    .collect::<Result<_, Self::Error>>()?,
+            )),
+            RawNext::Guards(guards) => Ok(Self::Guards(
+                guards
+                    .into_iter()
+                    .map(|guard| {
+                        Ok(NextGuard {
+                            cond: guard.cond,
+                            to: parse(guard.to)?,
+                        })
+                    })
+                    .collect::<Result<_, Self::Error>>()?,
+            )),
+            RawNext::Call { call, to } => Ok(Self::Call {
+                call: parse(call)?,
+                to: to.map(parse).transpose()?,
+            }),
+            RawNext::Text(text) => Ok(Self::Text(parse(text)?)),
+        }
+    }
 }
 
 /// The Ayaka config.
@@ -46,6 +166,11 @@ pub struct GameConfig {
     /// If the runtime fails to choose a best match,
     /// it fallbacks to this one.
     pub base_lang: Locale,
+    /// The fallback chains, indexed by locale.
+    /// The locales in a chain are tried in order before the base language.
+    /// If a locale has no chain specified, its CLDR parent locales are tried.
+    #[serde(default)]
+    pub fallbacks: HashMap<Locale, Vec<Locale>>,
 }
 
 /// The plugin config.
@@ -56,6 +181,74 @@ pub struct PluginConfig {
     /// The names of the plugins, without extension.
     #[serde(default)]
     pub modules: Vec<String>,
+    /// The default limits of the plugins.
+    #[serde(default)]
+    pub limits: PluginLimits,
+    /// The limits of specific plugins, indexed by names.
+    /// The unspecified limits fallback to the default ones.
+    #[serde(default)]
+    pub module_limits: HashMap<String, PluginLimits>,
+    /// The host namespaces granted to specific plugins, indexed by names.
+    #[serde(default)]
+    pub permissions: HashMap<String, Vec<String>>,
+    /// Denies all host namespaces to the plugins not specified in `permissions`.
+    /// Otherwise they are granted all namespaces.
+    #[serde(default)]
+    pub deny_by_default: bool,
+    /// Imports the WASI preview1 functions to the plugins.
+    /// The root directory is preopened read-only as `/`,
+    /// and the stdout and stderr are redirected to the log.
+    #[serde(default)]
+    pub wasi: bool,
+}
+
+impl PluginConfig {
+    /// Gets the limits of a plugin.
+    pub fn limits_of(&self, name: &str) -> PluginLimits {
+        match self.module_limits.get(name) {
+            Some(limits) => limits.or(&self.limits),
+            None => self.limits,
+        }
+    }
+
+    /// Determines if a host namespace is granted to a plugin.
+    pub fn is_granted(&self, name: &str, namespace: &str) -> bool {
+        match self.permissions.get(name) {
+            Some(namespaces) => namespaces.iter().any(|ns| ns == namespace),
+            None => !self.deny_by_default,
+        }
+    }
+}
+
+/// The limits of a plugin.
+/// All limits are unlimited if not specified.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct PluginLimits {
+    /// The max size of the linear memory, in bytes.
+    pub memory: Option<usize>,
+    /// The max count of the open files.
+    pub files: Option<usize>,
+    /// The max size of the data passed between the plugin and the host in a call, in bytes.
+    pub payload: Option<usize>,
+}
+
+impl PluginLimits {
+    /// Fills the unspecified limits with another one.
+    pub fn or(&self, other: &Self) -> Self {
+        Self {
+            memory: self.memory.or(other.memory),
+            files: self.files.or(other.files),
+            payload: self.payload.or(other.payload),
+        }
+    }
+
+    /// Gets the limits checked by [`ayaka_plugin::PluginModule`].
+    pub fn module_limits(&self) -> ModuleLimits {
+        ModuleLimits {
+            memory: self.memory,
+            payload: self.payload,
+        }
+    }
 }
 
 /// The full Ayaka game.
@@ -80,7 +273,53 @@ impl Game {
         }
     }
 
-    fn choose_from_keys<'a, V>(&'a self, loc: &Locale, map: &'a HashMap<Locale, V>) -> &'a Locale {
+    /// Updates the text index of the [`RawContext`] by its line identifier,
+    /// so that the records still point to the same lines after the paragraph changes.
+    /// The return points in the call stack are updated as well.
+    pub fn relocate(&self, ctx: &mut RawContext) {
+        self.relocate_act(
+            &ctx.cur_base_para,
+            &ctx.cur_para,
+            &ctx.cur_id,
+            &mut ctx.cur_act,
+        );
+        for frame in &mut ctx.call_stack {
+            self.relocate_act(
+                &frame.cur_base_para,
+                &frame.cur_para,
+                &frame.cur_id,
+                &mut frame.cur_act,
+            );
+        }
+    }
+
+    fn relocate_act(&self, base_tag: &str, tag: &str, id: &Option<String>, act: &mut usize) {
+        if let Some(id) = id {
+            match self
+                .find_para(&self.config.base_lang, base_tag, tag)
+                .and_then(|p| p.line_index(id))
+            {
+                Some(index) => *act = index,
+                None => log::warn!(
+                    "Cannot find line \"{}\" in paragraph \"{}\"",
+                    id.escape_default(),
+                    tag.escape_default()
+                ),
+            }
+        }
+    }
+
+    /// Gets the paragraphs of the base language, indexed by file names.
+    pub fn base_paras(&self) -> Option<&HashMap<String, Vec<Paragraph>>> {
+        self.paras
+            .get(self.choose_from_keys(&self.config.base_lang, &self.paras))
+    }
+
+    pub(crate) fn choose_from_keys<'a, V>(
+        &'a self,
+        loc: &Locale,
+        map: &'a HashMap<Locale, V>,
+    ) -> &'a Locale {
         loc.choose_from(map.keys())
             .unwrap_or(&self.config.base_lang)
     }
@@ -99,22 +338,73 @@ impl Game {
         None
     }
 
+    /// Gets the fallback chain of the locale among the keys of the map,
+    /// from the most specific one to the base language.
+    ///
+    /// The chain starts with the locale and its fallback chain,
+    /// either specified in [`GameConfig::fallbacks`] or derived from CLDR parent locales,
+    /// and then the best match and its fallback chain.
+    pub fn fallback_chain<'a, V>(
+        &'a self,
+        loc: &Locale,
+        map: &'a HashMap<Locale, V>,
+    ) -> Vec<&'a Locale> {
+        let mut chain = vec![];
+        let mut visited = vec![];
+        self.push_fallback(loc, map, &mut chain, &mut visited);
+        self.push_fallback(
+            self.choose_from_keys(loc, map),
+            map,
+            &mut chain,
+            &mut visited,
+        );
+        let base_key = self.choose_from_keys(&self.config.base_lang, map);
+        if !chain.contains(&base_key) {
+            chain.push(base_key);
+        }
+        chain
+    }
+
+    fn push_fallback<'a, V>(
+        &'a self,
+        loc: &Locale,
+        map: &'a HashMap<Locale, V>,
+        chain: &mut Vec<&'a Locale>,
+        visited: &mut Vec<Locale>,
+    ) {
+        if visited.contains(loc) {
+            return;
+        }
+        visited.push(loc.clone());
+        if let Some((key, _)) = map.get_key_value(loc) {
+            chain.push(key);
+        }
+        match self.config.fallbacks.get(loc) {
+            Some(locs) => {
+                for loc in locs {
+                    self.push_fallback(loc, map, chain, visited);
+                }
+            }
+            None => {
+                for loc in loc.parents() {
+                    self.push_fallback(&loc, map, chain, visited);
+                }
+            }
+        }
+    }
+
     /// Find a paragraph by tag, with specified locale.
     pub fn find_para_fallback(
         &self,
         loc: &Locale,
         base_tag: &str,
         tag: &str,
-    ) -> Fallback<&Paragraph> {
-        let key = self.choose_from_keys(loc, &self.paras);
-        let base_key = self.choose_from_keys(&self.config.base_lang, &self.paras);
-        Fallback::new(
-            if key == base_key {
-                None
-            } else {
-                self.find_para(key, base_tag, tag)
-            },
-            self.find_para(base_key, base_tag, tag),
+    ) -> FallbackChain<&Paragraph> {
+        FallbackChain::new(
+            self.fallback_chain(loc, &self.paras)
+                .into_iter()
+                .map(|key| self.find_para(key, base_tag, tag))
+                .collect(),
         )
     }
 
@@ -123,16 +413,12 @@ impl Game {
     }
 
     /// Find the resource map with specified locale.
-    pub fn find_res_fallback(&self, loc: &Locale) -> Fallback<&VarMap> {
-        let key = self.choose_from_keys(loc, &self.res);
-        let base_key = self.choose_from_keys(&self.config.base_lang, &self.res);
-        Fallback::new(
-            if key == base_key {
-                None
-            } else {
-                self.find_res(key)
-            },
-            self.find_res(base_key),
+    pub fn find_res_fallback(&self, loc: &Locale) -> FallbackChain<&VarMap> {
+        FallbackChain::new(
+            self.fallback_chain(loc, &self.res)
+                .into_iter()
+                .map(|key| self.find_res(key))
+                .collect(),
         )
     }
 }
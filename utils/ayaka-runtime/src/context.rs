@@ -2,18 +2,29 @@ use crate::{
     plugin::{LoadStatus, Runtime},
     *,
 };
-use anyhow::{anyhow, bail, Result};
+use anyhow::{anyhow, bail, Context as _, Result};
 use ayaka_bindings_types::*;
 use ayaka_plugin::RawModule;
 use fallback::Fallback;
 use log::error;
-use serde::Serialize;
-use std::{borrow::Cow, collections::HashMap, future::Future, path::Path, pin::pin, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    future::Future,
+    io::Write,
+    path::Path,
+    pin::pin,
+    sync::Arc,
+};
 use stream_future::{stream, Stream};
 use trylog::macros::*;
 use vfs::*;
 use vfs_tar::TarFS;
 
+/// The max depth of the paragraph calls.
+pub const MAX_CALL_DEPTH: usize = 256;
+
 /// The game running context.
 pub struct Context<M: RawModule + Send + Sync + 'static> {
     game: Game,
@@ -22,7 +33,12 @@ pub struct Context<M: RawModule + Send + Sync + 'static> {
     runtime: Arc<Runtime<M>>,
     ctx: RawContext,
     switches: Vec<bool>,
+    // The local name and the validation of the current input line.
+    input: Option<(String, Option<String>)>,
+    // The switch chosen when the current timed switches are timed out.
+    timeout_switch: Option<usize>,
     vars: VarMap,
+    seed: Option<u64>,
 }
 
 /// The open status when creating [`Context`].
@@ -56,12 +72,25 @@ impl From<LoadStatus> for OpenStatus {
 pub struct ContextBuilder<M: RawModule + Send + Sync + 'static> {
     frontend: FrontendType,
     linker: M::Linker,
+    seed: Option<u64>,
 }
 
 impl<M: RawModule + Send + Sync + 'static> ContextBuilder<M> {
     /// Create a new [`ContextBuilder`] with frontend type and plugin runtime linker.
     pub fn new(frontend: FrontendType, linker: M::Linker) -> Self {
-        Self { frontend, linker }
+        Self {
+            frontend,
+            linker,
+            seed: None,
+        }
+    }
+
+    /// Set the seed of the random number generator for the new games.
+    ///
+    /// If not set, a random seed is chosen for each new game.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
     }
 
     fn open_fs_from_paths(paths: &[impl AsRef<Path>]) -> Result<(VfsPath, Cow<str>)> {
@@ -107,6 +136,7 @@ impl<M: RawModule + Send + Sync + 'static> ContextBuilder<M> {
             filename,
             frontend: self.frontend,
             linker: self.linker,
+            seed: self.seed,
         })
     }
 
@@ -120,6 +150,7 @@ impl<M: RawModule + Send + Sync + 'static> ContextBuilder<M> {
             filename: "config.yaml".into(),
             frontend: self.frontend,
             linker: self.linker,
+            seed: self.seed,
         })
     }
 }
@@ -130,12 +161,19 @@ pub struct ContextBuilderWithPaths<'a, M: RawModule + Send + Sync + 'static> {
     filename: Cow<'a, str>,
     frontend: FrontendType,
     linker: M::Linker,
+    seed: Option<u64>,
 }
 
 impl<'a, M: RawModule + Send + Sync + 'static> ContextBuilderWithPaths<'a, M> {
     /// Open the config and load the [`Context`].
     pub fn open(self) -> impl Future<Output = Result<Context<M>>> + Stream<Item = OpenStatus> + 'a {
-        Context::<M>::open(self.root_path, self.filename, self.frontend, self.linker)
+        Context::<M>::open(
+            self.root_path,
+            self.filename,
+            self.frontend,
+            self.linker,
+            self.seed,
+        )
     }
 }
 
@@ -146,17 +184,16 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         filename: impl AsRef<str> + 'a,
         frontend: FrontendType,
         linker: M::Linker,
+        seed: Option<u64>,
     ) -> Result<Self> {
         yield OpenStatus::LoadProfile;
         let file = root_path.join(filename.as_ref())?.open_file()?;
+        // The files contributed by the game plugins are under the game files.
+        let plugin_files = VfsPath::from(MemoryFS::new());
+        let root_path = VfsPath::from(OverlayFS::new(&[root_path, plugin_files.clone()]));
         let mut config: GameConfig = serde_yaml::from_reader(file)?;
         let runtime = {
-            let runtime = Runtime::load(
-                &config.plugins.dir,
-                &root_path,
-                &config.plugins.modules,
-                linker,
-            );
+            let runtime = Runtime::load(&config.plugins, &root_path, linker);
             let mut runtime = pin!(runtime);
             while let Some(load_status) = runtime.next().await {
                 yield load_status.into();
@@ -165,13 +202,20 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         };
 
         yield OpenStatus::GamePlugin;
-        Self::preprocess_game(&mut config, &runtime)?;
+        let plugin_res = Self::preprocess_game(&mut config, &runtime, &plugin_files)?;
 
         yield OpenStatus::LoadResource;
-        let res = Self::load_resource(&config, &root_path)?;
+        let mut res = Self::load_resource(&config, &root_path)?;
+        for (loc, plugin_res) in plugin_res {
+            let res = res.entry(loc).or_default();
+            for (key, value) in plugin_res {
+                res.entry(key).or_insert(value);
+            }
+        }
 
         yield OpenStatus::LoadParagraph;
         let paras = Self::load_paragraph(&config, &root_path)?;
+        Self::check_exprs(&paras, &runtime)?;
 
         Ok(Self {
             game: Game { config, paras, res },
@@ -180,11 +224,21 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
             runtime,
             ctx: RawContext::default(),
             switches: vec![],
+            input: None,
+            timeout_switch: None,
             vars: VarMap::default(),
+            seed,
         })
     }
 
-    fn preprocess_game(config: &mut GameConfig, runtime: &Runtime<M>) -> Result<()> {
+    // The paragraphs and files are written to `plugin_files`,
+    // and the resources are returned, indexed by locale.
+    fn preprocess_game(
+        config: &mut GameConfig,
+        runtime: &Runtime<M>,
+        plugin_files: &VfsPath,
+    ) -> Result<HashMap<Locale, VarMap>> {
+        let mut plugin_res = HashMap::<Locale, VarMap>::new();
         for module in runtime.game_modules() {
             let ctx = GameProcessContextRef {
                 title: &config.title,
@@ -195,7 +249,37 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
             for (key, value) in res.props {
                 config.props.insert(key, value);
             }
+            for (loc, paras) in res.paras {
+                Self::parse_plugin_locale(&loc)?;
+                for (key, paras) in paras {
+                    let path = format!("{}/{}/{}.yaml", config.paras, loc, key);
+                    Self::write_plugin_file(plugin_files, &path, paras.as_bytes())?;
+                }
+            }
+            for (loc, res) in res.res {
+                plugin_res
+                    .entry(Self::parse_plugin_locale(&loc)?)
+                    .or_default()
+                    .extend(res);
+            }
+            for (path, data) in res.files {
+                Self::write_plugin_file(plugin_files, &path, &data)?;
+            }
         }
+        Ok(plugin_res)
+    }
+
+    fn parse_plugin_locale(loc: &str) -> Result<Locale> {
+        loc.parse()
+            .with_context(|| format!("Invalid locale \"{}\" from game plugins", loc))
+    }
+
+    fn write_plugin_file(plugin_files: &VfsPath, path: &str, data: &[u8]) -> Result<()> {
+        let path = plugin_files
+            .join(path.trim_start_matches('/'))
+            .with_context(|| format!("Invalid path \"{}\" from game plugins", path))?;
+        path.parent().create_dir_all()?;
+        path.create_file()?.write_all(data)?;
         Ok(())
     }
 
@@ -241,8 +325,7 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
                                 .strip_suffix(".yaml")
                                 .unwrap_or_default()
                                 .to_string();
-                            let para = p.open_file()?;
-                            let para = serde_yaml::from_reader(para)?;
+                            let para = Self::parse_paragraphs(&key, &p)?;
                             paras_map.insert(key, para);
                         }
                     }
@@ -253,6 +336,123 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         Ok(paras)
     }
 
+    // The guards and the validations are evaluated by the `exec` line command.
+    fn check_exprs(
+        paras: &HashMap<Locale, HashMap<String, Vec<Paragraph>>>,
+        runtime: &Runtime<M>,
+    ) -> Result<()> {
+        for (loc, paras) in paras {
+            for (key, paras) in paras {
+                for p in paras {
+                    let conds = match &p.next {
+                        Some(Next::Guards(guards)) => guards
+                            .iter()
+                            .filter_map(|guard| guard.cond.as_deref())
+                            .collect(),
+                        _ => vec![],
+                    };
+                    let validates = p.texts.iter().filter_map(|line| match &line.line {
+                        Line::Input { validate, .. } => validate.as_deref(),
+                        _ => None,
+                    });
+                    for expr in conds.into_iter().chain(validates) {
+                        if runtime.line_module("exec").is_none() {
+                            bail!(
+                                "Cannot find command exec to evaluate \"{}\", in {}: paragraph \"{}\" ({})",
+                                expr.escape_default(),
+                                key,
+                                p.tag.escape_default(),
+                                loc
+                            );
+                        }
+                        Self::parse_expr(expr).with_context(|| {
+                            format!(
+                                "In {}: paragraph \"{}\" ({})",
+                                key,
+                                p.tag.escape_default(),
+                                loc
+                            )
+                        })?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_expr(expr: &str) -> Result<ayaka_script::Expr> {
+        ayaka_script::ExprParser::new()
+            .parse(expr)
+            .map_err(|e| anyhow!("Invalid expression \"{}\": {}", expr.escape_default(), e))
+    }
+
+    fn parse_paragraphs(key: &str, path: &VfsPath) -> Result<Vec<Paragraph>> {
+        let file = path.open_file()?;
+        let values: Vec<serde_yaml::Value> = serde_yaml::from_reader(file)
+            .with_context(|| format!("Cannot parse paragraph file \"{}\"", path.as_str()))?;
+        let mut paras = values
+            .iter()
+            .map(|value| {
+                Paragraph::deserialize(value).map_err(|e| {
+                    anyhow!(
+                        "Cannot parse paragraph file \"{}\"\n{}",
+                        path.as_str(),
+                        Self::locate_paragraph_error(key, value, e)
+                    )
+                })
+            })
+            .collect::<Result<Vec<Paragraph>>>()?;
+        for p in &mut paras {
+            for (i, line) in p.texts.iter_mut().enumerate() {
+                line.source = SourceSpan::line(key, &p.tag, i);
+            }
+            let mut ids = HashSet::new();
+            for (i, line) in p.texts.iter().enumerate() {
+                if let Some(id) = &line.id {
+                    if !ids.insert(id) {
+                        bail!(
+                            "Cannot parse paragraph file \"{}\"\n{}: duplicate line id \"{}\"",
+                            path.as_str(),
+                            SourceSpan::line(key, &p.tag, i),
+                            id.escape_default()
+                        );
+                    }
+                }
+            }
+        }
+        Ok(paras)
+    }
+
+    // Finds the first line which fails to parse, to report the file, tag and index.
+    fn locate_paragraph_error(
+        key: &str,
+        value: &serde_yaml::Value,
+        e: serde_yaml::Error,
+    ) -> String {
+        let tag = match value.get("tag").and_then(|tag| tag.as_str()) {
+            Some(tag) => tag,
+            None => return e.to_string(),
+        };
+        let lines = value
+            .get("texts")
+            .and_then(|lines| lines.as_sequence())
+            .map(|lines| lines.as_slice())
+            .unwrap_or_default();
+        lines
+            .iter()
+            .enumerate()
+            .find_map(|(i, line)| {
+                let e = Line::deserialize(line).err()?;
+                let span = SourceSpan::line(key, tag, i);
+                let text = RawValue::deserialize(line).ok();
+                match text.map(|text| Text::try_from(text.get_str().as_ref())) {
+                    Some(Err(e)) => Some(format!("{}: {}", span.with_span(e.span), e)),
+                    _ => Some(format!("{}: {}", span, e)),
+                }
+            })
+            .unwrap_or_else(|| format!("{}: paragraph \"{}\": {}", key, tag.escape_default(), e))
+    }
+
     /// Initialize the [`RawContext`] at the start of the game.
     pub fn set_start_context(&mut self) {
         self.set_context(self.game().start_context())
@@ -268,14 +468,14 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
             .find_para(loc, &self.ctx.cur_base_para, &self.ctx.cur_para)
     }
 
-    fn current_paragraph_fallback(&self, loc: &Locale) -> Fallback<&Paragraph> {
+    fn current_paragraph_fallback(&self, loc: &Locale) -> FallbackChain<&Paragraph> {
         self.game
             .find_para_fallback(loc, &self.ctx.cur_base_para, &self.ctx.cur_para)
     }
 
     fn current_text(&self, loc: &Locale) -> Option<&Line> {
         self.current_paragraph(loc)
-            .and_then(|p| p.texts.get(self.ctx.cur_act))
+            .and_then(|p| p.line(self.ctx.cur_act))
     }
 
     fn find_res(&self, loc: &Locale, key: &str) -> Option<&RawValue> {
@@ -294,18 +494,114 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         &self.root_path
     }
 
+    /// The plugin runtime.
+    pub fn runtime(&self) -> &Runtime<M> {
+        &self.runtime
+    }
+
     /// Call the part of script with this context.
+    ///
+    /// The text plugins get the base language. See [`Self::call_locale`].
     pub fn call(&self, text: &Text) -> Result<String> {
+        self.call_locale(&self.game.config.base_lang, text)
+    }
+
+    /// Call the part of script with this context.
+    ///
+    /// The text plugins get the locale `loc`,
+    /// but the resources are not resolved.
+    pub fn call_locale(&self, loc: &Locale, text: &Text) -> Result<String> {
+        self.with_rng(|| self.call_impl(loc, text))
+    }
+
+    fn call_impl(&self, loc: &Locale, text: &Text) -> Result<String> {
+        let source = self.source_span(&self.game.config.base_lang, &self.ctx);
         let mut str = String::new();
         for sub_text in &text.sub_texts {
-            let sub_action = self.parse_sub_text(sub_text, None, &self.ctx.locals)?;
+            let sub_action = self.parse_sub_text(sub_text, loc, false, &self.ctx, &source)?;
             str.push_str(&sub_action.to_string());
         }
         Ok(str.trim().to_string())
     }
 
+    /// Evaluate a script expression with this context.
+    ///
+    /// The `exec` line command gets the base language. See [`Self::eval_locale`].
+    pub fn eval(&self, expr: &str) -> Result<RawValue> {
+        self.eval_locale(&self.game.config.base_lang, expr)
+    }
+
+    /// Evaluate a script expression with this context.
+    ///
+    /// The expression is executed by the `exec` line command with the locale `loc`,
+    /// and the changes to the locals and the random number generator are discarded.
+    /// It fails if `expr` is not a single expression.
+    pub fn eval_locale(&self, loc: &Locale, expr: &str) -> Result<RawValue> {
+        self.with_rng(|| self.eval_impl(loc, expr))
+    }
+
+    fn eval_impl(&self, loc: &Locale, expr: &str) -> Result<RawValue> {
+        const RES: &str = "res";
+        // A single expression cannot break out of the assignment.
+        Self::parse_expr(expr)?;
+        let module = self
+            .runtime
+            .line_module("exec")
+            .ok_or_else(|| anyhow!("Cannot find command exec"))?;
+        let props = VarMap::from([(
+            "exec".to_string(),
+            RawValue::Str(format!("{} = ({})", RES, expr)),
+        )]);
+        let ctx = LineProcessContextRef {
+            game_props: &self.game.config.props,
+            frontend: self.frontend,
+            locale: &loc.0,
+            ctx: &self.ctx,
+            props: &props,
+        };
+        let mut res = module.dispatch_line("exec", ctx)?;
+        Ok(res.vars.remove(RES).unwrap_or_default())
+    }
+
+    fn next_para(&self, loc: &Locale, next: &Next) -> Result<String> {
+        match next {
+            Next::Text(text) => self.call_impl(loc, text),
+            Next::Switch(tags) => {
+                let tag = self
+                    .ctx
+                    .locals
+                    .get("?")
+                    .and_then(|i| usize::try_from(i.get_num()).ok())
+                    .and_then(|i| tags.get(&i));
+                match tag {
+                    Some(text) => self.call_impl(loc, text),
+                    None => Ok(String::default()),
+                }
+            }
+            Next::Call { call, .. } => self.call_impl(loc, call),
+            Next::Guards(guards) => {
+                for guard in guards {
+                    let passed = match &guard.cond {
+                        Some(cond) => self.eval_impl(loc, cond)?.get_bool(),
+                        None => true,
+                    };
+                    if passed {
+                        return self.call_impl(loc, &guard.to);
+                    }
+                }
+                Ok(String::default())
+            }
+        }
+    }
+
     /// Choose a switch item by index, start by 0.
+    ///
+    /// The switch plugins are notified after the choice is stored in the locals.
     pub fn switch(&mut self, i: usize) {
+        self.with_ctx_rng(|this| this.switch_impl(i))
+    }
+
+    fn switch_impl(&mut self, i: usize) {
         assert!((0..self.switches.len()).contains(&i));
         assert!(self.switches[i]);
         self.ctx
@@ -314,9 +610,121 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         for i in 0..self.switches.len() {
             self.ctx.locals.remove(&i.to_string());
         }
+        let runtime = self.runtime.clone();
+        for module in runtime.switch_modules() {
+            let ctx = SwitchProcessContextRef {
+                game_props: &self.game.config.props,
+                frontend: self.frontend,
+                ctx: &self.ctx,
+                index: i,
+            };
+            let res = unwrap_or_default_log!(
+                module.process_switch(ctx),
+                format!("Cannot process switch in plugin \"{}\"", module.name())
+            );
+            self.ctx.locals.extend(res.locals);
+        }
     }
 
-    fn parse_text(&self, loc: &Locale, text: &Text, ctx: &RawContext) -> Result<ActionText> {
+    /// Submit the input of the current input line.
+    ///
+    /// The value is stored in the named local if it passes the validation,
+    /// and `false` is returned otherwise.
+    /// The validation is evaluated by [`Self::eval_locale`], with the value in the local.
+    pub fn input(&mut self, loc: &Locale, value: impl Into<String>) -> Result<bool> {
+        let value = value.into();
+        self.with_ctx_rng(|this| this.input_impl(loc, value))
+    }
+
+    fn input_impl(&mut self, loc: &Locale, value: String) -> Result<bool> {
+        let (name, validate) = self
+            .input
+            .clone()
+            .ok_or_else(|| anyhow!("The current line is not an input"))?;
+        let old = self.ctx.locals.insert(name.clone(), RawValue::Str(value));
+        let valid = match &validate {
+            Some(validate) => self.eval_impl(loc, validate).map(|res| res.get_bool()),
+            None => Ok(true),
+        };
+        if !matches!(valid, Ok(true)) {
+            match old {
+                Some(old) => self.ctx.locals.insert(name, old),
+                None => self.ctx.locals.remove(&name),
+            };
+        }
+        valid
+    }
+
+    /// Resolve the timeout of the current line.
+    ///
+    /// If the current line is timed switches,
+    /// the default switch is chosen by [`Self::switch`] and its index is returned.
+    /// The timed text lines are advanced by [`Self::next_run`] instead.
+    pub fn timeout(&mut self) -> Option<usize> {
+        let i = self.timeout_switch.take()?;
+        self.with_ctx_rng(|this| this.switch_impl(i));
+        Some(i)
+    }
+
+    // The default switch is chosen if enabled, otherwise the first enabled one.
+    fn default_switch(default: Option<usize>, enabled: &[bool]) -> Option<usize> {
+        default
+            .filter(|i| enabled.get(*i).copied().unwrap_or_default())
+            .or_else(|| enabled.iter().position(|enabled| *enabled))
+    }
+
+    fn process_paragraph(&mut self, event: ParagraphEvent) {
+        let runtime = self.runtime.clone();
+        for module in runtime.paragraph_modules() {
+            let ctx = ParagraphProcessContextRef {
+                game_props: &self.game.config.props,
+                frontend: self.frontend,
+                ctx: &self.ctx,
+                event,
+            };
+            let res = unwrap_or_default_log!(
+                module.process_paragraph(ctx),
+                format!("Cannot process paragraph in plugin \"{}\"", module.name())
+            );
+            self.ctx.locals.extend(res.locals);
+        }
+    }
+
+    /// Notify the record plugins that a record is saved or loaded.
+    ///
+    /// The locals of `ctx`, the context in the record, are updated by the plugins.
+    pub fn process_record(&self, event: RecordEvent, ctx: &mut RawContext) {
+        let state = ctx.rng.unwrap_or_else(|| self.runtime.rng_state());
+        let ((), state) = self
+            .runtime
+            .with_rng_state(state, || self.process_record_impl(event, ctx));
+        if ctx.rng.is_some() {
+            ctx.rng = Some(state);
+        }
+    }
+
+    fn process_record_impl(&self, event: RecordEvent, ctx: &mut RawContext) {
+        for module in self.runtime.record_modules() {
+            let res = unwrap_or_default_log!(
+                module.process_record(RecordProcessContextRef {
+                    game_props: &self.game.config.props,
+                    frontend: self.frontend,
+                    ctx,
+                    event,
+                }),
+                format!("Cannot process record in plugin \"{}\"", module.name())
+            );
+            ctx.locals.extend(res.locals);
+        }
+    }
+
+    fn parse_text(
+        &self,
+        loc: &Locale,
+        text: &Text,
+        ctx: &RawContext,
+        source: &SourceSpan,
+    ) -> Result<ActionText> {
         let mut action = ActionText::default();
         action.ch_key = text.ch_tag.clone();
         action.character = text.ch_alias.clone().or_else(|| {
@@ -327,64 +735,116 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
             .map(|value| value.get_str().into_owned())
         });
         for sub_text in &text.sub_texts {
-            let mut sub_action = self.parse_sub_text(sub_text, Some(loc), &ctx.locals)?;
+            let mut sub_action = self.parse_sub_text(sub_text, loc, true, ctx, source)?;
             action.text.append(&mut sub_action.text);
         }
         Ok(action)
     }
 
+    // The location of the current line, or the location after the last line for `next`.
+    fn source_span(&self, loc: &Locale, ctx: &RawContext) -> SourceSpan {
+        self.game
+            .find_para_fallback(loc, &ctx.cur_base_para, &ctx.cur_para)
+            .and_then(|p| p.find_line(ctx.cur_id.as_deref(), ctx.cur_act))
+            .map(|line| line.source.clone())
+            .unwrap_or_else(|| SourceSpan::line(&ctx.cur_base_para, &ctx.cur_para, ctx.cur_act))
+    }
+
+    // The errors returned by the plugins are reported as warnings of the line.
+    // The other errors, e.g., traps and limits, are still propagated.
+    fn plugin_warning<T: Default>(res: Result<T>, span: SourceSpan) -> Result<T> {
+        match res {
+            Err(e) if e.downcast_ref::<PluginError>().is_some() => {
+                log::warn!("{:#}, at {}", e, span);
+                Ok(T::default())
+            }
+            res => res,
+        }
+    }
+
+    // The `res` commands are resolved only `with_res`, and the tags are parsed without them.
     fn parse_sub_text(
         &self,
         sub_text: &SubText,
-        loc: Option<&Locale>,
-        locals: &VarMap,
+        loc: &Locale,
+        with_res: bool,
+        ctx: &RawContext,
+        source: &SourceSpan,
     ) -> Result<ActionText> {
         let mut action = ActionText::default();
         match sub_text {
-            SubText::Char(c) => action.push_back_chars(c.to_string()),
-            SubText::Str(s) => action.push_back_chars(s),
-            SubText::Cmd(cmd, args) => {
+            SubText::Char(c, _) => action.push_back_chars(c.to_string()),
+            SubText::Str(s, _) => action.push_back_chars(s),
+            SubText::Cmd(cmd, args, span) => {
                 let mut arg_strings = vec![];
                 for arg in args {
-                    let sub_action = self.parse_sub_text(arg, loc, locals)?;
+                    let sub_action = self.parse_sub_text(arg, loc, with_res, ctx, source)?;
                     arg_strings.push(sub_action.to_string());
                 }
                 match cmd.as_str() {
                     "res" => {
-                        if let Some(loc) = loc {
+                        if with_res {
                             if arg_strings.len() != 1 {
-                                log::warn!("Invalid parameter count for `res`: {}", args.len())
+                                log::warn!(
+                                    "Invalid parameter count for `res`: {}, at {}",
+                                    args.len(),
+                                    source.with_span(*span)
+                                )
                             }
                             if let Some(n) = arg_strings.get(0) {
                                 if let Some(value) = self.find_res(loc, n) {
                                     action.push_back_block(value.get_str())
                                 } else {
-                                    log::warn!("Cannot find resource {}", n);
+                                    log::warn!(
+                                        "Cannot find resource {}, at {}",
+                                        n,
+                                        source.with_span(*span)
+                                    );
                                 }
                             }
                         }
                     }
                     "var" => {
                         if arg_strings.len() != 1 {
-                            log::warn!("Invalid parameter count for `var`: {}", args.len())
+                            log::warn!(
+                                "Invalid parameter count for `var`: {}, at {}",
+                                args.len(),
+                                source.with_span(*span)
+                            )
                         }
                         if let Some(n) = arg_strings.get(0) {
-                            if let Some(value) = locals.get(n) {
+                            if let Some(value) = ctx.locals.get(n) {
                                 action.push_back_block(value.get_str())
                             } else {
-                                log::warn!("Cannot find variable {}", n)
+                                log::warn!(
+                                    "Cannot find variable {}, at {}",
+                                    n,
+                                    source.with_span(*span)
+                                )
                             }
                         }
                     }
                     _ => {
                         if let Some(module) = self.runtime.text_module(cmd) {
+                            let span = source.with_span(*span);
                             let ctx = TextProcessContextRef {
                                 game_props: &self.game.config.props,
                                 frontend: self.frontend,
+                                locale: &loc.0,
+                                ctx,
                             };
-                            let mut res = module.dispatch_text(cmd, &arg_strings, ctx)?;
+                            let mut res = Self::plugin_warning(
+                                module.dispatch_text(cmd, &arg_strings, ctx),
+                                span,
+                            )?;
                             action.text.append(&mut res.text.text);
                             action.vars.extend(res.text.vars);
+                        } else {
+                            log::warn!(
+                                "Cannot find text command {}, at {}",
+                                cmd,
+                                source.with_span(*span)
+                            )
                         }
                     }
                 }
@@ -393,9 +853,9 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
         Ok(action)
     }
 
-    fn parse_switches(&self, s: &[String]) -> Vec<Switch> {
+    fn parse_switches(s: &[String], enabled: &[bool]) -> Vec<Switch> {
         s.iter()
-            .zip(&self.switches)
+            .zip(enabled)
             .map(|(item, enabled)| Switch {
                 text: item.clone(),
                 enabled: *enabled,
@@ -403,87 +863,158 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
             .collect()
     }
 
-    fn process_line(&mut self, t: Line) -> Result<()> {
+    // The switch `i` is disabled by a false local `i`.
+    fn switches_enabled(locals: &VarMap, len: usize) -> Vec<bool> {
+        (0..len)
+            .map(|i| match locals.get(&i.to_string()) {
+                None | Some(RawValue::Unit) => true,
+                Some(enabled) => enabled.get_bool(),
+            })
+            .collect()
+    }
+
+    fn process_line(&mut self, loc: &Locale, t: Line) -> Result<Option<FlowDirective>> {
+        let mut flow = None;
+        self.input = None;
+        self.timeout_switch = None;
         match t {
-            Line::Empty | Line::Text(_) => {}
+            // The call is applied in `next_run` after the current line is recorded.
+            Line::Empty | Line::Text(_) | Line::Call { .. } => {}
             Line::Switch { switches } => {
-                self.switches.clear();
-                for i in 0..switches.len() {
-                    let enabled = self
-                        .ctx
+                self.switches = Self::switches_enabled(&self.ctx.locals, switches.len());
+            }
+            Line::Input {
+                input,
+                default,
+                validate,
+                ..
+            } => {
+                if let Some(default) = default {
+                    self.ctx
                         .locals
-                        .get(&i.to_string())
-                        .unwrap_or(&RawValue::Unit);
-                    let enabled = if let RawValue::Unit = enabled {
-                        true
-                    } else {
-                        enabled.get_bool()
-                    };
-                    self.switches.push(enabled);
+                        .insert(input.clone(), RawValue::Str(default));
                 }
+                self.input = Some((input, validate));
             }
             Line::Custom(props) => {
                 self.vars.clear();
-                let cmd = props.iter().next().map(|(key, _)| key);
-                if let Some(cmd) = cmd {
-                    if let Some(module) = self.runtime.line_module(cmd) {
+                // The keys which are not commands are the props of the commands accepting them.
+                let (cmds, args): (Vec<_>, Vec<_>) = props
+                    .into_iter()
+                    .partition(|(key, _)| self.runtime.line_module(key).is_some());
+                if cmds.is_empty() {
+                    if let Some((cmd, _)) = args.first() {
+                        bail!("Cannot find command {}", cmd)
+                    }
+                }
+                for (key, _) in &args {
+                    let declared = cmds
+                        .iter()
+                        .any(|(cmd, _)| self.runtime.accepts_line_prop(cmd, key));
+                    if !declared {
+                        log::warn!(
+                            "Unknown key `{}` in line, at {}",
+                            key,
+                            self.source_span(&self.game.config.base_lang, &self.ctx)
+                        );
+                    }
+                }
+                for (cmd, value) in cmds {
+                    if let Some(module) = self.runtime.line_module(&cmd) {
+                        let mut props = args
+                            .iter()
+                            .filter(|(key, _)| self.runtime.accepts_line_prop(&cmd, key))
+                            .cloned()
+                            .collect::<VarMap>();
+                        props.insert(cmd.clone(), value);
                         let ctx = LineProcessContextRef {
                             game_props: &self.game.config.props,
                             frontend: self.frontend,
+                            locale: &loc.0,
                             ctx: &self.ctx,
                             props: &props,
                         };
-                        let res = module.dispatch_line(cmd, ctx)?;
+                        let res = Self::plugin_warning(
+                            module.dispatch_line(&cmd, ctx),
+                            self.source_span(&self.game.config.base_lang, &self.ctx),
+                        )?;
                         self.ctx.locals.extend(res.locals);
                         self.vars.extend(res.vars);
-                    } else {
-                        bail!("Cannot find command {}", cmd)
+                        // The latter command overrides the former one.
+                        if res.flow.is_some() {
+                            flow = res.flow;
+                        }
                     }
                 }
             }
         }
-        Ok(())
+        Ok(flow)
     }
 
-    fn merge_action(&self, action: Fallback<Action>) -> Result<Action> {
+    fn merge_action(&self, action: Fallback<Action>) -> Result<Option<Action>> {
         match action.unzip() {
-            (None, None) => Ok(Action::default()),
-            (Some(action), None) | (None, Some(action)) => Ok(action),
+            (None, None) => Ok(None),
+            (Some(action), None) | (None, Some(action)) => Ok(Some(action)),
             (Some(action), Some(action_base)) => match (action, action_base) {
                 (Action::Text(action), Action::Text(action_base)) => {
                     let action = Fallback::new(Some(action), Some(action_base));
                     let action = action.spec();
-                    Ok(Action::Text(ActionText {
+                    Ok(Some(Action::Text(ActionText {
                         text: action.text.and_any().unwrap_or_default(),
                         ch_key: action.ch_key.flatten().fallback(),
                         character: action.character.flatten().fallback(),
                         vars: action.vars.and_any().unwrap_or_default(),
-                    }))
+                        timeout: action.timeout.flatten().fallback(),
+                    })))
                 }
+                // The timeout is resolved from the base language.
                 (Action::Switches(mut switches), Action::Switches(switches_base)) => {
-                    for (item, item_base) in switches.iter_mut().zip(switches_base) {
+                    for (item, item_base) in
+                        switches.switches.iter_mut().zip(switches_base.switches)
+                    {
                         item.enabled = item_base.enabled;
                     }
-                    Ok(Action::Switches(switches))
+                    switches.timeout = switches_base.timeout;
+                    switches.default = switches_base.default;
+                    Ok(Some(Action::Switches(switches)))
+                }
+                // The default value is stored from the base language.
+                (Action::Input(mut input), Action::Input(input_base)) => {
+                    if input.prompt.text.is_empty() {
+                        input.prompt = input_base.prompt;
+                    }
+                    input.default = input_base.default;
+                    Ok(Some(Action::Input(input)))
                 }
                 (Action::Custom(mut vars), Action::Custom(vars_base)) => {
                     vars.extend(vars_base);
-                    Ok(Action::Custom(vars))
+                    Ok(Some(Action::Custom(vars)))
                 }
                 _ => bail!("Mismatching action type"),
             },
         }
     }
 
-    fn process_action_text(&self, ctx: &RawContext, action: &mut ActionText) -> Result<()> {
+    fn process_action_text(
+        &self,
+        loc: &Locale,
+        ctx: &RawContext,
+        action: &mut ActionText,
+    ) -> Result<()> {
+        let span = self.source_span(loc, ctx);
         for module in self.runtime.action_modules() {
             let ctx = ActionProcessContextRef {
                 game_props: &self.game.config.props,
                 frontend: self.frontend,
+                locale: &loc.0,
                 ctx,
                 action,
             };
-            *action = module.process_action(ctx)?.action;
+            // The action is not changed if the plugin fails.
+            let res = module.process_action(ctx).map(|res| Some(res.action));
+            if let Some(res) = Self::plugin_warning(res, span.clone())? {
+                *action = res;
+            }
         }
         while let Some(act) = action.text.back() {
             if act.as_str().trim().is_empty() {
@@ -503,43 +1034,144 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
     }
 
     /// Get the [`Action`] from [`Locale`] and [`RawContext`].
+    ///
+    /// The actions of the same context are the same,
+    /// because the random number generator is restored after it.
     pub fn get_action(&self, loc: &Locale, ctx: &RawContext) -> Result<Action> {
+        let state = ctx.rng.unwrap_or_else(|| self.runtime.rng_state());
+        self.runtime
+            .with_rng_state(state, || self.get_action_impl(loc, ctx))
+            .0
+    }
+
+    fn get_action_impl(&self, loc: &Locale, ctx: &RawContext) -> Result<Action> {
         let cur_text = self
             .game
             .find_para_fallback(loc, &ctx.cur_base_para, &ctx.cur_para)
-            .map(|p| p.texts.get(ctx.cur_act))
+            .map(|p| p.find_line(ctx.cur_id.as_deref(), ctx.cur_act))
             .flatten();
 
         let action = cur_text
-            .map(|t| match t {
-                Line::Text(t) => self.parse_text(loc, t, ctx).map(Action::Text).ok(),
-                Line::Switch { switches } => Some(Action::Switches(self.parse_switches(switches))),
+            .map(|t| match &t.line {
+                Line::Text(text) => self
+                    .parse_text(loc, text, ctx, &t.source)
+                    .map(|mut text| {
+                        text.timeout = t.timeout.map(|timeout| timeout.duration);
+                        Action::Text(text)
+                    })
+                    .ok(),
+                Line::Switch { switches } => {
+                    let enabled = Self::switches_enabled(&ctx.locals, switches.len());
+                    Some(Action::Switches(ActionSwitches {
+                        switches: Self::parse_switches(switches, &enabled),
+                        timeout: t.timeout.map(|timeout| timeout.duration),
+                        default: t
+                            .timeout
+                            .and_then(|timeout| Self::default_switch(timeout.default, &enabled)),
+                    }))
+                }
+                Line::Input {
+                    prompt, default, ..
+                } => {
+                    let prompt = match prompt {
+                        Some(prompt) => self.parse_text(loc, prompt, ctx, &t.source).ok()?,
+                        None => ActionText::default(),
+                    };
+                    Some(Action::Input(ActionInput {
+                        prompt,
+                        default: default.clone(),
+                    }))
+                }
                 // The real vars will be filled in `merge_action`.
                 Line::Custom(_) => Some(Action::Custom(self.vars.clone())),
                 _ => None,
             })
             .flatten();
 
-        let mut act = self.merge_action(action)?;
+        let mut act = action
+            .try_merge(|action| self.merge_action(action))?
+            .unwrap_or_default();
         if let Action::Text(act) = &mut act {
-            self.process_action_text(ctx, act)?;
+            self.process_action_text(loc, ctx, act)?;
         }
         Ok(act)
     }
 
     /// Step to next line.
-    pub fn next_run(&mut self) -> Option<RawContext> {
+    /// The call lines are skipped, and the first line of the called paragraph is returned.
+    ///
+    /// The plugins generate random numbers from the state stored in the [`RawContext`],
+    /// so that the outcomes are the same after loading a record.
+    ///
+    /// The line plugins and the scripts get the locale `loc`.
+    ///
+    /// It fails if the calls are deeper than [`MAX_CALL_DEPTH`],
+    /// or the call lines call each other without other lines between them.
+    pub fn next_run(&mut self, loc: &Locale) -> Result<Option<RawContext>> {
+        self.with_ctx_rng(|this| this.next_run_calls(loc))
+    }
+
+    // The plugins called by `&self` methods use the state in the current context,
+    // but the changes are discarded.
+    fn with_rng<T>(&self, f: impl FnOnce() -> T) -> T {
+        let state = self.ctx.rng.unwrap_or_else(|| self.runtime.rng_state());
+        self.runtime.with_rng_state(state, f).0
+    }
+
+    // The plugins called by `&mut self` methods use and update the state in the current context.
+    fn with_ctx_rng<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let state = *self
+            .ctx
+            .rng
+            .get_or_insert_with(|| RngState::new(self.seed.unwrap_or_else(rand::random)));
+        let runtime = self.runtime.clone();
+        let (res, state) = runtime.with_rng_state(state, || f(self));
+        self.ctx.rng = Some(state);
+        res
+    }
+
+    // The call lines show nothing, so the called paragraphs are run directly.
+    fn next_run_calls(&mut self, loc: &Locale) -> Result<Option<RawContext>> {
+        let mut calls = HashSet::new();
+        loop {
+            match self.next_run_impl(loc)? {
+                Some(ctx) if self.is_call_line(&ctx) => {
+                    // The same call line is reached again, without any line shown.
+                    if !calls.insert((ctx.cur_base_para.clone(), ctx.cur_para.clone(), ctx.cur_act))
+                    {
+                        bail!(
+                            "Paragraph call cycle at {}",
+                            SourceSpan::line(&ctx.cur_base_para, &ctx.cur_para, ctx.cur_act)
+                        );
+                    }
+                }
+                res => return Ok(res),
+            }
+        }
+    }
+
+    fn is_call_line(&self, ctx: &RawContext) -> bool {
+        let line = self
+            .game
+            .find_para(
+                &self.game.config.base_lang,
+                &ctx.cur_base_para,
+                &ctx.cur_para,
+            )
+            .and_then(|p| p.line(ctx.cur_act));
+        matches!(line, Some(Line::Call { .. }))
+    }
+
+    fn next_run_impl(&mut self, loc: &Locale) -> Result<Option<RawContext>> {
         let cur_text_base = loop {
             let cur_para = self.current_paragraph(&self.game.config.base_lang);
             let cur_text = self.current_text(&self.game.config.base_lang);
             match (cur_para.is_some(), cur_text.is_some()) {
-                (true, true) => break cur_text,
+                (true, true) => break cur_text.cloned(),
                 (true, false) => {
-                    self.ctx.cur_para = cur_para
-                        .and_then(|p| p.next.as_ref())
-                        .map(|text| unwrap_or_default_log!(self.call(text), "Cannot get next para"))
-                        .unwrap_or_default();
-                    self.ctx.cur_act = 0;
+                    let next = cur_para.and_then(|p| p.next.clone());
+                    self.leave_paragraph();
+                    self.goto_next_para(loc, next.as_ref())?;
                 }
                 (false, _) => {
                     if self.ctx.cur_base_para == self.ctx.cur_para {
@@ -549,7 +1181,7 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
                                 self.ctx.cur_para.escape_default()
                             );
                         }
-                        return None;
+                        return Ok(None);
                     } else {
                         self.ctx.cur_base_para = self.ctx.cur_para.clone();
                     }
@@ -557,12 +1189,162 @@ impl<M: RawModule + Send + Sync + 'static> Context<M> {
             }
         };
 
-        let ctx = cur_text_base.cloned().map(|t| {
-            unwrap_or_default_log!(self.process_line(t), "Parse line error");
-            self.ctx.clone()
-        });
+        if self.ctx.cur_act == 0 {
+            self.process_paragraph(ParagraphEvent::Enter);
+        }
+
+        self.ctx.cur_id = self
+            .current_paragraph(&self.game.config.base_lang)
+            .and_then(|p| p.line_id(self.ctx.cur_act))
+            .map(|id| id.to_string());
+        let timeout = self
+            .current_paragraph(&self.game.config.base_lang)
+            .and_then(|p| p.line_timeout(self.ctx.cur_act))
+            .copied();
+
+        let Some(t) = cur_text_base else {
+            return Ok(None);
+        };
+        let call = match &t {
+            Line::Call { call } => Some(call.clone()),
+            _ => None,
+        };
+        let is_switch = matches!(t, Line::Switch { .. });
+        let flow = unwrap_or_default_log!(
+            self.process_line(loc, t),
+            format!(
+                "Parse line error at {}",
+                self.source_span(&self.game.config.base_lang, &self.ctx)
+            )
+        );
+        if is_switch {
+            self.timeout_switch =
+                timeout.and_then(|timeout| Self::default_switch(timeout.default, &self.switches));
+        }
+        self.ctx.rng = Some(self.runtime.rng_state());
+        let ctx = self.ctx.clone();
         self.ctx.cur_act += 1;
-        ctx
+        self.ctx.cur_id = None;
+        if let Some(call) = call {
+            // The call line is skipped if the called paragraph is unknown.
+            if let Some(tag) = self.called_para(loc, &call) {
+                self.call_para(tag, self.ctx.cur_para.clone(), self.ctx.cur_act)?;
+            }
+        }
+        if let Some(flow) = flow {
+            self.apply_flow(flow);
+        }
+        Ok(Some(ctx))
+    }
+
+    // The paragraphs without lines are not entered, so they are not left either.
+    fn leave_paragraph(&mut self) {
+        if self.ctx.cur_act > 0 {
+            self.process_paragraph(ParagraphEvent::Leave);
+        }
+    }
+
+    fn called_para(&self, loc: &Locale, call: &Text) -> Option<String> {
+        match self.call_impl(loc, call) {
+            Ok(tag) if !tag.is_empty() => Some(tag),
+            Ok(_) => {
+                error!(
+                    "The called paragraph is empty, at {}",
+                    self.source_span(&self.game.config.base_lang, &self.ctx)
+                );
+                None
+            }
+            Err(e) => {
+                error!("Cannot get called para: {:#}", e);
+                None
+            }
+        }
+    }
+
+    fn apply_flow(&mut self, flow: FlowDirective) {
+        match flow {
+            FlowDirective::Jump(tag) => {
+                self.leave_paragraph();
+                self.goto_para(tag);
+            }
+            FlowDirective::Skip(count) => {
+                self.ctx.cur_act = self.ctx.cur_act.saturating_add(count);
+            }
+            // The paragraph is left in `next_run`, and the `next` is applied.
+            FlowDirective::EndParagraph => {
+                if let Some(len) = self
+                    .current_paragraph(&self.game.config.base_lang)
+                    .map(|p| p.texts.len())
+                {
+                    self.ctx.cur_act = self.ctx.cur_act.max(len);
+                }
+            }
+        }
+    }
+
+    fn call_para(&mut self, tag: String, ret_para: String, ret_act: usize) -> Result<()> {
+        if self.ctx.call_stack.len() >= MAX_CALL_DEPTH {
+            bail!(
+                "The paragraph calls are deeper than {}, at {}",
+                MAX_CALL_DEPTH,
+                self.source_span(&self.game.config.base_lang, &self.ctx)
+            );
+        }
+        let ret_id = self
+            .game
+            .find_para(
+                &self.game.config.base_lang,
+                &self.ctx.cur_base_para,
+                &ret_para,
+            )
+            .and_then(|p| p.line_id(ret_act))
+            .map(|id| id.to_string());
+        self.ctx.call_stack.push(CallFrame {
+            cur_base_para: self.ctx.cur_base_para.clone(),
+            cur_para: ret_para,
+            cur_act: ret_act,
+            cur_id: ret_id,
+        });
+        self.ctx.cur_para = tag;
+        self.ctx.cur_act = 0;
+        Ok(())
+    }
+
+    fn goto_next_para(&mut self, loc: &Locale, next: Option<&Next>) -> Result<()> {
+        let tag = match next {
+            Some(Next::Call { call, to }) => {
+                let to = to.as_ref().map(|to| {
+                    unwrap_or_default_log!(self.call_impl(loc, to), "Cannot get next para")
+                });
+                match (self.called_para(loc, call), to) {
+                    (Some(tag), Some(to)) => return self.call_para(tag, to, 0),
+                    // The called paragraph returns to the caller of the current one.
+                    (Some(tag), None) => tag,
+                    // The paragraph continues to `to` if the called one is unknown.
+                    (None, to) => to.unwrap_or_default(),
+                }
+            }
+            Some(next) => {
+                unwrap_or_default_log!(self.next_para(loc, next), "Cannot get next para")
+            }
+            None => String::default(),
+        };
+        self.goto_para(tag);
+        Ok(())
+    }
+
+    fn goto_para(&mut self, tag: String) {
+        // The called paragraph ends, return to the caller.
+        if tag.is_empty() {
+            if let Some(frame) = self.ctx.call_stack.pop() {
+                self.ctx.cur_base_para = frame.cur_base_para;
+                self.ctx.cur_para = frame.cur_para;
+                self.ctx.cur_act = frame.cur_act;
+                return;
+            }
+        }
+        self.ctx.cur_para = tag;
+        self.ctx.cur_act = 0;
     }
 
     /// Get current paragraph title.
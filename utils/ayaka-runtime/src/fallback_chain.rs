@@ -0,0 +1,109 @@
+//! The multi-step fallback.
+
+use fallback::Fallback;
+
+/// Stores the layers of a locale fallback chain,
+/// from the most specific one to the base language.
+///
+/// It generalizes [`Fallback`] to an arbitrary number of layers.
+/// ```
+/// # use ayaka_runtime::FallbackChain;
+/// let chain = FallbackChain::new(vec![None, Some("hello"), Some("123")]);
+/// let num = chain.and_then(|s| s.parse::<i32>().ok());
+/// assert_eq!(num, Some(123));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackChain<T> {
+    layers: Vec<Option<T>>,
+}
+
+impl<T> FallbackChain<T> {
+    /// Creates a new [`FallbackChain`].
+    pub const fn new(layers: Vec<Option<T>>) -> Self {
+        Self { layers }
+    }
+
+    /// Returns `false` if all layers are [`None`].
+    pub fn is_some(&self) -> bool {
+        self.layers.iter().any(|data| data.is_some())
+    }
+
+    /// Fallbacks the data or part of data, layer by layer.
+    pub fn and_then<V>(self, f: impl FnMut(T) -> Option<V>) -> Option<V> {
+        self.layers.into_iter().flatten().find_map(f)
+    }
+
+    /// Fallbacks the total data.
+    pub fn fallback(self) -> Option<T> {
+        self.layers.into_iter().flatten().next()
+    }
+
+    /// Maps to a new [`FallbackChain`].
+    pub fn map<V>(self, mut f: impl FnMut(T) -> V) -> FallbackChain<V> {
+        FallbackChain::new(
+            self.layers
+                .into_iter()
+                .map(|data| data.map(&mut f))
+                .collect(),
+        )
+    }
+
+    /// Merges the layers from the base language,
+    /// with a function merging two adjacent layers.
+    ///
+    /// ```
+    /// # use ayaka_runtime::FallbackChain;
+    /// let chain = FallbackChain::new(vec![Some(vec![1]), None, Some(vec![2]), Some(vec![3])]);
+    /// let merged = chain.merge(|f| {
+    ///     let (data, base_data) = f.unzip();
+    ///     Some([data?, base_data?].concat())
+    /// });
+    /// assert_eq!(merged, Some(vec![1, 2, 3]));
+    /// ```
+    pub fn merge(self, mut f: impl FnMut(Fallback<T>) -> Option<T>) -> Option<T> {
+        self.try_merge(|data| Ok::<_, std::convert::Infallible>(f(data)))
+            .unwrap_or_else(|e| match e {})
+    }
+
+    /// Merges the layers from the base language,
+    /// with a fallible function merging two adjacent layers.
+    pub fn try_merge<E>(
+        self,
+        mut f: impl FnMut(Fallback<T>) -> Result<Option<T>, E>,
+    ) -> Result<Option<T>, E> {
+        let mut merged = None;
+        for data in self.layers.into_iter().rev() {
+            merged = match (data, merged) {
+                (None, merged) => merged,
+                (data, None) => data,
+                (data, merged) => f(Fallback::new(data, merged))?,
+            };
+        }
+        Ok(merged)
+    }
+
+    /// Gets the layers.
+    pub fn into_layers(self) -> Vec<Option<T>> {
+        self.layers
+    }
+}
+
+impl<T> FallbackChain<Option<T>> {
+    /// Converts from `FallbackChain<Option<T>>` to `FallbackChain<T>`.
+    pub fn flatten(self) -> FallbackChain<T> {
+        FallbackChain::new(self.layers.into_iter().map(Option::flatten).collect())
+    }
+}
+
+impl<T> From<Fallback<T>> for FallbackChain<T> {
+    fn from(data: Fallback<T>) -> Self {
+        let (data, base_data) = data.unzip();
+        Self::new(vec![data, base_data])
+    }
+}
+
+impl<T> From<FallbackChain<T>> for Option<T> {
+    fn from(data: FallbackChain<T>) -> Self {
+        data.fallback()
+    }
+}
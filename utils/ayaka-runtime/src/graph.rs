@@ -0,0 +1,334 @@
+//! The static story graph.
+
+use crate::{analysis::*, *};
+use ayaka_script::{BinaryOp, Expr};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// A paragraph in the [`StoryGraph`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoryNode {
+    /// The base paragraph tag, i.e., the file name.
+    pub base_para: String,
+    /// The paragraph tag.
+    pub para: String,
+    /// The title of the paragraph.
+    pub title: Option<String>,
+}
+
+/// The kind of a [`StoryEdge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoryEdgeKind {
+    /// From a text `next`.
+    Next,
+    /// From a switch map `next`, or a `next` depending on the chosen switch.
+    Switch,
+    /// From a guard in `next`.
+    Guard,
+    /// Calling a paragraph, from a `call` line or `next`.
+    Call,
+}
+
+/// A transition in the [`StoryGraph`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoryEdge {
+    /// The index of the source node.
+    pub from: usize,
+    /// The index of the target node.
+    /// If [`None`], the target cannot be determined or found.
+    pub to: Option<usize>,
+    /// The target tag, or the source of the `next` text if it cannot be determined.
+    pub target: String,
+    /// The kind of the edge.
+    pub kind: StoryEdgeKind,
+    /// The label, e.g., the switch text or the guard condition.
+    pub label: Option<String>,
+    /// Whether the target depends on the variables at runtime.
+    pub dynamic: bool,
+    /// Whether the target cannot be determined statically.
+    pub undetermined: bool,
+}
+
+/// The directed graph of the transitions between paragraphs in the base language.
+///
+/// The targets depending on `\var{?}` are expanded with the switches in the paragraph,
+/// and the targets depending on other variables are expanded with the
+/// string values assigned to them by `exec` in the paragraph, marked as dynamic.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct StoryGraph {
+    /// The paragraphs, ordered by file names.
+    pub nodes: Vec<StoryNode>,
+    /// The transitions.
+    pub edges: Vec<StoryEdge>,
+    /// The index of the start paragraph.
+    pub start: Option<usize>,
+}
+
+// The maximum count of the expanded targets of one `next` text.
+const MAX_TARGETS: usize = 64;
+
+// A candidate piece of a target.
+#[derive(Debug, Clone)]
+struct Piece {
+    value: String,
+    label: Option<String>,
+    switch: bool,
+    dynamic: bool,
+    unknown: bool,
+}
+
+impl Piece {
+    fn literal(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            label: None,
+            switch: false,
+            dynamic: false,
+            unknown: false,
+        }
+    }
+}
+
+// The candidate values of a context variable, from the `exec` lines.
+#[derive(Debug, Default)]
+struct Assigned {
+    values: BTreeSet<String>,
+    complete: bool,
+}
+
+impl StoryGraph {
+    /// Builds the graph from the paragraphs of the base language.
+    pub fn new(game: &Game) -> Self {
+        let mut graph = Self::default();
+        let Some(paras) = game.base_paras() else {
+            return graph;
+        };
+        let mut files = paras.keys().collect::<Vec<_>>();
+        files.sort();
+        let mut indices = HashMap::new();
+        for file in files {
+            for p in &paras[file] {
+                indices.insert((file.as_str(), p.tag.as_str()), graph.nodes.len());
+                graph.nodes.push(StoryNode {
+                    base_para: file.clone(),
+                    para: p.tag.clone(),
+                    title: p.title.clone(),
+                });
+            }
+        }
+        let resolve = |base_para: &str, tag: &str| {
+            indices
+                .get(&(base_para, tag))
+                .or_else(|| indices.get(&(tag, tag)))
+                .copied()
+        };
+        graph.start = resolve(&game.config.start, &game.config.start);
+
+        for from in 0..graph.nodes.len() {
+            let base_para = graph.nodes[from].base_para.clone();
+            let p = &paras[&base_para]
+                .iter()
+                .find(|p| p.tag == graph.nodes[from].para)
+                .expect("the node should be built from the paragraph");
+            let mut push = |text: &Text, kind: StoryEdgeKind, label: Option<&String>| {
+                for piece in Self::targets(p, text) {
+                    let to = if piece.unknown {
+                        None
+                    } else {
+                        resolve(&base_para, &piece.value)
+                    };
+                    graph.edges.push(StoryEdge {
+                        from,
+                        to,
+                        target: piece.value,
+                        kind: if piece.switch {
+                            StoryEdgeKind::Switch
+                        } else {
+                            kind
+                        },
+                        label: label.cloned().or(piece.label),
+                        dynamic: piece.dynamic,
+                        undetermined: piece.unknown,
+                    });
+                }
+            };
+            for line in &p.texts {
+                if let Line::Call { call } = &line.line {
+                    push(call, StoryEdgeKind::Call, None);
+                }
+            }
+            match &p.next {
+                None => {}
+                Some(Next::Text(text)) => push(text, StoryEdgeKind::Next, None),
+                Some(Next::Switch(tags)) => {
+                    let switches = Self::switches(p);
+                    for (i, text) in tags {
+                        push(
+                            text,
+                            StoryEdgeKind::Switch,
+                            Some(&switches.get(*i).cloned().unwrap_or_else(|| i.to_string())),
+                        );
+                    }
+                }
+                Some(Next::Guards(guards)) => {
+                    for guard in guards {
+                        push(&guard.to, StoryEdgeKind::Guard, guard.cond.as_ref());
+                    }
+                }
+                Some(Next::Call { call, to }) => {
+                    push(call, StoryEdgeKind::Call, None);
+                    if let Some(to) = to {
+                        push(to, StoryEdgeKind::Next, None);
+                    }
+                }
+            }
+        }
+        graph
+    }
+
+    /// Finds the node index by the base paragraph tag and the paragraph tag.
+    pub fn find_node(&self, base_para: &str, para: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.base_para == base_para && node.para == para)
+    }
+
+    /// Iterates the edges from the node.
+    pub fn edges_from(&self, from: usize) -> impl Iterator<Item = &StoryEdge> {
+        self.edges.iter().filter(move |edge| edge.from == from)
+    }
+
+    // The switches of the last switch line.
+    fn switches(p: &Paragraph) -> Vec<String> {
+        p.texts
+            .iter()
+            .rev()
+            .find_map(|line| match &line.line {
+                Line::Switch { switches } => Some(switches.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    // Expands the possible values of a `next` text.
+    fn targets(p: &Paragraph, text: &Text) -> Vec<Piece> {
+        let mut assigned = None;
+        let mut targets = vec![Piece::literal("")];
+        for sub_text in &text.sub_texts {
+            let pieces = match sub_text {
+                SubText::Char(c, _) => vec![Piece::literal(*c)],
+                SubText::Str(s, _) => vec![Piece::literal(s)],
+                SubText::Cmd(cmd, args, _) => match (cmd.as_str(), text_arg(args)) {
+                    ("var", Some(name)) if name == "?" => {
+                        let switches = Self::switches(p);
+                        if switches.is_empty() {
+                            return vec![Self::dynamic(text)];
+                        }
+                        switches
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, s)| Piece {
+                                label: Some(s),
+                                switch: true,
+                                ..Piece::literal(i.to_string())
+                            })
+                            .collect()
+                    }
+                    ("var", Some(name)) => {
+                        let assigned = assigned.get_or_insert_with(|| Self::assigned(p));
+                        match assigned.get(&name) {
+                            Some(a) if a.complete && !a.values.is_empty() => a
+                                .values
+                                .iter()
+                                .map(|value| Piece {
+                                    dynamic: true,
+                                    ..Piece::literal(value)
+                                })
+                                .collect(),
+                            _ => return vec![Self::dynamic(text)],
+                        }
+                    }
+                    _ => return vec![Self::dynamic(text)],
+                },
+            };
+            if targets.len() * pieces.len() > MAX_TARGETS {
+                return vec![Self::dynamic(text)];
+            }
+            targets = targets
+                .iter()
+                .flat_map(|target| {
+                    pieces.iter().map(move |piece| Piece {
+                        value: target.value.clone() + &piece.value,
+                        label: target.label.clone().or_else(|| piece.label.clone()),
+                        switch: target.switch || piece.switch,
+                        dynamic: target.dynamic || piece.dynamic,
+                        unknown: false,
+                    })
+                })
+                .collect();
+        }
+        targets
+            .into_iter()
+            .map(|target| Piece {
+                value: target.value.trim().to_string(),
+                ..target
+            })
+            // An empty tag means the end.
+            .filter(|target| !target.value.is_empty())
+            .collect()
+    }
+
+    fn dynamic(text: &Text) -> Piece {
+        Piece {
+            dynamic: true,
+            unknown: true,
+            ..Piece::literal(text.to_string())
+        }
+    }
+
+    // Collects the values assigned to the context variables in the `exec` lines.
+    fn assigned(p: &Paragraph) -> HashMap<String, Assigned> {
+        let mut assigned = HashMap::<String, Assigned>::new();
+        let programs = p.texts.iter().flat_map(|line| match &line.line {
+            Line::Custom(props) => line_programs(props).collect(),
+            _ => vec![],
+        });
+        for program in programs {
+            for expr in &program.0 {
+                visit_assignments(expr, &mut |a| {
+                    let entry = assigned
+                        .entry(a.name.to_string())
+                        .or_insert_with(|| Assigned {
+                            complete: true,
+                            ..Default::default()
+                        });
+                    // The items and the in-place operations change the values unknown.
+                    if a.whole && *a.op == BinaryOp::Assign {
+                        entry.complete &= Self::values(a.value, &mut entry.values);
+                    } else {
+                        entry.complete = false;
+                    }
+                });
+            }
+        }
+        assigned
+    }
+
+    // Collects the possible string values of an expression.
+    // Returns false if some values cannot be determined.
+    fn values(expr: &Expr, values: &mut BTreeSet<String>) -> bool {
+        match expr {
+            Expr::Const(RawValue::Unit) => true,
+            Expr::Const(value @ (RawValue::Str(_) | RawValue::Num(_))) => {
+                values.insert(value.get_str().into_owned());
+                true
+            }
+            Expr::Call(ns, name, args) if ns.is_empty() && name == "if" && args.len() > 1 => args
+                [1..]
+                .iter()
+                .fold(true, |complete, arg| Self::values(arg, values) && complete),
+            _ => false,
+        }
+    }
+}
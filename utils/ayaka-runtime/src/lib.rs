@@ -9,27 +9,38 @@
 #![feature(generators)]
 #![feature(lazy_cell)]
 
+mod analysis;
 mod config;
 mod context;
+mod fallback_chain;
+mod graph;
+mod lint;
 mod locale;
 pub mod plugin;
+mod translation;
 
 #[doc(no_inline)]
 pub use anyhow;
 #[doc(no_inline)]
 pub use ayaka_bindings_types::*;
 #[doc(no_inline)]
-pub use ayaka_plugin::{Linker, RawModule};
+pub use ayaka_plugin::{
+    LimitError, Linker, LinkerConfig, ModuleLimits, PermissionError, RawModule,
+};
 #[doc(no_inline)]
 pub use ayaka_primitive::*;
 pub use config::*;
 pub use context::*;
+pub use fallback_chain::*;
 #[doc(no_inline)]
 pub use futures_util::{StreamExt, TryStreamExt};
+pub use graph::*;
+pub use lint::*;
 #[doc(no_inline)]
 pub use locale::*;
 #[doc(no_inline)]
 pub use log;
+pub use translation::*;
 #[doc(no_inline)]
 pub use vfs;
 
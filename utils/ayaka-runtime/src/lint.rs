@@ -0,0 +1,476 @@
+//! The static checks of a game.
+
+use crate::{analysis::*, plugin::Runtime, *};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+};
+
+/// The kind of a [`LintDiagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintKind {
+    /// The paragraph cannot be reached from the start paragraph.
+    UnreachableParagraph,
+    /// The target of `next` or `call` doesn't exist.
+    MissingNext,
+    /// The `\res{}` key doesn't exist in the resources of the locale.
+    MissingResource,
+    /// The `\var{}` reads a variable which is never assigned.
+    UnassignedVariable,
+    /// No loaded plugin provides the key in the custom line,
+    /// neither as a command, nor as a prop of the commands in the line.
+    UnknownLineCommand,
+    /// No loaded plugin provides the text command.
+    UnknownTextCommand,
+    /// The switches differ from the ones in the base language.
+    SwitchMismatch,
+}
+
+impl LintKind {
+    /// The name of the kind, same as the serialized one.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UnreachableParagraph => "unreachable_paragraph",
+            Self::MissingNext => "missing_next",
+            Self::MissingResource => "missing_resource",
+            Self::UnassignedVariable => "unassigned_variable",
+            Self::UnknownLineCommand => "unknown_line_command",
+            Self::UnknownTextCommand => "unknown_text_command",
+            Self::SwitchMismatch => "switch_mismatch",
+        }
+    }
+}
+
+/// The severity of a [`LintDiagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintSeverity {
+    /// The problem doesn't break the game, but may be unexpected.
+    Warning,
+    /// The problem breaks the game.
+    Error,
+}
+
+impl LintSeverity {
+    /// The name of the severity, same as the serialized one.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// A problem found by [`Context::lint`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintDiagnostic {
+    /// The severity of the problem.
+    pub severity: LintSeverity,
+    /// The kind of the problem.
+    pub kind: LintKind,
+    /// The locale of the paragraph, if the problem is specific to it.
+    pub locale: Option<Locale>,
+    /// The base paragraph tag, i.e., the file name.
+    pub base_para: String,
+    /// The paragraph tag.
+    pub para: String,
+    /// The index of the line, if the problem is in a line.
+    pub line: Option<usize>,
+    /// The message.
+    pub message: String,
+}
+
+impl Display for LintDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}[{}]: ", self.severity.name(), self.kind.name())?;
+        match self.line {
+            Some(index) => write!(
+                f,
+                "{}",
+                SourceSpan::line(&self.base_para, &self.para, index)
+            )?,
+            None => write!(
+                f,
+                "{}: paragraph \"{}\"",
+                self.base_para,
+                self.para.escape_default()
+            )?,
+        }
+        if let Some(loc) = &self.locale {
+            write!(f, " ({})", loc)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+struct Linter<'a, M: RawModule + Send + Sync + 'static> {
+    game: &'a Game,
+    base_loc: &'a Locale,
+    runtime: &'a Runtime<M>,
+    assigned: HashSet<String>,
+    diagnostics: Vec<LintDiagnostic>,
+}
+
+impl<'a, M: RawModule + Send + Sync + 'static> Linter<'a, M> {
+    fn push(
+        &mut self,
+        kind: LintKind,
+        locale: Option<&Locale>,
+        base_para: &str,
+        para: &str,
+        line: Option<usize>,
+        message: String,
+    ) {
+        let severity = match kind {
+            // The unassigned variables are empty,
+            // and the unreachable paragraphs are never shown.
+            LintKind::UnreachableParagraph | LintKind::UnassignedVariable => LintSeverity::Warning,
+            // The resources fallback to the base language.
+            LintKind::MissingResource if locale != Some(self.base_loc) => LintSeverity::Warning,
+            _ => LintSeverity::Error,
+        };
+        self.diagnostics.push(LintDiagnostic {
+            severity,
+            kind,
+            locale: locale.cloned(),
+            base_para: base_para.to_string(),
+            para: para.to_string(),
+            line,
+            message,
+        })
+    }
+
+    fn graph(&mut self) {
+        let graph = StoryGraph::new(self.game);
+        let start = &self.game.config.start;
+        let Some(start) = graph.start else {
+            self.push(
+                LintKind::MissingNext,
+                None,
+                start,
+                start,
+                None,
+                format!(
+                    "cannot find the start paragraph \"{}\"",
+                    start.escape_default()
+                ),
+            );
+            return;
+        };
+        for edge in &graph.edges {
+            if edge.to.is_none() && !edge.undetermined {
+                let node = &graph.nodes[edge.from];
+                self.push(
+                    LintKind::MissingNext,
+                    None,
+                    &node.base_para,
+                    &node.para,
+                    None,
+                    format!("cannot find paragraph \"{}\"", edge.target.escape_default()),
+                );
+            }
+        }
+        let mut visited = vec![false; graph.nodes.len()];
+        let mut queue = VecDeque::from([start]);
+        visited[start] = true;
+        while let Some(from) = queue.pop_front() {
+            for to in graph.edges_from(from).filter_map(|edge| edge.to) {
+                if !visited[to] {
+                    visited[to] = true;
+                    queue.push_back(to);
+                }
+            }
+        }
+        // The targets of some edges are unknown, so the paragraphs may be reached.
+        let uncertain = graph
+            .edges
+            .iter()
+            .any(|edge| visited[edge.from] && edge.undetermined);
+        for (node, _) in graph.nodes.iter().zip(visited).filter(|(_, v)| !v) {
+            self.push(
+                LintKind::UnreachableParagraph,
+                None,
+                &node.base_para,
+                &node.para,
+                None,
+                if uncertain {
+                    "the paragraph may be unreachable, because some targets cannot be determined"
+                        .to_string()
+                } else {
+                    "the paragraph is unreachable".to_string()
+                },
+            );
+        }
+    }
+
+    fn collect_assigned(&mut self, paras: &HashMap<String, Vec<Paragraph>>) {
+        self.assigned.insert("?".to_string());
+        for line in paras.values().flatten().flat_map(|p| &p.texts) {
+            if let Line::Input { input, .. } = &line.line {
+                self.assigned.insert(input.clone());
+            }
+            if let Line::Custom(props) = &line.line {
+                for (key, _) in props {
+                    // The line commands usually set the local variables with the same names.
+                    if self.runtime.line_module(key).is_some() {
+                        self.assigned.insert(key.clone());
+                    }
+                }
+                if self.runtime.line_module(SCRIPT_COMMAND).is_some() {
+                    for program in line_programs(props) {
+                        for expr in &program.0 {
+                            visit_assignments(expr, &mut |a| {
+                                self.assigned.insert(a.name.to_string());
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn lines(&mut self, paras: &HashMap<String, Vec<Paragraph>>) {
+        let mut files = paras.keys().collect::<Vec<_>>();
+        files.sort();
+        for file in files {
+            for p in &paras[file] {
+                for (i, line) in p.texts.iter().enumerate() {
+                    match &line.line {
+                        Line::Text(text) => {
+                            self.text(self.base_loc, file, &p.tag, Some(i), text, true)
+                        }
+                        Line::Call { call } => {
+                            self.text(self.base_loc, file, &p.tag, Some(i), call, false)
+                        }
+                        Line::Input {
+                            prompt: Some(prompt),
+                            ..
+                        } => self.text(self.base_loc, file, &p.tag, Some(i), prompt, true),
+                        Line::Custom(props) => {
+                            let cmds = props
+                                .iter()
+                                .map(|(key, _)| key)
+                                .filter(|key| self.runtime.line_module(key).is_some())
+                                .collect::<Vec<_>>();
+                            for (key, _) in props {
+                                let known = cmds.iter().any(|cmd| {
+                                    *cmd == key || self.runtime.accepts_line_prop(cmd, key)
+                                });
+                                if !known {
+                                    self.push(
+                                        LintKind::UnknownLineCommand,
+                                        None,
+                                        file,
+                                        &p.tag,
+                                        Some(i),
+                                        format!("cannot find line command or prop `{}`", key),
+                                    );
+                                }
+                            }
+                        }
+                        Line::Empty | Line::Switch { .. } | Line::Input { .. } => {}
+                    }
+                }
+                let next_texts = match &p.next {
+                    None => vec![],
+                    Some(Next::Text(text)) => vec![text],
+                    Some(Next::Switch(tags)) => tags.values().collect(),
+                    Some(Next::Guards(guards)) => guards.iter().map(|guard| &guard.to).collect(),
+                    Some(Next::Call { call, to }) => std::iter::once(call).chain(to).collect(),
+                };
+                for text in next_texts {
+                    self.text(self.base_loc, file, &p.tag, None, text, false);
+                }
+            }
+        }
+    }
+
+    fn translated_lines(&mut self, loc: &Locale, paras: &HashMap<String, Vec<Paragraph>>) {
+        let mut files = paras.keys().collect::<Vec<_>>();
+        files.sort();
+        for file in files {
+            for p in &paras[file] {
+                let loc_para = self.game.find_para(loc, file, &p.tag);
+                for (i, base_line) in p.texts.iter().enumerate() {
+                    let line = loc_para
+                        .and_then(|loc_para| loc_para.find_line(base_line.id.as_deref(), i))
+                        .map(|line| &line.line);
+                    match (&base_line.line, line) {
+                        (_, None | Some(Line::Empty)) => {
+                            // Falls back to the base line.
+                            if let Line::Text(text) = &base_line.line {
+                                self.resources(loc, file, &p.tag, i, &text.sub_texts);
+                            }
+                        }
+                        (Line::Switch { switches: base }, Some(Line::Switch { switches }))
+                            if base.len() != switches.len() =>
+                        {
+                            self.push(
+                                LintKind::SwitchMismatch,
+                                Some(loc),
+                                file,
+                                &p.tag,
+                                Some(i),
+                                format!(
+                                    "{} switches, but {} in the base language",
+                                    switches.len(),
+                                    base.len()
+                                ),
+                            );
+                        }
+                        (Line::Switch { .. }, Some(Line::Switch { .. })) => {}
+                        (Line::Switch { .. }, Some(_)) => self.push(
+                            LintKind::SwitchMismatch,
+                            Some(loc),
+                            file,
+                            &p.tag,
+                            Some(i),
+                            "expected switches as the base language".to_string(),
+                        ),
+                        (_, Some(Line::Switch { .. })) => self.push(
+                            LintKind::SwitchMismatch,
+                            Some(loc),
+                            file,
+                            &p.tag,
+                            Some(i),
+                            "unexpected switches, not in the base language".to_string(),
+                        ),
+                        (_, Some(Line::Text(text))) => {
+                            self.text(loc, file, &p.tag, Some(i), text, true);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn text(
+        &mut self,
+        loc: &Locale,
+        base_para: &str,
+        para: &str,
+        line: Option<usize>,
+        text: &Text,
+        with_res: bool,
+    ) {
+        self.commands(loc, base_para, para, line, &text.sub_texts);
+        if let (true, Some(line)) = (with_res, line) {
+            self.resources(loc, base_para, para, line, &text.sub_texts);
+        }
+    }
+
+    // Walks the commands, and checks the variables and the text commands.
+    fn commands(
+        &mut self,
+        loc: &Locale,
+        base_para: &str,
+        para: &str,
+        line: Option<usize>,
+        sub_texts: &[SubText],
+    ) {
+        let locale = if loc == self.base_loc {
+            None
+        } else {
+            Some(loc)
+        };
+        for sub_text in sub_texts {
+            if let SubText::Cmd(cmd, args, _) = sub_text {
+                match (cmd.as_str(), text_arg(args)) {
+                    ("res", _) => {}
+                    ("var", Some(name)) => {
+                        if !self.assigned.contains(&name) {
+                            self.push(
+                                LintKind::UnassignedVariable,
+                                locale,
+                                base_para,
+                                para,
+                                line,
+                                format!("variable `{}` is never assigned", name),
+                            );
+                        }
+                    }
+                    ("var", None) => {}
+                    (cmd, _) => {
+                        if self.runtime.text_module(cmd).is_none() {
+                            self.push(
+                                LintKind::UnknownTextCommand,
+                                locale,
+                                base_para,
+                                para,
+                                line,
+                                format!("cannot find text command `{}`", cmd),
+                            );
+                        }
+                    }
+                }
+                self.commands(loc, base_para, para, line, args);
+            }
+        }
+    }
+
+    // Checks the `\res{}` keys in the resources of the locale.
+    fn resources(
+        &mut self,
+        loc: &Locale,
+        base_para: &str,
+        para: &str,
+        line: usize,
+        sub_texts: &[SubText],
+    ) {
+        let res_loc = self.game.choose_from_keys(loc, &self.game.res);
+        let res = self.game.res.get(res_loc);
+        for sub_text in sub_texts {
+            if let SubText::Cmd(cmd, args, _) = sub_text {
+                if let ("res", Some(key)) = (cmd.as_str(), text_arg(args)) {
+                    if !res.map(|res| res.contains_key(&key)).unwrap_or_default() {
+                        self.push(
+                            LintKind::MissingResource,
+                            Some(res_loc),
+                            base_para,
+                            para,
+                            Some(line),
+                            format!("cannot find resource `{}`", key),
+                        );
+                    }
+                }
+                self.resources(loc, base_para, para, line, args);
+            }
+        }
+    }
+}
+
+impl<M: RawModule + Send + Sync + 'static> Context<M> {
+    /// Checks the game statically, without running it.
+    ///
+    /// The variables are treated as assigned if they are assigned by `exec`,
+    /// or they have the same names as the line commands.
+    pub fn lint(&self) -> Vec<LintDiagnostic> {
+        let game = self.game();
+        let base_loc = game.choose_from_keys(&game.config.base_lang, &game.paras);
+        let mut linter = Linter {
+            game,
+            base_loc,
+            runtime: self.runtime(),
+            assigned: HashSet::new(),
+            diagnostics: vec![],
+        };
+        if let Some(paras) = game.paras.get(base_loc) {
+            linter.collect_assigned(paras);
+            linter.graph();
+            linter.lines(paras);
+            let mut locs = game
+                .paras
+                .keys()
+                .filter(|loc| *loc != base_loc)
+                .collect::<Vec<_>>();
+            locs.sort_by_key(|loc| loc.to_string());
+            for loc in locs {
+                linter.translated_lines(loc, paras);
+            }
+        }
+        linter.diagnostics
+    }
+}
@@ -1,4 +1,5 @@
 use icu_locid::{LanguageIdentifier, ParserError};
+use icu_locid_transform::fallback::{LocaleFallbackConfig, LocaleFallbacker};
 use language_matcher::LanguageMatcher;
 use serde::{Deserialize, Serialize};
 use std::{fmt::Display, str::FromStr, sync::LazyLock};
@@ -51,6 +52,31 @@ impl Locale {
             .matches(self.0.clone(), locales.into_iter().map(|loc| loc.into()))
             .map(|(lang, _)| lang)
     }
+
+    /// Get the parent locales defined by CLDR, from the nearest one.
+    /// The locale itself and the root locale are excluded.
+    ///
+    /// ```
+    /// # use ayaka_runtime::locale;
+    /// assert_eq!(
+    ///     locale!("en-GB").parents(),
+    ///     [locale!("en-001"), locale!("en")],
+    /// );
+    /// ```
+    pub fn parents(&self) -> Vec<Locale> {
+        let mut iter = LocaleFallbacker::new()
+            .for_config(LocaleFallbackConfig::default())
+            .fallback_for(self.0.clone().into());
+        let mut parents = vec![];
+        while !iter.get().is_und() {
+            let loc = Locale(iter.get().get_langid());
+            if &loc != self && !parents.contains(&loc) {
+                parents.push(loc);
+            }
+            iter.step();
+        }
+        parents
+    }
 }
 
 impl Display for Locale {
@@ -118,4 +144,14 @@ mod test {
             Some(&locale!("zh-Hant"))
         );
     }
+
+    #[test]
+    fn parents() {
+        assert_eq!(
+            locale!("es-MX").parents(),
+            [locale!("es-419"), locale!("es")]
+        );
+        assert_eq!(locale!("zh-TW").parents().last(), Some(&locale!("zh-Hant")));
+        assert!(locale!("ja").parents().is_empty());
+    }
 }
@@ -10,11 +10,12 @@ mod script_interop;
 mod test;
 
 use crate::*;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use ayaka_plugin::*;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     collections::HashMap,
-    sync::{Arc, RwLock, Weak},
+    sync::{Arc, Mutex, RwLock, Weak},
 };
 use stream_future::stream;
 use trylog::macros::*;
@@ -22,24 +23,60 @@ use vfs::*;
 
 /// The plugin module with high-level interfaces.
 pub struct Module<M: RawModule> {
+    name: String,
     module: PluginModule<M>,
+    metadata: PluginMetadata,
 }
 
 impl<M: RawModule> Module<M> {
-    fn new(module: M) -> Self {
+    fn new(name: String, module: M, limits: ModuleLimits) -> Self {
         Self {
-            module: PluginModule::new(module),
+            name,
+            module: PluginModule::with_limits(module, limits),
+            metadata: PluginMetadata::default(),
         }
     }
 
+    /// The name of the plugin.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The metadata of the plugin.
+    pub fn metadata(&self) -> &PluginMetadata {
+        &self.metadata
+    }
+
+    /// Calls a method, and converts the returned [`PluginError`] to an error.
+    ///
+    /// The native backend takes the owned args converted by `owned`.
+    fn call<P: Serialize, O: Send + 'static, R: DeserializeOwned + 'static>(
+        &self,
+        method: &str,
+        args: P,
+        owned: impl FnOnce(P) -> O,
+    ) -> Result<R> {
+        self.module
+            .call_with::<_, _, std::result::Result<R, PluginError>>(method, args, owned)
+            .and_then(|res| Ok(res?))
+            .with_context(|| format!("Calling \"{}\" in plugin \"{}\"", method, self.name))
+    }
+
+    /// Gets the ABI version of the plugin.
+    pub fn abi_version(&self) -> Result<u32> {
+        self.module.call(ABI_VERSION_NAME, ())
+    }
+
     /// Gets the [`PluginType`].
     pub fn plugin_type(&self) -> Result<PluginType> {
-        self.module.call("plugin_type", ())
+        self.call("plugin_type", (), |args| args)
     }
 
     /// Processes [`Action`] in action plugin.
     pub fn process_action(&self, ctx: ActionProcessContextRef) -> Result<ActionProcessResult> {
-        self.module.call("process_action", (ctx,))
+        self.call("process_action", (ctx,), |(ctx,)| {
+            (ActionProcessContext::from(ctx),)
+        })
     }
 
     /// Calls a custom command in the text plugin.
@@ -49,7 +86,9 @@ impl<M: RawModule> Module<M> {
         args: &[String],
         ctx: TextProcessContextRef,
     ) -> Result<TextProcessResult> {
-        self.module.call(name, (args, ctx))
+        self.call(name, (args, ctx), |(args, ctx)| {
+            (args.to_vec(), TextProcessContext::from(ctx))
+        })
     }
 
     /// Calls a custom command in the line plugin.
@@ -58,22 +97,111 @@ impl<M: RawModule> Module<M> {
         name: &str,
         ctx: LineProcessContextRef,
     ) -> Result<LineProcessResult> {
-        self.module.call(name, (ctx,))
+        self.call(name, (ctx,), |(ctx,)| (LineProcessContext::from(ctx),))
     }
 
     /// Processes [`Game`] when opening the config file.
     pub fn process_game(&self, ctx: GameProcessContextRef) -> Result<GameProcessResult> {
-        self.module.call("process_game", (ctx,))
+        self.call("process_game", (ctx,), |(ctx,)| {
+            (GameProcessContext::from(ctx),)
+        })
+    }
+
+    /// Processes the paragraph events in paragraph plugin.
+    pub fn process_paragraph(
+        &self,
+        ctx: ParagraphProcessContextRef,
+    ) -> Result<ParagraphProcessResult> {
+        self.call("process_paragraph", (ctx,), |(ctx,)| {
+            (ParagraphProcessContext::from(ctx),)
+        })
+    }
+
+    /// Processes the chosen switch in switch plugin.
+    pub fn process_switch(&self, ctx: SwitchProcessContextRef) -> Result<SwitchProcessResult> {
+        self.call("process_switch", (ctx,), |(ctx,)| {
+            (SwitchProcessContext::from(ctx),)
+        })
+    }
+
+    /// Processes the record events in record plugin.
+    pub fn process_record(&self, ctx: RecordProcessContextRef) -> Result<RecordProcessResult> {
+        self.call("process_record", (ctx,), |(ctx,)| {
+            (RecordProcessContext::from(ctx),)
+        })
     }
 }
 
+/// The error when a plugin is incompatible with the runtime.
+///
+/// It could be found by [`anyhow::Error::downcast_ref`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CompatibilityError {
+    /// The ABI version of the plugin is not [`ABI_VERSION`].
+    AbiVersion {
+        /// The name of the plugin.
+        name: String,
+        /// The ABI version of the plugin, or [`None`] if it is not exported.
+        version: Option<u32>,
+    },
+    /// The plugin requires a newer runtime.
+    RuntimeVersion {
+        /// The name of the plugin.
+        name: String,
+        /// The minimum version of the runtime required by the plugin.
+        required: String,
+        /// The version of the runtime.
+        current: String,
+    },
+}
+
+impl std::fmt::Display for CompatibilityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AbiVersion {
+                name,
+                version: Some(version),
+            } => write!(
+                f,
+                "plugin \"{}\" uses ABI version {}, but the runtime supports ABI version {}; rebuild it with the matching ayaka-bindings",
+                name, version, ABI_VERSION
+            ),
+            Self::AbiVersion {
+                name,
+                version: None,
+            } => write!(
+                f,
+                "plugin \"{}\" doesn't export an ABI version, but the runtime supports ABI version {}; rebuild it with the matching ayaka-bindings",
+                name, ABI_VERSION
+            ),
+            Self::RuntimeVersion {
+                name,
+                required,
+                current,
+            } => write!(
+                f,
+                "plugin \"{}\" requires runtime version {} or later, but the runtime version is {}",
+                name, required, current
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompatibilityError {}
+
 /// The plugin runtime.
 pub struct Runtime<M: RawModule + Send + Sync + 'static> {
     modules: HashMap<String, Module<M>>,
     action_modules: Vec<String>,
     text_modules: HashMap<String, String>,
     line_modules: HashMap<String, String>,
+    line_props: HashMap<String, Vec<String>>,
     game_modules: Vec<String>,
+    paragraph_modules: Vec<String>,
+    switch_modules: Vec<String>,
+    record_modules: Vec<String>,
+    rand: Arc<Mutex<rand_interop::RandEngine>>,
 }
 
 /// The load status of [`Runtime`].
@@ -88,34 +216,55 @@ pub enum LoadStatus {
 impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
     /// Load plugins from specific directory and plugin names.
     ///
-    /// The actual load folder will be `rel_to.join(dir)`.
+    /// The actual load folder will be `root_path.join(config.dir)`.
+    ///
+    /// If `config.modules` is empty, all WASM files will be loaded.
     ///
-    /// If `names` is empty, all WASM files will be loaded.
+    /// The modules registered in the linker are also loaded,
+    /// and they take precedence over the WASM files with the same names.
     #[stream(LoadStatus, lifetime = 'a)]
     pub async fn load<'a>(
-        dir: impl AsRef<str> + 'a,
+        config: &'a PluginConfig,
         root_path: &'a VfsPath,
-        names: &'a [impl AsRef<str>],
         mut store: M::Linker,
     ) -> Result<Arc<Self>> {
-        let path = root_path.join(dir)?;
-        let paths = Self::find_plugins(&path, names)?;
+        let path = root_path.join(&config.dir)?;
+        let paths = Self::find_plugins(&path, &config.modules, &store.registered())?;
 
         yield LoadStatus::CreateEngine;
         let handle = Arc::new(RwLock::new(Weak::new()));
         log_interop::register(&mut store)?;
-        plugin_interop::register(&mut store, handle.clone())?;
-        fs_interop::register(&mut store, root_path)?;
-        rand_interop::register(&mut store)?;
+        let rand = Arc::new(Mutex::new(rand_interop::RandEngine::new(RngState::new(
+            rand::random(),
+        ))));
+        rand_interop::register(&mut store, rand.clone())?;
         script_interop::register(&mut store)?;
-        let mut runtime = Self::new();
+        let mut runtime = Self::new(rand.clone());
 
         let total_len = paths.len();
         for (i, (name, p)) in paths.into_iter().enumerate() {
             yield LoadStatus::LoadPlugin(name.clone(), i, total_len);
-            let mut buf = vec![];
-            p.open_file()?.read_to_end(&mut buf)?;
-            let module = Module::new(store.create(&buf)?);
+            let limits = config.limits_of(&name);
+            let module_limits = limits.module_limits();
+            plugin_interop::register(&mut store, handle.clone(), module_limits)?;
+            fs_interop::register(&mut store, root_path, &limits)?;
+            store.set_limits(module_limits);
+            if config.wasi {
+                store.import_wasi(
+                    wasi::WasiConfig::new(name.clone(), root_path.clone())
+                        .with_files_limit(limits.files),
+                )?;
+            }
+            let module = match p {
+                Some(p) => {
+                    let mut buf = vec![];
+                    p.open_file()?.read_to_end(&mut buf)?;
+                    store.create_with(&buf, |ns| config.is_granted(&name, ns))
+                }
+                None => store.create_registered(&name),
+            }
+            .with_context(|| format!("Loading plugin \"{}\"", name))?;
+            let module = Module::new(name.clone(), module, module_limits);
             runtime.insert_module(name, module)?;
         }
         let runtime = Arc::new(runtime);
@@ -123,42 +272,64 @@ impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
         Ok(runtime)
     }
 
-    fn new() -> Self {
+    fn new(rand: Arc<Mutex<rand_interop::RandEngine>>) -> Self {
         Self {
             modules: HashMap::default(),
             action_modules: vec![],
             text_modules: HashMap::default(),
             line_modules: HashMap::default(),
+            line_props: HashMap::default(),
             game_modules: vec![],
+            paragraph_modules: vec![],
+            switch_modules: vec![],
+            record_modules: vec![],
+            rand,
         }
     }
 
-    fn find_plugins(path: &VfsPath, names: &[impl AsRef<str>]) -> Result<Vec<(String, VfsPath)>> {
+    fn find_plugins(
+        path: &VfsPath,
+        names: &[impl AsRef<str>],
+        registered: &[String],
+    ) -> Result<Vec<(String, Option<VfsPath>)>> {
         let paths = if names.is_empty() {
-            path.read_dir()?
-                .filter_map(|p| {
-                    if p.is_file().unwrap_or_default()
-                        && p.extension().unwrap_or_default() == "wasm"
-                    {
-                        let name = p
-                            .filename()
-                            .strip_suffix(".wasm")
-                            .unwrap_or_default()
-                            .to_string();
-                        Some((name, p))
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>()
+            let mut paths = if path.exists()? {
+                path.read_dir()?
+                    .filter_map(|p| {
+                        if p.is_file().unwrap_or_default()
+                            && p.extension().unwrap_or_default() == "wasm"
+                        {
+                            let name = p
+                                .filename()
+                                .strip_suffix(".wasm")
+                                .unwrap_or_default()
+                                .to_string();
+                            if registered.contains(&name) {
+                                None
+                            } else {
+                                Some((name, Some(p)))
+                            }
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            } else {
+                vec![]
+            };
+            paths.extend(registered.iter().map(|name| (name.clone(), None)));
+            paths
         } else {
             names
                 .iter()
                 .filter_map(|name| {
                     let name = name.as_ref();
+                    if registered.iter().any(|n| n == name) {
+                        return Some((name.to_string(), None));
+                    }
                     let p = path.join(format!("{}.wasm", name)).ok()?;
                     if p.exists().unwrap_or_default() {
-                        Some((name.to_string(), p))
+                        Some((name.to_string(), Some(p)))
                     } else {
                         None
                     }
@@ -168,9 +339,29 @@ impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
         Ok(paths)
     }
 
-    fn insert_module(&mut self, name: String, module: Module<M>) -> Result<()> {
+    /// Checks the ABI version and the metadata of the module before inserting it.
+    fn insert_module(&mut self, name: String, mut module: Module<M>) -> Result<()> {
+        let version = module.abi_version().ok();
+        if version != Some(ABI_VERSION) {
+            return Err(CompatibilityError::AbiVersion { name, version }.into());
+        }
         let plugin_type =
             unwrap_or_default_log!(module.plugin_type(), "Cannot determine module type");
+        if let Some(required) = &plugin_type.metadata.min_runtime {
+            let current = env!("CARGO_PKG_VERSION");
+            let required_version = semver::Version::parse(required).with_context(|| {
+                format!("Invalid minimum runtime version of plugin \"{}\"", name)
+            })?;
+            if required_version > semver::Version::parse(current)? {
+                return Err(CompatibilityError::RuntimeVersion {
+                    name,
+                    required: required.clone(),
+                    current: current.to_string(),
+                }
+                .into());
+            }
+        }
+        module.metadata = plugin_type.metadata;
         if plugin_type.action {
             self.action_modules.push(name.clone());
         }
@@ -186,6 +377,9 @@ impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
             }
         }
         for cmd in plugin_type.line {
+            let props = plugin_type.line_props.get(&cmd).cloned();
+            self.line_props
+                .insert(cmd.clone(), props.unwrap_or_default());
             let res = self.line_modules.insert(cmd.clone(), name.clone());
             if let Some(old_module) = res {
                 log::warn!(
@@ -199,6 +393,15 @@ impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
         if plugin_type.game {
             self.game_modules.push(name.clone());
         }
+        if plugin_type.paragraph {
+            self.paragraph_modules.push(name.clone());
+        }
+        if plugin_type.switch {
+            self.switch_modules.push(name.clone());
+        }
+        if plugin_type.record {
+            self.record_modules.push(name.clone());
+        }
         self.modules.insert(name, module);
         Ok(())
     }
@@ -225,8 +428,71 @@ impl<M: RawModule + Send + Sync + 'static> Runtime<M> {
         self.line_modules.get(cmd).and_then(|key| self.module(key))
     }
 
+    /// Gets the props accepted by the line command.
+    pub fn line_props(&self, cmd: &str) -> &[String] {
+        self.line_props
+            .get(cmd)
+            .map(|props| props.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Checks if the line command accepts the prop.
+    ///
+    /// The commands declaring no props accept all of them.
+    pub fn accepts_line_prop(&self, cmd: &str, key: &str) -> bool {
+        let props = self.line_props(cmd);
+        props.is_empty() || props.iter().any(|prop| prop == key)
+    }
+
     /// Iterates game modules.
     pub fn game_modules(&self) -> impl Iterator<Item = &Module<M>> {
         self.game_modules.iter().filter_map(|key| self.module(key))
     }
+
+    /// Iterates paragraph modules.
+    pub fn paragraph_modules(&self) -> impl Iterator<Item = &Module<M>> {
+        self.paragraph_modules
+            .iter()
+            .filter_map(|key| self.module(key))
+    }
+
+    /// Iterates switch modules.
+    pub fn switch_modules(&self) -> impl Iterator<Item = &Module<M>> {
+        self.switch_modules
+            .iter()
+            .filter_map(|key| self.module(key))
+    }
+
+    /// Iterates record modules.
+    pub fn record_modules(&self) -> impl Iterator<Item = &Module<M>> {
+        self.record_modules
+            .iter()
+            .filter_map(|key| self.module(key))
+    }
+
+    /// Gets the state of the random number generator used by the plugins.
+    pub fn rng_state(&self) -> RngState {
+        self.rand.lock().unwrap().state()
+    }
+
+    /// Sets the state of the random number generator used by the plugins.
+    pub fn set_rng_state(&self, state: RngState) {
+        let mut rand = self.rand.lock().unwrap();
+        if rand.state() != state {
+            *rand = rand_interop::RandEngine::new(state);
+        }
+    }
+
+    /// Runs `f` with the random number generator in `state`,
+    /// and restores the previous state after it.
+    ///
+    /// The state after `f` is returned with its result.
+    pub fn with_rng_state<T>(&self, state: RngState, f: impl FnOnce() -> T) -> (T, RngState) {
+        let old = self.rng_state();
+        self.set_rng_state(state);
+        let res = f();
+        let state = self.rng_state();
+        self.set_rng_state(old);
+        (res, state)
+    }
 }
@@ -1,6 +1,7 @@
+use crate::PluginLimits;
 use anyhow::Result;
-use ayaka_bindings_types::{FileMetadata, FileSeekFrom};
-use ayaka_plugin::{Linker, LinkerHandle, RawModule};
+use ayaka_bindings_types::{FileError, FileMetadata, FileSeekFrom};
+use ayaka_plugin::{LimitError, Linker, LinkerHandle, RawModule};
 use slab::Slab;
 use std::{
     collections::HashMap,
@@ -9,14 +10,24 @@ use std::{
 };
 use vfs::*;
 
-#[derive(Default)]
 struct FDMap {
     map: Slab<Box<dyn SeekAndRead + Send>>,
+    limit: Option<usize>,
 }
 
 impl FDMap {
-    pub fn open(&mut self, file: Box<dyn SeekAndRead + Send>) -> u64 {
-        self.map.insert(file) as u64
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            map: Slab::new(),
+            limit,
+        }
+    }
+
+    pub fn open(&mut self, file: Box<dyn SeekAndRead + Send>) -> Result<u64, LimitError> {
+        match self.limit {
+            Some(limit) if self.map.len() >= limit => Err(LimitError::FilesExceeded { limit }),
+            _ => Ok(self.map.insert(file) as u64),
+        }
     }
 
     pub fn close(&mut self, fd: u64) {
@@ -38,27 +49,73 @@ impl FDMap {
     }
 }
 
-pub fn register<M: RawModule>(store: &mut impl Linker<M>, root_path: &VfsPath) -> Result<()> {
+/// Normalizes a path from a plugin, relative to the root directory.
+///
+/// The path should be absolute.
+/// The `.` components are removed, and the `..` components are resolved.
+/// The path is rejected if it escapes the root directory.
+fn normalize_path(path: &str) -> Result<String, FileError> {
+    let path = path.strip_prefix('/').ok_or(FileError::InvalidPath)?;
+    let mut components = vec![];
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop().ok_or(FileError::InvalidPath)?;
+            }
+            _ if component.contains(['\\', '\0']) => return Err(FileError::InvalidPath),
+            _ => components.push(component),
+        }
+    }
+    Ok(components.join("/"))
+}
+
+fn join_path(root_path: &VfsPath, path: &str) -> Result<VfsPath, FileError> {
+    Ok(root_path.join(normalize_path(path)?)?)
+}
+
+fn read_dir(root_path: &VfsPath, path: &str) -> Result<Vec<String>, FileError> {
+    let path = join_path(root_path, path)?;
+    if path.metadata()?.file_type != VfsFileType::Directory {
+        return Err(FileError::NotADir);
+    }
+    Ok(path.read_dir()?.map(|p| p.as_str().to_string()).collect())
+}
+
+fn open_file(root_path: &VfsPath, path: &str) -> Result<Box<dyn SeekAndRead + Send>, FileError> {
+    let path = join_path(root_path, path)?;
+    if path.metadata()?.file_type != VfsFileType::File {
+        return Err(FileError::NotAFile);
+    }
+    Ok(path.open_file()?)
+}
+
+/// Registers the `fs` namespace for one plugin,
+/// as the open files are limited separately.
+pub fn register<M: RawModule>(
+    store: &mut impl Linker<M>,
+    root_path: &VfsPath,
+    limits: &PluginLimits,
+) -> Result<()> {
     let p = root_path.clone();
-    let read_dir_func = store.wrap(move |(path,): (String,)| {
-        Ok(p.join(&path[1..])?
-            .read_dir()
-            .map(|iter| iter.map(|p| p.as_str().to_string()).collect::<Vec<_>>())
-            .ok())
-    });
+    let read_dir_func = store.wrap(move |(path,): (String,)| Ok(read_dir(&p, &path)));
     let p = root_path.clone();
     let metadata_func = store.wrap(move |(path,): (String,)| {
-        Ok(p.join(&path[1..])?.metadata().map(FileMetadata::from).ok())
+        Ok(join_path(&p, &path).and_then(|path| Ok(FileMetadata::from(path.metadata()?))))
     });
     let p = root_path.clone();
-    let exists_func = store.wrap(move |(path,): (String,)| Ok(p.join(&path[1..])?.exists()?));
+    let exists_func = store.wrap(move |(path,): (String,)| {
+        Ok(join_path(&p, &path).and_then(|path| Ok(path.exists()?)))
+    });
 
-    let fd_map = Arc::new(Mutex::new(FDMap::default()));
+    let fd_map = Arc::new(Mutex::new(FDMap::new(limits.files)));
     let p = root_path.clone();
     let map = fd_map.clone();
     let open_file_func = store.wrap(move |(path,): (String,)| {
-        let file = p.join(&path[1..])?.open_file();
-        Ok(file.map(|file| map.lock().unwrap().open(file)).ok())
+        Ok(match open_file(&p, &path) {
+            Ok(file) => Ok(map.lock().unwrap().open(file)?),
+            Err(e) => Err(e),
+        })
     });
     let map = fd_map.clone();
     let close_file_func = store.wrap(move |(fd,): (u64,)| {
@@ -66,14 +123,20 @@ pub fn register<M: RawModule>(store: &mut impl Linker<M>, root_path: &VfsPath) -
         Ok(())
     });
     let map = fd_map.clone();
+    let module_limits = limits.module_limits();
     let file_read_func = store.wrap_with(move |mut handle, (fd, ptr, len): (u64, i32, i32)| {
+        module_limits.check_payload(len as usize)?;
         Ok(handle
             .slice_mut(ptr, len, |buf| map.lock().unwrap().read(fd, buf))
-            .ok())
+            .map_err(FileError::from))
     });
     let map = fd_map;
     let file_seek_func = store.wrap(move |(fd, pos): (u64, FileSeekFrom)| {
-        Ok(map.lock().unwrap().seek(fd, pos.into()).ok())
+        Ok(map
+            .lock()
+            .unwrap()
+            .seek(fd, pos.into())
+            .map_err(FileError::from))
     });
     store.import(
         "fs",
@@ -89,3 +152,52 @@ pub fn register<M: RawModule>(store: &mut impl Linker<M>, root_path: &VfsPath) -
     )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn files_limit() {
+        let mut map = FDMap::new(Some(2));
+        let fd = map.open(Box::new(Cursor::new(vec![]))).unwrap();
+        map.open(Box::new(Cursor::new(vec![]))).unwrap();
+        assert_eq!(
+            map.open(Box::new(Cursor::new(vec![]))),
+            Err(LimitError::FilesExceeded { limit: 2 })
+        );
+        map.close(fd);
+        map.open(Box::new(Cursor::new(vec![]))).unwrap();
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_path("/").unwrap(), "");
+        assert_eq!(normalize_path("/a/./b//c/").unwrap(), "a/b/c");
+        assert_eq!(normalize_path("/a/../b/c/..").unwrap(), "b");
+        assert_eq!(normalize_path("a/b"), Err(FileError::InvalidPath));
+        assert_eq!(normalize_path("/.."), Err(FileError::InvalidPath));
+        assert_eq!(normalize_path("/a/../../b"), Err(FileError::InvalidPath));
+        assert_eq!(normalize_path("/a\\..\\..\\b"), Err(FileError::InvalidPath));
+    }
+
+    #[test]
+    fn errors() {
+        let root: VfsPath = MemoryFS::new().into();
+        root.join("dir").unwrap().create_dir().unwrap();
+        write!(root.join("dir/a.txt").unwrap().create_file().unwrap(), "a").unwrap();
+        let root = root.join("dir").unwrap();
+
+        assert_eq!(read_dir(&root, "/").unwrap(), ["/dir/a.txt"]);
+        assert_eq!(read_dir(&root, "/a.txt").err(), Some(FileError::NotADir));
+        assert_eq!(read_dir(&root, "/b").err(), Some(FileError::NotFound));
+        assert!(open_file(&root, "/./a.txt").is_ok());
+        assert_eq!(open_file(&root, "/").err(), Some(FileError::NotAFile));
+        assert_eq!(open_file(&root, "/b.txt").err(), Some(FileError::NotFound));
+        assert_eq!(
+            open_file(&root, "/../dir/a.txt").err(),
+            Some(FileError::InvalidPath)
+        );
+    }
+}
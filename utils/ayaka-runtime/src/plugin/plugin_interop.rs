@@ -1,14 +1,17 @@
 use super::Runtime;
 use anyhow::{bail, Result};
-use ayaka_plugin::{Linker, LinkerHandle, RawModule};
+use ayaka_plugin::{Linker, LinkerHandle, ModuleLimits, RawModule};
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock, Weak},
 };
 
+/// Registers the `plugin` namespace for one plugin,
+/// as the payloads are limited by both the caller and the callee.
 pub fn register<M: RawModule + Send + Sync + 'static>(
     store: &mut impl Linker<M>,
     handle: Arc<RwLock<Weak<Runtime<M>>>>,
+    limits: ModuleLimits,
 ) -> Result<()> {
     let h = handle.clone();
     let modules_func = store.wrap(move |_: ()| {
@@ -22,12 +25,14 @@ pub fn register<M: RawModule + Send + Sync + 'static>(
     let call_func = store.wrap_with(
         move |mut handle, (module, name, args): (String, String, Vec<u8>)| {
             if let Some(this) = h.read().unwrap().upgrade() {
-                Ok(handle.call(
-                    this.modules[&module].module.inner(),
-                    &name,
-                    &args,
-                    |slice| Ok(slice.to_vec()),
-                )?)
+                let callee = &this.modules[&module].module;
+                limits.check_payload(args.len())?;
+                callee.limits().check_payload(args.len())?;
+                Ok(handle.call(callee.inner(), &name, &args, |slice| {
+                    callee.limits().check_payload(slice.len())?;
+                    limits.check_payload(slice.len())?;
+                    Ok(slice.to_vec())
+                })?)
             } else {
                 bail!("Runtime hasn't been initialized.")
             }
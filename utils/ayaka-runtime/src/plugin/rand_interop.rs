@@ -1,17 +1,44 @@
 use anyhow::{anyhow, Result};
+use ayaka_bindings_types::RngState;
 use ayaka_plugin::{Linker, RawModule};
-use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
 use std::{
     collections::HashMap,
-    sync::{LazyLock, Mutex},
+    sync::{Arc, Mutex},
 };
 
-static RNG: LazyLock<Mutex<StdRng>> = LazyLock::new(|| Mutex::new(StdRng::from_entropy()));
+pub struct RandEngine {
+    seed: u64,
+    rng: ChaCha12Rng,
+}
+
+impl RandEngine {
+    pub fn new(state: RngState) -> Self {
+        let mut rng = ChaCha12Rng::seed_from_u64(state.seed);
+        rng.set_word_pos(state.pos.into());
+        Self {
+            seed: state.seed,
+            rng,
+        }
+    }
+
+    pub fn state(&self) -> RngState {
+        RngState {
+            seed: self.seed,
+            pos: self.rng.get_word_pos() as u64,
+        }
+    }
+}
 
-pub fn register<M: RawModule>(store: &mut impl Linker<M>) -> Result<()> {
-    let rnd_func = store.wrap(|(start, end): (i64, i64)| {
-        RNG.lock()
-            .map(|mut rng| rng.gen_range(start..end))
+pub fn register<M: RawModule>(
+    store: &mut impl Linker<M>,
+    engine: Arc<Mutex<RandEngine>>,
+) -> Result<()> {
+    let rnd_func = store.wrap(move |(start, end): (i64, i64)| {
+        engine
+            .lock()
+            .map(|mut engine| engine.rng.gen_range(start..end))
             .map_err(|_| anyhow!("Cannot lock random engine"))
     });
     store.import("rand", HashMap::from([("__rnd".to_string(), rnd_func)]))?;
@@ -1,12 +1,106 @@
 use crate::{plugin::*, *};
 use ayaka_plugin::RawModule;
+use ayaka_plugin_native::{NativeLinker, NativeModule, NativePlugin};
+use ayaka_plugin_wasmi::{WasmiLinker, WasmiModule};
+use futures_util::future::LocalBoxFuture;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     path::Path,
-    sync::atomic::{AtomicUsize, Ordering},
 };
 
-static CUR_ACT: AtomicUsize = AtomicUsize::new(0);
+/// The backends running the example plugins in the tests.
+trait TestModule: RawModule + Send + Sync + 'static {
+    /// Creates the linker running the plugins.
+    fn linker<'a>(
+        config: LinkerConfig,
+        plugins: &'a PluginConfig,
+        root_path: &'a VfsPath,
+    ) -> LocalBoxFuture<'a, Result<Self::Linker>>;
+}
+
+macro_rules! impl_wasm_test_module {
+    ($($t:ty),*) => {
+        $(
+            impl TestModule for $t {
+                fn linker<'a>(
+                    config: LinkerConfig,
+                    _plugins: &'a PluginConfig,
+                    _root_path: &'a VfsPath,
+                ) -> LocalBoxFuture<'a, Result<Self::Linker>> {
+                    Box::pin(async move { <Self as RawModule>::Linker::new(config) })
+                }
+            }
+        )*
+    };
+}
+
+impl_wasm_test_module!(
+    WasmiModule,
+    ayaka_plugin_wasmtime::WasmtimeModule,
+    ayaka_plugin_wasmer::WasmerModule
+);
+
+// The example plugins are WASM binaries,
+// so they are loaded by Wasmi, and called by the native proxies.
+impl TestModule for NativeModule {
+    fn linker<'a>(
+        config: LinkerConfig,
+        plugins: &'a PluginConfig,
+        root_path: &'a VfsPath,
+    ) -> LocalBoxFuture<'a, Result<Self::Linker>> {
+        Box::pin(async move {
+            let runtime = Runtime::load(plugins, root_path, WasmiLinker::new(config)?).await?;
+            let mut linker = NativeLinker::new(LinkerConfig::default())?;
+            for name in &plugins.modules {
+                linker.register(name, native_proxy(&runtime, name)?);
+            }
+            Ok(linker)
+        })
+    }
+}
+
+/// Proxies the commands of a WASM plugin with typed args.
+fn native_proxy(runtime: &Arc<Runtime<WasmiModule>>, name: &str) -> Result<NativePlugin> {
+    let plugin_type = runtime.module(name).unwrap().plugin_type()?;
+    let mut plugin = NativePlugin::new().export("plugin_type", {
+        let plugin_type = plugin_type.clone();
+        move |()| Ok(plugin_type.clone())
+    });
+    for cmd in plugin_type.line {
+        let (runtime, name) = (runtime.clone(), name.to_string());
+        plugin = plugin.export(cmd.clone(), move |(ctx,): (LineProcessContext,)| {
+            runtime.module(&name).unwrap().dispatch_line(
+                &cmd,
+                LineProcessContextRef {
+                    game_props: &ctx.game_props,
+                    frontend: ctx.frontend,
+                    locale: &ctx.locale,
+                    ctx: &ctx.ctx,
+                    props: &ctx.props,
+                },
+            )
+        });
+    }
+    for cmd in plugin_type.text {
+        let (runtime, name) = (runtime.clone(), name.to_string());
+        plugin = plugin.export(
+            cmd.clone(),
+            move |(args, ctx): (Vec<String>, TextProcessContext)| {
+                runtime.module(&name).unwrap().dispatch_text(
+                    &cmd,
+                    &args,
+                    TextProcessContextRef {
+                        game_props: &ctx.game_props,
+                        frontend: ctx.frontend,
+                        locale: &ctx.locale,
+                        ctx: &ctx.ctx,
+                    },
+                )
+            },
+        );
+    }
+    Ok(plugin)
+}
 
 struct ModuleWrapper<'a, M: RawModule> {
     module: &'a Module<M>,
@@ -14,49 +108,207 @@ struct ModuleWrapper<'a, M: RawModule> {
 
 impl<'a, M: RawModule> ModuleWrapper<'a, M> {
     pub fn call(&self, script: &str) -> VarMap {
-        let game_props = HashMap::default();
-        let frontend = FrontendType::Text;
-        let mut ctx = RawContext::default();
-        // Update cur_act to avoid ayacript cache.
-        let cur_act = CUR_ACT.fetch_add(1, Ordering::SeqCst);
-        ctx.cur_act = cur_act;
-        let props = VarMap::from([("exec".to_string(), RawValue::Str(script.to_string()))]);
-        let ctx = LineProcessContextRef {
-            game_props: &game_props,
-            frontend,
-            ctx: &ctx,
-            props: &props,
-        };
-        let res = self.module.dispatch_line("exec", ctx).unwrap();
-        res.locals
+        self.try_call(script).unwrap()
+    }
+
+    pub fn try_call(&self, script: &str) -> Result<VarMap> {
+        // All scripts are executed in the same line.
+        let res = dispatch_line(self.module, "exec", script)?;
+        Ok(res.locals)
     }
 }
 
-async fn with_ctx<M: RawModule + Send + Sync + 'static>(f: impl FnOnce(&ModuleWrapper<M>))
-where
-    M::Linker: Linker<M, Config = ()>,
-{
+/// Dispatches a line command in an empty context, with the command as the only prop.
+fn dispatch_line<M: RawModule>(
+    module: &Module<M>,
+    cmd: &str,
+    value: &str,
+) -> Result<LineProcessResult> {
+    let game_props = HashMap::default();
+    let ctx = RawContext::default();
+    let props = VarMap::from([(cmd.to_string(), RawValue::Str(value.to_string()))]);
+    let ctx = LineProcessContextRef {
+        game_props: &game_props,
+        frontend: FrontendType::Text,
+        locale: &LanguageIdentifier::default(),
+        ctx: &ctx,
+        props: &props,
+    };
+    module.dispatch_line(cmd, ctx)
+}
+
+async fn with_ctx<M: TestModule>(f: impl FnOnce(&ModuleWrapper<M>)) {
+    with_config::<M>(LinkerConfig::default(), f).await
+}
+
+fn plugin_config() -> PluginConfig {
+    PluginConfig {
+        dir: "plugins".to_string(),
+        modules: vec!["ayacript".to_string(), "random".to_string()],
+        ..Default::default()
+    }
+}
+
+async fn load<M: TestModule>(
+    config: LinkerConfig,
+    plugins: PluginConfig,
+) -> Result<Arc<Runtime<M>>> {
     let root_path =
         vfs::PhysicalFS::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples")).into();
-    let linker = M::Linker::new(()).unwrap();
-    let runtime = Runtime::load("plugins", &root_path, &["ayacript", "random"], linker)
-        .await
-        .unwrap();
+    let linker = M::linker(config, &plugins, &root_path).await?;
+    Runtime::load(&plugins, &root_path, linker).await
+}
+
+async fn with_config<M: TestModule>(config: LinkerConfig, f: impl FnOnce(&ModuleWrapper<M>)) {
+    with_plugins::<M>(config, plugin_config(), f).await
+}
+
+async fn with_plugins<M: TestModule>(
+    config: LinkerConfig,
+    plugins: PluginConfig,
+    f: impl FnOnce(&ModuleWrapper<M>),
+) {
+    let runtime = load::<M>(config, plugins).await.unwrap();
     let module = runtime.line_module("exec").unwrap();
     let wrapper = ModuleWrapper { module };
     f(&wrapper);
 }
 
+#[tokio::test]
+async fn native() {
+    let mut linker = NativeLinker::new(LinkerConfig::default()).unwrap();
+    linker.register(
+        "echo",
+        NativePlugin::new()
+            .export("plugin_type", |()| {
+                Ok(PluginType::builder().line(["echo"]).build())
+            })
+            .export("echo", |(ctx,): (LineProcessContext,)| {
+                Ok(LineProcessResult {
+                    locals: VarMap::from([("echo".to_string(), ctx.props["echo"].clone())]),
+                    ..Default::default()
+                })
+            }),
+    );
+    // The plugin directory doesn't exist.
+    let config = PluginConfig {
+        dir: "native".to_string(),
+        ..Default::default()
+    };
+    let root_path = vfs::MemoryFS::new().into();
+    let runtime = Runtime::<NativeModule>::load(&config, &root_path, linker)
+        .await
+        .unwrap();
+    let module = runtime.line_module("echo").unwrap();
+    assert_eq!(module.name(), "echo");
+
+    let res = dispatch_line(module, "echo", "hello").unwrap();
+    assert_eq!(res.locals["echo"], RawValue::Str("hello".to_string()));
+    assert!(module
+        .process_game(GameProcessContextRef {
+            title: "",
+            author: "",
+            props: &HashMap::default(),
+        })
+        .is_err());
+}
+
+#[test]
+fn native_values() {
+    let mut linker = NativeLinker::new(LinkerConfig::default()).unwrap();
+    linker.register(
+        "native",
+        NativePlugin::new().export("inc", |(i,): (i64,)| Ok(i + 1)),
+    );
+    let module = linker.create_registered("native").unwrap();
+    let limits = ModuleLimits::default();
+    let res: Result<i64, PluginError> = module
+        .call_value("inc", (1i64,), |args| args, &limits)
+        .unwrap();
+    assert_eq!(res, Ok(2));
+    // The args are downcasted to the exported type.
+    let res: Result<i64, PluginError> = module
+        .call_value("inc", ("1".to_string(),), |args| args, &limits)
+        .unwrap();
+    assert_eq!(res.unwrap_err().to_string(), "mismatched input args");
+    // So are the returns.
+    let err = module
+        .call_value::<_, _, Result<String, PluginError>>("inc", (1i64,), |args| args, &limits)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Method \"inc\" returns a mismatched type");
+
+    // The native modules cannot be called with bytes.
+    let err = module.call("inc", &[], |_| Ok(())).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Method \"inc\" of a native module cannot be called with bytes"
+    );
+    let err = module.call("dec", &[], |_| Ok(())).unwrap_err();
+    assert_eq!(err.to_string(), "Method \"dec\" is not exported");
+}
+
+#[tokio::test]
+async fn compatibility() {
+    async fn load_native(plugin: NativePlugin) -> Result<Arc<Runtime<NativeModule>>> {
+        let mut linker = NativeLinker::new(LinkerConfig::default())?;
+        linker.register("native", plugin);
+        let config = PluginConfig {
+            dir: "native".to_string(),
+            ..Default::default()
+        };
+        let root_path = vfs::MemoryFS::new().into();
+        Runtime::load(&config, &root_path, linker).await
+    }
+
+    let metadata = PluginMetadata {
+        name: "native".to_string(),
+        version: "1.0.0".to_string(),
+        author: "Ayaka".to_string(),
+        min_runtime: Some("0.1.0".to_string()),
+    };
+    let runtime = load_native(NativePlugin::new().export("plugin_type", {
+        let metadata = metadata.clone();
+        move |()| Ok(PluginType::builder().metadata(metadata.clone()).build())
+    }))
+    .await
+    .unwrap();
+    assert_eq!(runtime.module("native").unwrap().metadata(), &metadata);
+
+    let err = load_native(NativePlugin::new().export_any(ABI_VERSION_NAME, |_| Ok(Box::new(0u32))))
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.downcast_ref::<CompatibilityError>(),
+        Some(&CompatibilityError::AbiVersion {
+            name: "native".to_string(),
+            version: Some(0)
+        })
+    );
+
+    let err = load_native(NativePlugin::new().export("plugin_type", |()| {
+        Ok(PluginType::builder()
+            .metadata(PluginMetadata {
+                min_runtime: Some("999.0.0".to_string()),
+                ..Default::default()
+            })
+            .build())
+    }))
+    .await
+    .err()
+    .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<CompatibilityError>(),
+        Some(CompatibilityError::RuntimeVersion { required, .. }) if required == "999.0.0"
+    ));
+}
+
 #[generic_tests::define(attrs(tokio::test))]
 mod runtime_tests {
     use super::*;
-    use ayaka_plugin::RawModule;
 
     #[tokio::test]
-    async fn vars<M: RawModule + Send + Sync + 'static>()
-    where
-        M::Linker: Linker<M, Config = ()>,
-    {
+    async fn vars<M: TestModule>() {
         with_ctx::<M>(|ctx| {
             assert_eq!(
                 ctx.call(
@@ -85,10 +337,7 @@ mod runtime_tests {
     }
 
     #[tokio::test]
-    async fn if_test<M: RawModule + Send + Sync + 'static>()
-    where
-        M::Linker: Linker<M, Config = ()>,
-    {
+    async fn if_test<M: TestModule>() {
         with_ctx::<M>(|ctx| {
             assert_eq!(
                 ctx.call(
@@ -113,10 +362,75 @@ mod runtime_tests {
     }
 
     #[tokio::test]
-    async fn random<M: RawModule + Send + Sync + 'static>()
-    where
-        M::Linker: Linker<M, Config = ()>,
-    {
+    async fn collections<M: TestModule>() {
+        with_ctx::<M>(|ctx| {
+            let locals = ctx.call(
+                r##"
+                    $items = ["sword", "shield"];
+                    $items += "potion";
+                    $items[0] = "axe";
+                    $aff = { alice: 1.5 };
+                    $aff["bob"] = 2;
+                    $aff["alice"] *= 2;
+                    $total = 0;
+                    for k in $aff { $total += $aff[k] };
+                    $upper = for i in 3 { i * 2 };
+                    $last = $items[-1];
+                    $has = contains($items, "shield");
+                    $empty_str = "a" * -1;
+                    $empty_list = [1] * -1;
+                    $num_eq = 1 == 1.0;
+                    $num_has = contains([1, 2], 2.0);
+                    $not = [!0, !1, !0.0, !1.5, ![], !"a"];
+                    $len = [len("名字"), len([1, 2]), len({ a: 1 }), len(5)];
+                    $range = len(for i in 100000 { i })
+                "##,
+            );
+            assert_eq!(
+                locals["items"],
+                RawValue::List(vec![
+                    RawValue::Str("axe".into()),
+                    RawValue::Str("shield".into()),
+                    RawValue::Str("potion".into()),
+                ])
+            );
+            assert_eq!(
+                locals["aff"],
+                RawValue::Map(BTreeMap::from([
+                    ("alice".to_string(), RawValue::Float(3.0)),
+                    ("bob".to_string(), RawValue::Num(2)),
+                ]))
+            );
+            assert_eq!(locals["total"], RawValue::Float(5.0));
+            assert_eq!(
+                locals["upper"],
+                RawValue::List(vec![RawValue::Num(0), RawValue::Num(2), RawValue::Num(4)])
+            );
+            assert_eq!(locals["last"], RawValue::Str("potion".into()));
+            assert_eq!(locals["has"], RawValue::Bool(true));
+            assert_eq!(locals["empty_str"], RawValue::Str(String::new()));
+            assert_eq!(locals["empty_list"], RawValue::List(vec![]));
+            assert_eq!(locals["num_eq"], RawValue::Bool(true));
+            assert_eq!(locals["num_has"], RawValue::Bool(true));
+            assert_eq!(
+                locals["not"],
+                RawValue::List(
+                    [true, false, true, false, true, false]
+                        .map(RawValue::Bool)
+                        .to_vec()
+                )
+            );
+            assert_eq!(
+                locals["len"],
+                RawValue::List([2, 2, 1, 0].map(RawValue::Num).to_vec())
+            );
+            assert_eq!(locals["range"], RawValue::Num(65536));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn random<M: TestModule>() {
         with_ctx::<M>(|ctx| {
             assert!((0..10).contains(
                 &ctx.call(
@@ -130,6 +444,336 @@ mod runtime_tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn plugin_error<M: TestModule>() {
+        let plugins = PluginConfig {
+            dir: "plugins".to_string(),
+            modules: vec!["basictex".to_string()],
+            ..Default::default()
+        };
+        let runtime = load::<M>(LinkerConfig::default(), plugins).await.unwrap();
+        let module = runtime.text_module("ruby").unwrap();
+        let game_props = HashMap::default();
+        let locale = LanguageIdentifier::default();
+        let raw_ctx = RawContext::default();
+        let ctx = || TextProcessContextRef {
+            game_props: &game_props,
+            frontend: FrontendType::Text,
+            locale: &locale,
+            ctx: &raw_ctx,
+        };
+        let res = module
+            .dispatch_text("ruby", &["a".to_string(), "b".to_string()], ctx())
+            .unwrap();
+        assert_eq!(res.text.to_string(), "a(b)");
+        let ja = locale!("ja");
+        let res = module
+            .dispatch_text(
+                "ruby",
+                &["a".to_string(), "b".to_string()],
+                TextProcessContextRef {
+                    locale: &ja.0,
+                    ..ctx()
+                },
+            )
+            .unwrap();
+        assert_eq!(res.text.to_string(), "a（b）");
+        let err = module
+            .dispatch_text("ruby", &["a".to_string()], ctx())
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<PluginError>(),
+            Some(&PluginError::new(
+                "`ruby` expects 2 argument(s), but 1 were given"
+            ))
+        );
+        // The plugin works after an error.
+        assert!(module
+            .dispatch_text("ruby", &["a".to_string(), "b".to_string()], ctx())
+            .is_ok());
+    }
+
+    #[instantiate_tests(<ayaka_plugin_wasmi::WasmiModule>)]
+    mod inst_wasmi {}
+    #[instantiate_tests(<ayaka_plugin_wasmtime::WasmtimeModule>)]
+    mod inst_wasmtime {}
+    #[instantiate_tests(<ayaka_plugin_wasmer::WasmerModule>)]
+    mod inst_wasmer {}
+    #[instantiate_tests(<ayaka_plugin_native::NativeModule>)]
+    mod inst_native {}
+}
+
+/// The limits and the host functions, only for the WASM backends.
+#[generic_tests::define(attrs(tokio::test))]
+mod wasm_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fuel<M: TestModule>() {
+        let config = LinkerConfig {
+            fuel: Some(10_000_000),
+        };
+        with_config::<M>(config, |ctx| {
+            // The fuel is refilled for each call.
+            for _ in 0..100 {
+                assert_eq!(
+                    ctx.call("$a = for i in 100 { i }; $b = $a[-1]")["b"],
+                    RawValue::Num(99)
+                );
+            }
+            let err = ctx
+                .try_call("$a = for i in 10000 { for j in 10000 { j }; 0 }")
+                .unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<LimitError>(),
+                Some(&LimitError::FuelExhausted {
+                    method: "exec".to_string()
+                })
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn memory<M: TestModule>() {
+        let mut plugins = plugin_config();
+        plugins.module_limits.insert(
+            "ayacript".to_string(),
+            PluginLimits {
+                memory: Some(1024),
+                ..Default::default()
+            },
+        );
+        let err = load::<M>(LinkerConfig::default(), plugins)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<LimitError>(),
+            Some(LimitError::MemoryExceeded { limit: 1024, .. })
+        ));
+        assert!(format!("{:?}", err).contains("\"ayacript\""));
+    }
+
+    #[tokio::test]
+    async fn memory_grow<M: TestModule>() {
+        const LIMIT: usize = 4 * 1024 * 1024;
+        let mut plugins = plugin_config();
+        plugins.module_limits.insert(
+            "ayacript".to_string(),
+            PluginLimits {
+                memory: Some(LIMIT),
+                ..Default::default()
+            },
+        );
+        with_plugins::<M>(LinkerConfig::default(), plugins, |ctx| {
+            assert_eq!(ctx.call("$a = 1 + 1")["a"], RawValue::Num(2));
+            // The plugin traps when the memory fails to grow.
+            let err = ctx.try_call("$a = \"a\" * 10000000").unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<LimitError>(),
+                Some(LimitError::MemoryExceeded { limit: LIMIT, .. })
+            ));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn fuel_memory<M: TestModule>() {
+        const LIMIT: usize = 4 * 1024 * 1024;
+        let config = LinkerConfig {
+            fuel: Some(10_000_000),
+        };
+        let mut plugins = plugin_config();
+        plugins.module_limits.insert(
+            "ayacript".to_string(),
+            PluginLimits {
+                memory: Some(LIMIT),
+                ..Default::default()
+            },
+        );
+        // The metered modules are limited by the same memory limits.
+        with_plugins::<M>(config, plugins, |ctx| {
+            assert_eq!(ctx.call("$a = 1 + 1")["a"], RawValue::Num(2));
+            let err = ctx.try_call("$a = \"a\" * 10000000").unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<LimitError>(),
+                Some(LimitError::MemoryExceeded { limit: LIMIT, .. })
+            ));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn payload<M: TestModule>() {
+        let mut plugins = plugin_config();
+        plugins.module_limits.insert(
+            "ayacript".to_string(),
+            PluginLimits {
+                payload: Some(1024),
+                ..Default::default()
+            },
+        );
+        with_plugins::<M>(LinkerConfig::default(), plugins, |ctx| {
+            assert_eq!(ctx.call("$a = 1 + 1")["a"], RawValue::Num(2));
+            let err = ctx
+                .try_call(&format!("$a = \"{}\"", "a".repeat(1024)))
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<LimitError>(),
+                Some(LimitError::PayloadExceeded { limit: 1024, .. })
+            ));
+            assert!(format!("{:?}", err).contains("\"ayacript\""));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn permissions<M: TestModule>() {
+        let mut plugins = plugin_config();
+        plugins.deny_by_default = true;
+        plugins.permissions.insert(
+            "ayacript".to_string(),
+            vec!["log".to_string(), "plugin".to_string()],
+        );
+        let err = load::<M>(LinkerConfig::default(), plugins)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.downcast_ref::<PermissionError>()
+                .map(|e| &e.namespace[..]),
+            Some("script")
+        );
+        assert!(format!("{:?}", err).contains("\"ayacript\""));
+
+        let mut plugins = plugin_config();
+        plugins.deny_by_default = true;
+        plugins.permissions.insert(
+            "ayacript".to_string(),
+            vec![
+                "log".to_string(),
+                "plugin".to_string(),
+                "script".to_string(),
+            ],
+        );
+        plugins
+            .permissions
+            .insert("random".to_string(), vec!["rand".to_string()]);
+        with_plugins::<M>(LinkerConfig::default(), plugins, |ctx| {
+            assert!((0..10).contains(&ctx.call("$a = random.rnd(10)")["a"].get_num()));
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn wasi<M: TestModule>()
+    where
+        M::Linker: Linker<M, Config = LinkerConfig>,
+    {
+        let binary = wat::parse_str(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "path_open"
+                    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_read"
+                    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_prestat_get"
+                    (func $fd_prestat_get (param i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0) "a.txt")
+                (data (i32.const 16) "../a.txt")
+                (data (i32.const 32) "hello\n")
+                (data (i32.const 48) "\64\00\00\00\40\00\00\00\20\00\00\00\06\00\00\00")
+                (func (export "__abi_alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "__abi_free") (param i32 i32))
+                ;; The last preopened fd is the root directory.
+                (func $preopen (result i32) (local $fd i32)
+                    (local.set $fd (i32.const 3))
+                    (block $done
+                        (loop $next
+                            (br_if $done (call $fd_prestat_get (local.get $fd) (i32.const 96)))
+                            (local.set $fd (i32.add (local.get $fd) (i32.const 1)))
+                            (br $next)))
+                    (i32.sub (local.get $fd) (i32.const 1)))
+                (func (export "run") (param i32 i32) (result i64)
+                    (drop (call $path_open (call $preopen) (i32.const 0) (i32.const 0) (i32.const 5)
+                        (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 64)))
+                    (drop (call $fd_read (i32.load (i32.const 64)) (i32.const 48) (i32.const 1) (i32.const 68)))
+                    (drop (call $fd_write (i32.const 1) (i32.const 56) (i32.const 1) (i32.const 72)))
+                    (i64.or
+                        (i64.shl (i64.extend_i32_u (i32.load (i32.const 68))) (i64.const 32))
+                        (i64.const 100)))
+                (func (export "escape") (param i32 i32) (result i64)
+                    (i32.store8 (i32.const 80)
+                        (call $path_open (call $preopen) (i32.const 0) (i32.const 16) (i32.const 8)
+                            (i32.const 0) (i64.const 2) (i64.const 0) (i32.const 0) (i32.const 64)))
+                    (i64.const 0x100000050))
+                (func (export "stdin") (param i32 i32) (result i64)
+                    (i32.store8 (i32.const 80)
+                        (call $fd_read (i32.const 0) (i32.const 48) (i32.const 1) (i32.const 68)))
+                    (i64.const 0x100000050))
+                (func (export "exit") (param i32 i32) (result i64)
+                    (call $proc_exit (i32.const 3))
+                    unreachable))
+            "#,
+        )
+        .unwrap();
+        let root_path: VfsPath = vfs::MemoryFS::new().into();
+        root_path
+            .join("a.txt")
+            .unwrap()
+            .create_file()
+            .unwrap()
+            .write_all(b"Hello from WASI")
+            .unwrap();
+
+        let mut linker = M::Linker::new(LinkerConfig::default()).unwrap();
+        linker
+            .import_wasi(wasi::WasiConfig::new("wasi", root_path))
+            .unwrap();
+        let module = linker.create(&binary).unwrap();
+        let data = module.call("run", &[], |data| Ok(data.to_vec())).unwrap();
+        assert_eq!(data, b"Hello from WASI");
+        // Escaping the root is rejected with ERRNO_NOTCAPABLE.
+        let data = module
+            .call("escape", &[], |data| Ok(data.to_vec()))
+            .unwrap();
+        assert_eq!(data, [76]);
+        // Reading the stdin is rejected with ERRNO_NOTSUP.
+        let data = module.call("stdin", &[], |data| Ok(data.to_vec())).unwrap();
+        assert_eq!(data, [58]);
+        let err = module.call("exit", &[], |_| Ok(())).unwrap_err();
+        assert_eq!(err.downcast_ref::<wasi::WasiExit>().map(|e| e.0), Some(3));
+    }
+
+    #[tokio::test]
+    async fn wasi_plugin<M: TestModule>() {
+        // The plugin targets wasm32-wasi, and uses `std::fs` and `println!`.
+        let plugins = PluginConfig {
+            dir: "wasi-plugins".to_string(),
+            modules: vec!["textfile".to_string()],
+            wasi: true,
+            ..Default::default()
+        };
+        let runtime = load::<M>(LinkerConfig::default(), plugins).await.unwrap();
+        let module = runtime.line_module("textfile").unwrap();
+        let read = |path: &str| dispatch_line(module, "textfile", path).unwrap().locals;
+        let text = std::fs::read_to_string(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples/Basic/config.yaml"),
+        )
+        .unwrap();
+        assert_eq!(
+            read("/Basic/config.yaml").get("textfile"),
+            Some(&RawValue::Str(text))
+        );
+        assert!(read("/Basic/missing.yaml").is_empty());
+        assert!(read("/../Cargo.toml").is_empty());
+    }
+
     #[instantiate_tests(<ayaka_plugin_wasmi::WasmiModule>)]
     mod inst_wasmi {}
     #[instantiate_tests(<ayaka_plugin_wasmtime::WasmtimeModule>)]
@@ -0,0 +1,315 @@
+//! The translation utilities.
+
+use crate::*;
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// A paragraph or a line which is not translated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MissingTranslation {
+    /// The base paragraph tag, i.e., the file name.
+    pub base_para: String,
+    /// The paragraph tag.
+    pub para: String,
+    /// The index of the line, if a line is missing.
+    pub line: Option<usize>,
+}
+
+/// The translation coverage of a locale.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TranslationCoverage {
+    /// The locale.
+    pub locale: Locale,
+    /// The count of the translatable lines, i.e., texts and switches, in the base language.
+    pub total_lines: usize,
+    /// The count of the translated lines.
+    pub translated_lines: usize,
+    /// The paragraphs missing in the locale.
+    pub missing_paras: Vec<MissingTranslation>,
+    /// The lines which are missing or empty in the locale,
+    /// and fallback to the base language.
+    pub empty_lines: Vec<MissingTranslation>,
+    /// The count of the resource keys in the base language.
+    pub total_res: usize,
+    /// The resource keys missing in the locale.
+    pub missing_res: Vec<String>,
+}
+
+/// A translatable unit in the base language.
+///
+/// The key is
+/// * `para/<file>/<tag>/<line>` for a text line,
+/// * `para/<file>/<tag>/<line>/<index>` for an item in a switch line,
+/// * `title/<file>/<tag>` for a paragraph title,
+/// * `res/<key>` for a resource.
+///
+/// The `<line>` is the line identifier if specified, otherwise the line index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TranslationUnit {
+    /// The key of the unit.
+    pub key: String,
+    /// The text in the base language.
+    pub source: String,
+    /// The text in the target locale, if translated.
+    pub target: Option<String>,
+}
+
+/// A line in a [`TranslatedParagraph`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum TranslatedLine {
+    /// A text line.
+    Text(String),
+    /// A text line with the line identifier.
+    IdText {
+        /// The line identifier.
+        id: String,
+        /// The text.
+        text: String,
+    },
+    /// A switch line.
+    Switch {
+        /// The line identifier, if specified.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        /// The switch items.
+        switches: Vec<String>,
+    },
+}
+
+/// A paragraph generated from the translations.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TranslatedParagraph {
+    /// The paragraph tag.
+    pub tag: String,
+    /// The translated title.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The translated lines.
+    /// The untranslated ones are [`None`], and fallback to the base language.
+    pub texts: Vec<Option<TranslatedLine>>,
+}
+
+/// The paragraphs and resources generated from the translations.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Translation {
+    /// The paragraphs, indexed by file names.
+    pub paras: BTreeMap<String, Vec<TranslatedParagraph>>,
+    /// The resources.
+    pub res: BTreeMap<String, String>,
+}
+
+fn line_key(file: &str, p: &Paragraph, index: usize) -> String {
+    let line = p
+        .line_id(index)
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| index.to_string());
+    format!("para/{}/{}/{}", file, p.tag, line)
+}
+
+fn sorted<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut items = map.iter().collect::<Vec<_>>();
+    items.sort_by_key(|(key, _)| *key);
+    items
+}
+
+impl Game {
+    fn base_res(&self) -> Option<&VarMap> {
+        self.res
+            .get(self.choose_from_keys(&self.config.base_lang, &self.res))
+    }
+
+    /// Gets the translation coverage of the locale.
+    ///
+    /// Only the paragraphs and resources of the exact locale are counted.
+    pub fn translation_coverage(&self, loc: &Locale) -> TranslationCoverage {
+        let mut coverage = TranslationCoverage {
+            locale: loc.clone(),
+            total_lines: 0,
+            translated_lines: 0,
+            missing_paras: vec![],
+            empty_lines: vec![],
+            total_res: 0,
+            missing_res: vec![],
+        };
+        for (file, paras) in sorted(self.base_paras().unwrap_or(&HashMap::new())) {
+            for p in paras {
+                let loc_para = self.find_para(loc, file, &p.tag);
+                if loc_para.is_none() {
+                    coverage.missing_paras.push(MissingTranslation {
+                        base_para: file.clone(),
+                        para: p.tag.clone(),
+                        line: None,
+                    });
+                }
+                for (i, line) in p.texts.iter().enumerate() {
+                    if !matches!(line.line, Line::Text(_) | Line::Switch { .. }) {
+                        continue;
+                    }
+                    coverage.total_lines += 1;
+                    match loc_para
+                        .and_then(|loc_para| loc_para.find_line(line.id.as_deref(), i))
+                        .map(|line| &line.line)
+                    {
+                        None | Some(Line::Empty) => {
+                            if loc_para.is_some() {
+                                coverage.empty_lines.push(MissingTranslation {
+                                    base_para: file.clone(),
+                                    para: p.tag.clone(),
+                                    line: Some(i),
+                                });
+                            }
+                        }
+                        Some(_) => coverage.translated_lines += 1,
+                    }
+                }
+            }
+        }
+        if let Some(base_res) = self.base_res() {
+            coverage.total_res = base_res.len();
+            let res = self.res.get(loc);
+            coverage.missing_res = sorted(base_res)
+                .into_iter()
+                .map(|(key, _)| key)
+                .filter(|key| !res.map(|res| res.contains_key(*key)).unwrap_or_default())
+                .cloned()
+                .collect();
+        }
+        coverage
+    }
+
+    /// Gets the translatable units of the base language,
+    /// with the existing translations of the locale.
+    pub fn translation_units(&self, loc: &Locale) -> Vec<TranslationUnit> {
+        let mut units = vec![];
+        for (file, paras) in sorted(self.base_paras().unwrap_or(&HashMap::new())) {
+            for p in paras {
+                let loc_para = self.find_para(loc, file, &p.tag);
+                if let Some(title) = &p.title {
+                    units.push(TranslationUnit {
+                        key: format!("title/{}/{}", file, p.tag),
+                        source: title.clone(),
+                        target: loc_para.and_then(|p| p.title.clone()),
+                    });
+                }
+                for (i, line) in p.texts.iter().enumerate() {
+                    let loc_line = loc_para
+                        .and_then(|loc_para| loc_para.find_line(line.id.as_deref(), i))
+                        .map(|line| &line.line);
+                    match &line.line {
+                        Line::Text(text) => units.push(TranslationUnit {
+                            key: line_key(file, p, i),
+                            source: text.to_string(),
+                            target: match loc_line {
+                                Some(Line::Text(text)) => Some(text.to_string()),
+                                _ => None,
+                            },
+                        }),
+                        Line::Switch { switches } => {
+                            for (j, switch) in switches.iter().enumerate() {
+                                units.push(TranslationUnit {
+                                    key: format!("{}/{}", line_key(file, p, i), j),
+                                    source: switch.clone(),
+                                    target: match loc_line {
+                                        Some(Line::Switch { switches }) => switches.get(j).cloned(),
+                                        _ => None,
+                                    },
+                                })
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if let Some(base_res) = self.base_res() {
+            let res = self.res.get(loc);
+            for (key, value) in sorted(base_res) {
+                if let RawValue::Str(source) = value {
+                    units.push(TranslationUnit {
+                        key: format!("res/{}", key),
+                        source: source.clone(),
+                        target: res
+                            .and_then(|res| res.get(key))
+                            .map(|value| value.get_str().into_owned()),
+                    })
+                }
+            }
+        }
+        units
+    }
+
+    /// Generates the paragraphs and resources from the translations,
+    /// which are indexed by the keys of [`TranslationUnit`].
+    ///
+    /// A switch line is translated only if all items are translated.
+    pub fn apply_translation(&self, translations: &HashMap<String, String>) -> Result<Translation> {
+        let get = |key: &str| {
+            translations
+                .get(key)
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+        };
+        let mut translation = Translation::default();
+        for (file, paras) in sorted(self.base_paras().unwrap_or(&HashMap::new())) {
+            let mut loc_paras = vec![];
+            for p in paras {
+                let mut texts = p
+                    .texts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, line)| {
+                        let key = line_key(file, p, i);
+                        match &line.line {
+                            Line::Text(_) => get(&key)
+                                .map(|text| {
+                                    Text::try_from(text.as_str()).map_err(|e| {
+                                        anyhow!("Invalid translation `{}`\n{}", key, e)
+                                    })?;
+                                    Ok(match &line.id {
+                                        Some(id) => TranslatedLine::IdText {
+                                            id: id.clone(),
+                                            text,
+                                        },
+                                        None => TranslatedLine::Text(text),
+                                    })
+                                })
+                                .transpose(),
+                            Line::Switch { switches } => Ok((0..switches.len())
+                                .map(|j| get(&format!("{}/{}", key, j)))
+                                .collect::<Option<Vec<_>>>()
+                                .map(|switches| TranslatedLine::Switch {
+                                    id: line.id.clone(),
+                                    switches,
+                                })),
+                            _ => Ok(None),
+                        }
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                while let Some(None) = texts.last() {
+                    texts.pop();
+                }
+                let title = get(&format!("title/{}/{}", file, p.tag));
+                if title.is_some() || !texts.is_empty() {
+                    loc_paras.push(TranslatedParagraph {
+                        tag: p.tag.clone(),
+                        title,
+                        texts,
+                    });
+                }
+            }
+            if !loc_paras.is_empty() {
+                translation.paras.insert(file.clone(), loc_paras);
+            }
+        }
+        if let Some(base_res) = self.base_res() {
+            for key in base_res.keys() {
+                if let Some(value) = get(&format!("res/{}", key)) {
+                    translation.res.insert(key.clone(), value);
+                }
+            }
+        }
+        Ok(translation)
+    }
+}
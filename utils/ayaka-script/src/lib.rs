@@ -34,6 +34,16 @@ pub enum Expr {
     Binary(Box<Expr>, BinaryOp, Box<Expr>),
     /// A call to a function.
     Call(String, String, Vec<Expr>),
+    /// A list literal.
+    List(Vec<Expr>),
+    /// A map literal.
+    Map(Vec<(String, Expr)>),
+    /// An indexing of a list, a map or a string.
+    Index(Box<Expr>, Box<Expr>),
+    /// An iteration over a value.
+    ///
+    /// The value of the iteration is a list of the values of the body.
+    For(Ref, Box<Expr>, Vec<Expr>),
 }
 
 /// Unary operations.
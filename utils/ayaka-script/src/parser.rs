@@ -10,6 +10,15 @@ lalrpop_mod!(
 
 pub use grammer::{ConstParser, ExprParser, ProgramParser, RefParser};
 
+// The variables and their items are assignable.
+fn is_place(e: &Expr) -> bool {
+    match e {
+        Expr::Ref(_) => true,
+        Expr::Index(e, _) => is_place(e),
+        _ => false,
+    }
+}
+
 impl std::str::FromStr for Program {
     type Err = anyhow::Error;
 
@@ -88,6 +97,69 @@ mod test {
         );
     }
 
+    #[test]
+    fn collection() {
+        let num = |i| Expr::Const(RawValue::Num(i));
+        assert_eq!(
+            ExprParser::new().parse("[1, a]").unwrap(),
+            Expr::List(vec![num(1), var("a")])
+        );
+        assert_eq!(
+            ExprParser::new().parse("{a: 1, \"b c\": []}").unwrap(),
+            Expr::Map(vec![
+                ("a".into(), num(1)),
+                ("b c".into(), Expr::List(vec![]))
+            ])
+        );
+        assert_eq!(
+            ExprParser::new().parse("-a[0][b]").unwrap(),
+            Expr::Unary(
+                UnaryOp::Negative,
+                Box::new(Expr::Index(
+                    Box::new(Expr::Index(Box::new(var("a")), Box::new(num(0)))),
+                    Box::new(var("b"))
+                ))
+            )
+        );
+        assert_eq!(
+            ExprParser::new().parse("$a[\"b\"] += 1").unwrap(),
+            Expr::Binary(
+                Box::new(Expr::Index(
+                    Box::new(Expr::Ref(Ref::Ctx("a".into()))),
+                    Box::new(Expr::Const(RawValue::Str("b".into())))
+                )),
+                BinaryOp::Inplace(ValBinaryOp::Add),
+                Box::new(num(1))
+            )
+        );
+        assert_eq!(
+            ExprParser::new()
+                .parse("for i in $a { b += i; i }")
+                .unwrap(),
+            Expr::For(
+                Ref::Var("i".into()),
+                Box::new(Expr::Ref(Ref::Ctx("a".into()))),
+                vec![
+                    Expr::Binary(
+                        Box::new(var("b")),
+                        BinaryOp::Inplace(ValBinaryOp::Add),
+                        Box::new(var("i"))
+                    ),
+                    var("i")
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn assign() {
+        assert!(ExprParser::new().parse("a = b = 1").is_ok());
+        assert!(ExprParser::new().parse("$a[0][b] -= 1").is_ok());
+        assert!(ExprParser::new().parse("1 + 2 = 3").is_err());
+        assert!(ExprParser::new().parse("f(a) = 1").is_err());
+        assert!(ExprParser::new().parse("[a][0] = 1").is_err());
+    }
+
     #[test]
     fn r#const() {
         assert_eq!(ConstParser::new().parse("~").unwrap(), RawValue::Unit);
@@ -106,6 +178,11 @@ mod test {
             RawValue::Num(114514.into())
         );
 
+        assert_eq!(
+            ConstParser::new().parse("3.25").unwrap(),
+            RawValue::Float(3.25)
+        );
+
         assert_eq!(
             ConstParser::new().parse("\"Hello world!\"").unwrap(),
             RawValue::Str("Hello world!".into())